@@ -28,7 +28,6 @@ impl Request for EntriesReq {
 
 	const PATH: &'static str = "/api/cinema/entries";
 	const METHOD: Method = Method::GET;
-	const HEADERS: &'static [&'static str] = &["auth-token"];
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
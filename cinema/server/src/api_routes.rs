@@ -4,9 +4,10 @@ use crate::data;
 use crate::db::{self, CinemaDb};
 use crate::error::{Error, Result};
 
+use crate::{CheckedUser, Cinema};
 use chuchi_postgres::connection::ConnectionOwned;
 use chuchi_postgres::Database;
-use core_lib::users::{CheckedUser, Users};
+use core_lib::users::{AppKey, Users};
 
 use chuchi::api::stream::{StreamError, StreamServer, Streamer};
 use chuchi::{api, api_stream, Chuchi, Res};
@@ -39,7 +40,13 @@ pub async fn progress(
 	cinema: &CinemaDb,
 	users: &Users,
 ) -> Result<()> {
-	let (_, user) = users.sess_user_from_token(&req.token).await?;
+	let (sess, user) = users.sess_user_from_token(&req.token).await?;
+	if !user.rights.has_app(Cinema::KEY) {
+		return Err(Error::MissingRights);
+	}
+
+	// root watching as the user should not change their progress
+	let read_only = sess.is_impersonated();
 
 	loop {
 		let msg = match stream.recv().await {
@@ -50,6 +57,10 @@ pub async fn progress(
 			}
 		};
 
+		if read_only {
+			continue;
+		}
+
 		let mut conn = database
 			.get()
 			.await
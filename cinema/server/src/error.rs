@@ -15,7 +15,13 @@ pub enum Error {
 	InvalidAuthToken,
 	MissingDataToken,
 	InvalidDataToken,
+	InvalidApiToken,
 	InvalidUser,
+	MissingRights,
+	/// the password needs to be entered again via the core
+	ReauthRequired,
+	/// the action is not allowed while root impersonates the user
+	Impersonated,
 	NotFound,
 	Internal(String),
 	Request(String),
@@ -27,6 +33,10 @@ impl error::ApiError for Error {
 			ApiError::HeadersMissing(_) | ApiError::Deserialize(_) => {
 				Self::Request(e.to_string())
 			}
+			ApiError::ExtractionError(e) => e
+				.downcast::<users::Error>()
+				.map(|e| (*e).into())
+				.unwrap_or_else(|e| Self::Internal(e.to_string())),
 			e => Self::Internal(e.to_string()),
 		}
 	}
@@ -38,7 +48,11 @@ impl error::ApiError for Error {
 			| Self::InvalidAuthToken
 			| Self::MissingDataToken
 			| Self::InvalidDataToken
-			| Self::InvalidUser => StatusCode::FORBIDDEN,
+			| Self::InvalidApiToken
+			| Self::InvalidUser
+			| Self::MissingRights
+			| Self::ReauthRequired
+			| Self::Impersonated => StatusCode::FORBIDDEN,
 			Self::NotFound => StatusCode::NOT_FOUND,
 			Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
 			Self::Request(_) => StatusCode::BAD_REQUEST,
@@ -69,7 +83,11 @@ impl From<users::Error> for Error {
 			MissingDataToken => Self::MissingDataToken,
 			InvalidAuthToken => Self::InvalidAuthToken,
 			InvalidDataToken => Self::InvalidDataToken,
+			InvalidApiToken => Self::InvalidApiToken,
 			InvalidUser => Self::InvalidUser,
+			MissingRights => Self::MissingRights,
+			ReauthRequired => Self::ReauthRequired,
+			Impersonated => Self::Impersonated,
 			Db(e) => Self::Internal(e.to_string()),
 		}
 	}
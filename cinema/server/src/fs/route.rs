@@ -11,7 +11,8 @@ use chuchi::extractor::PathStr;
 use chuchi::fs::{serve_file, Caching, IntoPathBuf};
 use chuchi::{get, Chuchi, Error, Request, Response, Result};
 
-use core_lib::users::{CheckedUser, DataToken, RightsAny};
+use crate::CheckedUser;
+use core_lib::users::DataToken;
 
 use tokio::fs;
 
@@ -28,7 +29,7 @@ fn get_caching() -> Caching {
 async fn get_movies(
 	req: &mut Request,
 	path: &PathStr,
-	_sess: CheckedUser<RightsAny, DataToken>,
+	_sess: CheckedUser<DataToken>,
 	cfg: &CinemaConf,
 ) -> Result<Response> {
 	let req_path = into_path_buf(&path)?;
@@ -80,7 +81,7 @@ async fn get_full_posters_movies(
 async fn get_series(
 	req: &mut Request,
 	path: &PathStr,
-	_sess: CheckedUser<RightsAny, DataToken>,
+	_sess: CheckedUser<DataToken>,
 	cfg: &CinemaConf,
 ) -> Result<Response> {
 	let req_path = into_path_buf(&path)?;
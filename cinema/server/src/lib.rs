@@ -19,8 +19,8 @@ use chuchi_postgres::database::Config as DbConfig;
 use db::CinemaDb;
 
 use core_lib::config::DbConf;
-use core_lib::users::Users;
-use core_lib::{init_fn, Core};
+use core_lib::users::{self, AppKey, NormalToken, RightsApp, Users};
+use core_lib::{init_fn, Core, Manifest};
 
 use chuchi::Resource;
 use serde::{Deserialize, Serialize};
@@ -54,7 +54,29 @@ struct CinemaConf {
 	allow_deletes: bool,
 }
 
-init_fn!(init, "cinema", assets::JS, assets::CSS);
+/// users need this app in their rights to access any route
+pub(crate) struct Cinema;
+
+impl AppKey for Cinema {
+	const KEY: &'static str = "cinema";
+}
+
+pub(crate) type CheckedUser<AuthToken = NormalToken> =
+	users::CheckedUser<RightsApp<Cinema>, AuthToken>;
+
+init_fn!(
+	init,
+	Cinema::KEY,
+	assets::JS,
+	assets::CSS,
+	Manifest {
+		version: env!("CARGO_PKG_VERSION"),
+		display_name: "Cinema",
+		icon: "icon.png",
+		description: "Filme und Serie luege",
+		priority: 10,
+	}
+);
 async fn init(core: Core) {
 	tracing_subscriber::fmt()
 		.with_env_filter("cinema_server=info,chuchi=info,warn")
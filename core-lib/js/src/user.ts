@@ -20,15 +20,31 @@ export class Session {
 	timeout!: number;
 	createdOn!: DateTime;
 	userId!: string;
+	// until when the password counts as recently entered
+	freshUntil!: DateTime;
+	// the root user which sees what this user sees
+	impersonatedBy!: string | null;
 
 	constructor(d: any) {
 		Object.assign(this, d);
 		this.createdOn = new DateTime(d.createdOn);
+		this.freshUntil = new DateTime(d.freshUntil);
+		this.impersonatedBy = d.impersonatedBy ?? null;
 	}
 
 	isValid() {
 		return this.timeout * 1000 > Date.now();
 	}
+
+	// if endpoints which require a fresh login can be used
+	isFresh() {
+		return this.freshUntil.time > Date.now();
+	}
+
+	// apps should show this and not allow destructive actions
+	isImpersonated() {
+		return this.impersonatedBy !== null;
+	}
 }
 
 export class SessionStore {
@@ -131,6 +131,7 @@ impl<T> c_slice<T> {
 	}
 }
 
+/// Apps export this as `c_abi_version` since 0.6, see `init_fn!`
 #[repr(C)]
 pub struct c_core_version {
 	pub major: u16,
@@ -179,6 +180,10 @@ impl Default for c_terminated {
 	}
 }
 
+/// Get's called instead of `c_terminated::terminated` with the same ctx if
+/// the app panicked, msg contains the message and the location of the panic
+pub type c_crashed_fn = extern "C" fn(ctx: *mut u8, msg: c_string);
+
 /// needs to call free else you will leak memory
 #[repr(C)]
 pub struct c_error {
@@ -340,14 +345,105 @@ pub struct c_session {
 	pub timeout: c_systemtime,
 	pub created_on: c_datetime,
 	pub user_id: c_uid,
+	/// since 0.2
+	pub fresh_until: c_datetime,
+	/// since 0.4, only set if impersonated is true
+	pub impersonated_by: c_uid,
+	pub impersonated: bool,
+}
+
+/// The layout of `c_session` before 0.2
+///
+/// Apps built before 0.2 expect this layout, it can never change.
+#[repr(C)]
+pub struct c_session_v1 {
+	pub token: c_token,
+	pub data_token: c_token,
+	pub timeout: c_systemtime,
+	pub created_on: c_datetime,
+	pub user_id: c_uid,
+}
+
+impl c_session_v1 {
+	pub fn from_session(s: c_session) -> Self {
+		Self {
+			token: s.token,
+			data_token: s.data_token,
+			timeout: s.timeout,
+			created_on: s.created_on,
+			user_id: s.user_id,
+		}
+	}
+
+	pub fn into_session(self) -> c_session {
+		c_session {
+			token: self.token,
+			data_token: self.data_token,
+			timeout: self.timeout,
+			created_on: self.created_on,
+			user_id: self.user_id,
+			// core does not tell when the password was entered, so the
+			// session never counts as fresh
+			fresh_until: c_datetime { secs: 0, nanos: 0 },
+			impersonated_by: c_uid { bytes: [0; 10] },
+			impersonated: false,
+		}
+	}
+}
+
+/// The layout of `c_session` in 0.2 and 0.3, it can never change.
+#[repr(C)]
+pub struct c_session_v2 {
+	pub token: c_token,
+	pub data_token: c_token,
+	pub timeout: c_systemtime,
+	pub created_on: c_datetime,
+	pub user_id: c_uid,
+	pub fresh_until: c_datetime,
+}
+
+impl c_session_v2 {
+	pub fn from_session(s: c_session) -> Self {
+		Self {
+			token: s.token,
+			data_token: s.data_token,
+			timeout: s.timeout,
+			created_on: s.created_on,
+			user_id: s.user_id,
+			fresh_until: s.fresh_until,
+		}
+	}
+
+	pub fn into_session(self) -> c_session {
+		c_session {
+			token: self.token,
+			data_token: self.data_token,
+			timeout: self.timeout,
+			created_on: self.created_on,
+			user_id: self.user_id,
+			fresh_until: self.fresh_until,
+			impersonated_by: c_uid { bytes: [0; 10] },
+			impersonated: false,
+		}
+	}
+}
+
+pub type c_session_fn<S> = extern "C" fn(*const u8, c_token, *mut S) -> bool;
+
+extern "C" fn no_session<S>(
+	_ctx: *const u8,
+	_token: c_token,
+	_session: *mut S,
+) -> bool {
+	false
 }
 
 #[repr(C)]
 pub struct c_sessions {
 	pub ctx: *const u8,
-	pub by_token: extern "C" fn(*const u8, c_token, *mut c_session) -> bool,
-	pub by_data_token:
-		extern "C" fn(*const u8, c_token, *mut c_session) -> bool,
+	/// writes a `c_session_v1`
+	pub by_token: c_session_fn<c_session_v1>,
+	pub by_data_token: c_session_fn<c_session_v1>,
 	pub free: extern "C" fn(*const u8),
 }
 
@@ -359,33 +455,233 @@ impl c_sessions {
 
 impl Default for c_sessions {
 	fn default() -> Self {
-		extern "C" fn by_token(
+		extern "C" fn free(_ctx: *const u8) {}
+
+		Self {
+			ctx: ptr::null(),
+			by_token: no_session,
+			by_data_token: no_session,
+			free,
+		}
+	}
+}
+
+/// The lookups which write a `c_session_v2`, they get called with the ctx
+/// of `c_sessions`
+#[repr(C)]
+pub struct c_sessions_v2 {
+	pub by_token: c_session_fn<c_session_v2>,
+	pub by_data_token: c_session_fn<c_session_v2>,
+}
+
+impl c_sessions_v2 {
+	pub fn take(&mut self) -> Self {
+		mem::take(self)
+	}
+}
+
+impl Default for c_sessions_v2 {
+	fn default() -> Self {
+		Self {
+			by_token: no_session,
+			by_data_token: no_session,
+		}
+	}
+}
+
+/// The lookups which write a `c_session`, they get called with the ctx of
+/// `c_sessions`
+#[repr(C)]
+pub struct c_sessions_v3 {
+	pub by_token: c_session_fn<c_session>,
+	pub by_data_token: c_session_fn<c_session>,
+}
+
+impl c_sessions_v3 {
+	pub fn take(&mut self) -> Self {
+		mem::take(self)
+	}
+}
+
+impl Default for c_sessions_v3 {
+	fn default() -> Self {
+		Self {
+			by_token: no_session,
+			by_data_token: no_session,
+		}
+	}
+}
+
+/// Get's called once a preference operation finished
+#[repr(C)]
+pub struct c_preference_done {
+	pub ctx: *mut u8,
+	/// get's called exactly once, possibly from another thread
+	///
+	/// found tells if the preference existed, value contains the json
+	/// encoded value and is only set by get
+	pub done: extern "C" fn(
+		ctx: *mut u8,
+		error: c_error,
+		found: bool,
+		value: c_string,
+	),
+}
+
+/// The strs only need to be valid during the call
+///
+/// Since 0.8 core ignores `app` and uses the name the app was loaded with.
+#[repr(C)]
+pub struct c_preferences {
+	pub ctx: *const u8,
+	pub get: extern "C" fn(
+		*const u8,
+		app: c_str,
+		user_id: c_uid,
+		key: c_str,
+		done: c_preference_done,
+	),
+	/// value needs to be json
+	pub set: extern "C" fn(
+		*const u8,
+		app: c_str,
+		user_id: c_uid,
+		key: c_str,
+		value: c_str,
+		done: c_preference_done,
+	),
+	pub remove: extern "C" fn(
+		*const u8,
+		app: c_str,
+		user_id: c_uid,
+		key: c_str,
+		done: c_preference_done,
+	),
+	pub free: extern "C" fn(*const u8),
+}
+
+impl c_preferences {
+	pub fn take(&mut self) -> Self {
+		mem::take(self)
+	}
+}
+
+impl Default for c_preferences {
+	fn default() -> Self {
+		extern "C" fn get(
 			_ctx: *const u8,
-			_token: c_token,
-			_session: *mut c_session,
-		) -> bool {
-			false
+			_app: c_str,
+			_user_id: c_uid,
+			_key: c_str,
+			done: c_preference_done,
+		) {
+			preferences_unsupported(done)
 		}
-		extern "C" fn by_data_token(
+		extern "C" fn set(
 			_ctx: *const u8,
-			_token: c_token,
-			_session: *mut c_session,
-		) -> bool {
-			false
+			_app: c_str,
+			_user_id: c_uid,
+			_key: c_str,
+			_value: c_str,
+			done: c_preference_done,
+		) {
+			preferences_unsupported(done)
+		}
+		extern "C" fn remove(
+			_ctx: *const u8,
+			_app: c_str,
+			_user_id: c_uid,
+			_key: c_str,
+			done: c_preference_done,
+		) {
+			preferences_unsupported(done)
 		}
 		extern "C" fn free(_ctx: *const u8) {}
 
 		Self {
 			ctx: ptr::null(),
-			by_token,
-			by_data_token,
+			get,
+			set,
+			remove,
 			free,
 		}
 	}
 }
 
+/// The calls without the app, they get called with the ctx of
+/// `c_preferences`
+#[repr(C)]
+pub struct c_preferences_v2 {
+	pub get: extern "C" fn(
+		*const u8,
+		user_id: c_uid,
+		key: c_str,
+		done: c_preference_done,
+	),
+	/// value needs to be json
+	pub set: extern "C" fn(
+		*const u8,
+		user_id: c_uid,
+		key: c_str,
+		value: c_str,
+		done: c_preference_done,
+	),
+	pub remove: extern "C" fn(
+		*const u8,
+		user_id: c_uid,
+		key: c_str,
+		done: c_preference_done,
+	),
+}
+
+impl c_preferences_v2 {
+	pub fn take(&mut self) -> Self {
+		mem::take(self)
+	}
+}
+
+impl Default for c_preferences_v2 {
+	fn default() -> Self {
+		extern "C" fn get(
+			_ctx: *const u8,
+			_user_id: c_uid,
+			_key: c_str,
+			done: c_preference_done,
+		) {
+			preferences_unsupported(done)
+		}
+		extern "C" fn set(
+			_ctx: *const u8,
+			_user_id: c_uid,
+			_key: c_str,
+			_value: c_str,
+			done: c_preference_done,
+		) {
+			preferences_unsupported(done)
+		}
+		extern "C" fn remove(
+			_ctx: *const u8,
+			_user_id: c_uid,
+			_key: c_str,
+			done: c_preference_done,
+		) {
+			preferences_unsupported(done)
+		}
+
+		Self { get, set, remove }
+	}
+}
+
+fn preferences_unsupported(done: c_preference_done) {
+	let e = c_error::new(C_ERROR_OTHER, "preferences are not supported".into());
+	(done.done)(done.ctx, e, false, c_string::empty());
+}
+
 /// The server receives a pointer to this struct in the init call
 /// Don't hold on to core beyond the init call
+///
+/// New fields only get appended so apps built against an older version
+/// still work.
 #[repr(C)]
 pub struct c_core {
 	/// config is the original toml str (this needs to be parsed for your
@@ -396,6 +692,16 @@ pub struct c_core {
 	/// Gets provided by the client and should be called when it is safe
 	/// to destroy all references to the server.
 	pub terminated: c_terminated,
+	/// since 0.2
+	pub sessions_v2: c_sessions_v2,
+	/// since 0.3
+	pub preferences: c_preferences,
+	/// since 0.4
+	pub sessions_v3: c_sessions_v3,
+	/// since 0.7
+	pub crashed: c_crashed_fn,
+	/// since 0.8
+	pub preferences_v2: c_preferences_v2,
 }
 
 /// All this properties should be set by the app (the server)
@@ -421,7 +727,56 @@ pub struct c_app {
 	/// The listener accept fn get's called once a new connection should be
 	/// accepted
 	pub listener: c_listener,
+	/// since 0.5, only write this if core is at least that version
+	///
+	/// core zeroes the struct before calling c_init so this stays false
+	/// for apps built against an older version
+	pub has_manifest: bool,
+	/// since 0.5, only read if has_manifest is true
+	pub manifest: c_manifest,
+}
+
+/// Describes the app to the user, all strings need to be static and might
+/// be empty
+#[repr(C)]
+pub struct c_manifest {
+	pub version: c_str,
+	pub display_name: c_str,
+	/// path of the icon relative to the assets of the app
+	pub icon: c_str,
+	pub description: c_str,
+	/// apps with a lower priority get listed first
+	pub priority: i32,
 }
 
 #[allow(non_camel_case_types)]
 pub type c_init_fn = extern "C" fn(*mut c_core, *mut c_app);
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use std::mem::{offset_of, size_of};
+
+	// apps built before 0.2 only know these fields, they can never move
+	#[test]
+	fn baseline_layout() {
+		let p = size_of::<usize>();
+
+		assert_eq!(size_of::<c_sessions>(), 4 * p);
+		assert_eq!(size_of::<c_terminated>(), 2 * p);
+		assert_eq!(offset_of!(c_core, config), 0);
+		assert_eq!(offset_of!(c_core, version), 2 * p);
+		assert_eq!(offset_of!(c_core, sessions), 3 * p);
+		assert_eq!(offset_of!(c_core, terminated), 7 * p);
+		assert_eq!(offset_of!(c_core, sessions_v2), 9 * p);
+
+		assert_eq!(size_of::<c_listener>(), 3 * p);
+		assert_eq!(offset_of!(c_app, name), 0);
+		assert_eq!(offset_of!(c_app, js_entry), 2 * p);
+		assert_eq!(offset_of!(c_app, css_entry), 4 * p);
+		assert_eq!(offset_of!(c_app, terminator), 6 * p);
+		assert_eq!(offset_of!(c_app, listener), 8 * p);
+		assert_eq!(offset_of!(c_app, has_manifest), 11 * p);
+	}
+}
@@ -3,6 +3,7 @@ pub mod ffi;
 pub mod chuchi;
 pub mod client;
 pub mod macros;
+pub mod preferences;
 pub mod progress_channel;
 pub mod server;
 pub mod stream;
@@ -12,6 +13,7 @@ pub mod users;
 
 mod util;
 
+use preferences::Preferences;
 use users::Sessions;
 
 use std::{fmt, io};
@@ -24,6 +26,8 @@ pub struct Core {
 	pub on_terminate: server::OnTerminate,
 	pub listener: stream::Listener,
 	pub sessions: Sessions,
+	/// the preferences of this app
+	pub preferences: Preferences,
 }
 
 impl Core {
@@ -35,12 +39,138 @@ impl Core {
 	}
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct CoreVersion {
 	pub major: u16,
 	pub minor: u16,
 }
 
+impl CoreVersion {
+	/// the version of the abi this core-lib was built with
+	///
+	/// A new minor version only appends fields which get checked against
+	/// the version of the other side, a new major version changes the layout.
+	pub const CURRENT: Self = Self { major: 0, minor: 8 };
+
+	pub fn from_c(v: &ffi::c_core_version) -> Self {
+		Self {
+			major: v.major,
+			minor: v.minor,
+		}
+	}
+
+	pub fn to_c(self) -> ffi::c_core_version {
+		ffi::c_core_version {
+			major: self.major,
+			minor: self.minor,
+		}
+	}
+
+	/// returns true if an app built against this version can be loaded
+	/// by core with the other version
+	pub fn is_compatible(&self, core: &Self) -> bool {
+		self.major == core.major
+	}
+}
+
+impl fmt::Display for CoreVersion {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}.{}", self.major, self.minor)
+	}
+}
+
+/// the first version of core which set's `c_core::sessions_v2`
+#[doc(hidden)]
+pub const FRESH_VERSION: CoreVersion = CoreVersion { major: 0, minor: 2 };
+
+/// the first version of core which set's `c_core::preferences`
+#[doc(hidden)]
+pub const PREFERENCES_VERSION: CoreVersion = CoreVersion { major: 0, minor: 3 };
+
+/// the first version of core which set's `c_core::sessions_v3`
+#[doc(hidden)]
+pub const IMPERSONATION_VERSION: CoreVersion =
+	CoreVersion { major: 0, minor: 4 };
+
+/// the first version of core which reads `c_app::manifest`
+#[doc(hidden)]
+pub const MANIFEST_VERSION: CoreVersion = CoreVersion { major: 0, minor: 5 };
+
+/// the first version of core which provides `c_core::crashed`
+#[doc(hidden)]
+pub const CRASHED_VERSION: CoreVersion = CoreVersion { major: 0, minor: 7 };
+
+/// the first version of core which set's `c_core::preferences_v2`
+#[doc(hidden)]
+pub const PREFERENCES_V2_VERSION: CoreVersion =
+	CoreVersion { major: 0, minor: 8 };
+
+/// Information about the app which is shown to the user
+///
+/// ```
+/// use core_lib::Manifest;
+///
+/// const MANIFEST: Manifest = Manifest {
+///     version: env!("CARGO_PKG_VERSION"),
+///     display_name: "Some App",
+///     ..Manifest::new()
+/// };
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Manifest {
+	pub version: &'static str,
+	/// defaults to the name of the app if empty
+	pub display_name: &'static str,
+	/// path of the icon relative to the assets of the app, defaults to
+	/// `icon.png` if empty
+	pub icon: &'static str,
+	pub description: &'static str,
+	/// apps with a lower priority get listed first
+	pub priority: i32,
+}
+
+impl Manifest {
+	pub const fn new() -> Self {
+		Self {
+			version: "",
+			display_name: "",
+			icon: "",
+			description: "",
+			priority: 0,
+		}
+	}
+
+	/// ## Safety
+	/// all strings need to be static
+	pub unsafe fn from_c(manifest: &ffi::c_manifest) -> Self {
+		unsafe {
+			Self {
+				version: manifest.version.to_static_str(),
+				display_name: manifest.display_name.to_static_str(),
+				icon: manifest.icon.to_static_str(),
+				description: manifest.description.to_static_str(),
+				priority: manifest.priority,
+			}
+		}
+	}
+
+	pub fn into_c(self) -> ffi::c_manifest {
+		ffi::c_manifest {
+			version: ffi::c_str::from_static_str(self.version),
+			display_name: ffi::c_str::from_static_str(self.display_name),
+			icon: ffi::c_str::from_static_str(self.icon),
+			description: ffi::c_str::from_static_str(self.description),
+			priority: self.priority,
+		}
+	}
+}
+
+impl Default for Manifest {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Error {
 	pub kind: ErrorKind,
@@ -126,11 +256,20 @@ impl ErrorKind {
 }
 
 /// ```
-/// use core_lib::{init_fn, Core};
+/// use core_lib::{init_fn, Core, Manifest};
 ///
-/// init_fn!(init, "some_app");
+/// init_fn!(
+///     init,
+///     "some_app",
+///     "main.js",
+///     "style.css",
+///     Manifest {
+///         display_name: "Some App",
+///         ..Manifest::new()
+///     }
+/// );
 /// async fn init(core: Core) {
-/// 	todo!()
+///     todo!()
 /// }
 /// ```
 #[macro_export]
@@ -142,16 +281,48 @@ macro_rules! init_fn {
 		$crate::init_fn!($init, $name, $js_entry, "");
 	};
 	($init:ident, $name:expr, $js_entry:expr, $css_entry:expr) => {
+		$crate::init_fn!(
+			$init,
+			$name,
+			$js_entry,
+			$css_entry,
+			$crate::Manifest::new()
+		);
+	};
+	(
+		$init:ident,
+		$name:expr,
+		$js_entry:expr,
+		$css_entry:expr,
+		$manifest:expr
+	) => {
+		/// get's read by core before calling c_init
+		#[no_mangle]
+		#[allow(non_upper_case_globals)]
+		pub static c_abi_version: $crate::ffi::c_core_version =
+			$crate::ffi::c_core_version {
+				major: $crate::CoreVersion::CURRENT.major,
+				minor: $crate::CoreVersion::CURRENT.minor,
+			};
+
 		#[no_mangle]
 		pub extern "C" fn c_init(
 			core: *mut $crate::ffi::c_core,
 			app: *mut $crate::ffi::c_app,
 		) {
+			use std::ptr::addr_of_mut;
 			use $crate::ffi;
 
 			let core = unsafe { &mut *core };
-			let terminated =
-				$crate::server::Terminated::new(core.terminated.take());
+			let version = $crate::CoreVersion::from_c(&core.version);
+
+			// older versions of core don't have this field
+			let crashed =
+				(version >= $crate::CRASHED_VERSION).then(|| core.crashed);
+			let terminated = $crate::server::Terminated::new(
+				core.terminated.take(),
+				crashed,
+			);
 
 			// init terminator
 			let (terminator, terminate_rx) = $crate::client::Terminator::new();
@@ -159,48 +330,98 @@ macro_rules! init_fn {
 			// init listener
 			let (listener, c_listener) = $crate::stream::Listener::new();
 
+			// older versions of core allocate a smaller c_app so every
+			// field needs to be written on it's own
 			unsafe {
-				app.write(ffi::c_app {
-					name: ffi::c_str::from_str($name),
-					js_entry: ffi::c_str::from_str($js_entry),
-					css_entry: ffi::c_str::from_str($css_entry),
-					terminator: terminator.into_c(),
-					listener: c_listener.into_c(),
-				});
+				addr_of_mut!((*app).name).write(ffi::c_str::from_str($name));
+				addr_of_mut!((*app).js_entry)
+					.write(ffi::c_str::from_str($js_entry));
+				addr_of_mut!((*app).css_entry)
+					.write(ffi::c_str::from_str($css_entry));
+				addr_of_mut!((*app).terminator).write(terminator.into_c());
+				addr_of_mut!((*app).listener).write(c_listener.into_c());
+
+				if version >= $crate::MANIFEST_VERSION {
+					let manifest: $crate::Manifest = $manifest;
+					addr_of_mut!((*app).has_manifest).write(true);
+					addr_of_mut!((*app).manifest).write(manifest.into_c());
+				}
 			}
 
 			let config = unsafe { core.config.to_str().to_string() };
-			let version = $crate::CoreVersion {
-				major: core.version.major,
-				minor: core.version.minor,
+			// older versions of core don't have these fields
+			let lookup = if version >= $crate::IMPERSONATION_VERSION {
+				$crate::users::SessionsLookup::V3(core.sessions_v3.take())
+			} else if version >= $crate::FRESH_VERSION {
+				$crate::users::SessionsLookup::V2(core.sessions_v2.take())
+			} else {
+				$crate::users::SessionsLookup::V1
+			};
+			let sessions =
+				$crate::users::Sessions::new(core.sessions.take(), lookup);
+			// older versions of core don't have this field
+			let preferences = if version >= $crate::PREFERENCES_VERSION {
+				core.preferences.take()
+			} else {
+				Default::default()
 			};
-			let sessions = $crate::users::Sessions::new(core.sessions.take());
+			let preferences_v2 = if version >= $crate::PREFERENCES_V2_VERSION {
+				Some(core.preferences_v2.take())
+			} else {
+				None
+			};
+			let preferences = $crate::preferences::Preferences::new(
+				preferences,
+				preferences_v2,
+				$name,
+			);
 
 			std::thread::Builder::new()
 				.name($name.into())
 				.spawn(move || {
-					let _ = std::panic::catch_unwind(
-						std::panic::AssertUnwindSafe(move || {
-							let core = $crate::Core {
-								config,
-								version,
-								on_terminate: terminate_rx,
-								listener,
-								sessions,
-							};
-
-							let rt = $crate::runtime::Runtime::new().unwrap();
-							rt.block_on(async move {
-								let _: () = $init(core).await;
-							});
-						}),
-					);
+					let res = $crate::server::catch_panic(move || {
+						let core = $crate::Core {
+							config,
+							version,
+							on_terminate: terminate_rx,
+							listener,
+							sessions,
+							preferences,
+						};
+
+						let rt = $crate::runtime::Runtime::new().unwrap();
+						rt.block_on(async move {
+							let _: () = $init(core).await;
+						});
+					});
 
 					// the runtime as stopped we should be able to call terminated
 					// now
-					terminated.terminated();
+					match res {
+						Ok(()) => terminated.terminated(),
+						Err(msg) => terminated.crashed(msg),
+					}
 				})
 				.unwrap();
 		}
 	};
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn core_version() {
+		let v = |major, minor| CoreVersion { major, minor };
+
+		assert!(v(0, 5).is_compatible(&v(0, 5)));
+		// minor versions only append fields
+		assert!(v(0, 1).is_compatible(&v(0, 5)));
+		assert!(v(0, 6).is_compatible(&v(0, 5)));
+		assert!(!v(1, 0).is_compatible(&v(0, 5)));
+		assert!(!v(0, 5).is_compatible(&v(1, 5)));
+		assert!(v(0, 4) < v(0, 5));
+		assert_eq!(v(0, 5).to_string(), "0.5");
+	}
+}
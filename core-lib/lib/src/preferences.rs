@@ -0,0 +1,160 @@
+//! Small per-user settings stored by core
+//!
+//! Every app has it's own namespace, the values are stored as json.
+
+use crate::{ffi, Error, ErrorKind};
+
+use chuchi_postgres::UniqueId;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tokio::sync::oneshot;
+
+type Response = Result<Option<String>, Error>;
+
+pub struct Preferences {
+	inner: ffi::c_preferences,
+	/// `None` if core is older than 0.8, it then needs to be told the app
+	v2: Option<ffi::c_preferences_v2>,
+	app: &'static str,
+}
+
+impl Preferences {
+	pub fn new(
+		inner: ffi::c_preferences,
+		v2: Option<ffi::c_preferences_v2>,
+		app: &'static str,
+	) -> Self {
+		Self { inner, v2, app }
+	}
+
+	/// returns `None` if the preference was never set
+	pub async fn get<T>(
+		&self,
+		user_id: &UniqueId,
+		key: &str,
+	) -> Result<Option<T>, Error>
+	where
+		T: DeserializeOwned,
+	{
+		let (done, rx) = done();
+		let user_id = ffi::c_uid::from_uid(*user_id);
+		let key = ffi::c_str::from_str(key);
+		match &self.v2 {
+			Some(v2) => (v2.get)(self.inner.ctx, user_id, key, done),
+			None => (self.inner.get)(
+				self.inner.ctx,
+				ffi::c_str::from_str(self.app),
+				user_id,
+				key,
+				done,
+			),
+		}
+
+		match received(rx).await? {
+			Some(v) => serde_json::from_str(&v)
+				.map(Some)
+				.map_err(|e| Error::new(ErrorKind::Other, e.to_string())),
+			None => Ok(None),
+		}
+	}
+
+	pub async fn set<T>(
+		&self,
+		user_id: &UniqueId,
+		key: &str,
+		value: &T,
+	) -> Result<(), Error>
+	where
+		T: Serialize + ?Sized,
+	{
+		let value = serde_json::to_string(value)
+			.map_err(|e| Error::new(ErrorKind::Other, e.to_string()))?;
+
+		let (done, rx) = done();
+		let user_id = ffi::c_uid::from_uid(*user_id);
+		let key = ffi::c_str::from_str(key);
+		let value = ffi::c_str::from_str(&value);
+		match &self.v2 {
+			Some(v2) => (v2.set)(self.inner.ctx, user_id, key, value, done),
+			None => (self.inner.set)(
+				self.inner.ctx,
+				ffi::c_str::from_str(self.app),
+				user_id,
+				key,
+				value,
+				done,
+			),
+		}
+
+		received(rx).await.map(|_| ())
+	}
+
+	/// does nothing if the preference was never set
+	pub async fn remove(
+		&self,
+		user_id: &UniqueId,
+		key: &str,
+	) -> Result<(), Error> {
+		let (done, rx) = done();
+		let user_id = ffi::c_uid::from_uid(*user_id);
+		let key = ffi::c_str::from_str(key);
+		match &self.v2 {
+			Some(v2) => (v2.remove)(self.inner.ctx, user_id, key, done),
+			None => (self.inner.remove)(
+				self.inner.ctx,
+				ffi::c_str::from_str(self.app),
+				user_id,
+				key,
+				done,
+			),
+		}
+
+		received(rx).await.map(|_| ())
+	}
+}
+
+impl Drop for Preferences {
+	fn drop(&mut self) {
+		(self.inner.free)(self.inner.ctx);
+	}
+}
+
+unsafe impl Send for Preferences {}
+unsafe impl Sync for Preferences {}
+
+fn done() -> (ffi::c_preference_done, oneshot::Receiver<Response>) {
+	extern "C" fn done_fn(
+		ctx: *mut u8,
+		error: ffi::c_error,
+		found: bool,
+		value: ffi::c_string,
+	) {
+		let tx =
+			unsafe { Box::from_raw(ctx as *mut oneshot::Sender<Response>) };
+		let value = unsafe { value.into_string() };
+
+		let res = if error.is_ok() {
+			error.free();
+			Ok(Some(value).filter(|_| found))
+		} else {
+			Err(Error::from_c(error))
+		};
+
+		// the receiver might no longer exist
+		let _ = tx.send(res);
+	}
+
+	let (tx, rx) = oneshot::channel();
+	let done = ffi::c_preference_done {
+		ctx: Box::into_raw(Box::new(tx)) as *mut u8,
+		done: done_fn,
+	};
+
+	(done, rx)
+}
+
+async fn received(rx: oneshot::Receiver<Response>) -> Response {
+	rx.await
+		.map_err(|_| Error::new(ErrorKind::Closed, "core did not respond"))?
+}
@@ -1,5 +1,9 @@
 use crate::{ffi, progress_channel as prog};
 
+use std::cell::RefCell;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Once;
+
 pub use tokio::runtime;
 
 #[derive(Debug, Clone)]
@@ -21,17 +25,77 @@ impl OnTerminate {
 #[doc(hidden)]
 pub struct Terminated {
 	inner: ffi::c_terminated,
+	/// `None` if core is older than 0.7
+	crashed: Option<ffi::c_crashed_fn>,
 }
 
 impl Terminated {
-	pub fn new(inner: ffi::c_terminated) -> Self {
-		Self { inner }
+	pub fn new(
+		inner: ffi::c_terminated,
+		crashed: Option<ffi::c_crashed_fn>,
+	) -> Self {
+		Self { inner, crashed }
 	}
 
 	pub fn terminated(self) {
 		(self.inner.terminated)(self.inner.ctx)
 	}
+
+	/// older versions of core only see that the app terminated
+	pub fn crashed(self, msg: String) {
+		match self.crashed {
+			Some(crashed) => {
+				crashed(self.inner.ctx, ffi::c_string::from_string(msg))
+			}
+			None => self.terminated(),
+		}
+	}
+}
+
+thread_local! {
+	static PANIC_MSG: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// returns the message and location if f panicked
+///
+/// The panic hook of this library get's extended to store the message, the
+/// previous hook still get's called.
+#[doc(hidden)]
+pub fn catch_panic(f: impl FnOnce()) -> Result<(), String> {
+	static HOOK: Once = Once::new();
+	HOOK.call_once(|| {
+		let prev = panic::take_hook();
+		panic::set_hook(Box::new(move |info| {
+			PANIC_MSG.with(|m| *m.borrow_mut() = Some(info.to_string()));
+			prev(info);
+		}));
+	});
+
+	PANIC_MSG.with(|m| m.borrow_mut().take());
+
+	panic::catch_unwind(AssertUnwindSafe(f)).map_err(|payload| {
+		PANIC_MSG
+			.with(|m| m.borrow_mut().take())
+			.or_else(|| payload.downcast_ref::<&str>().map(|s| s.to_string()))
+			.or_else(|| payload.downcast_ref::<String>().cloned())
+			.unwrap_or_else(|| "unknown panic".into())
+	})
 }
 
 unsafe impl Send for Terminated {}
 unsafe impl Sync for Terminated {}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn panic_msg() {
+		assert_eq!(catch_panic(|| {}), Ok(()));
+
+		let msg = catch_panic(|| panic!("oh no")).unwrap_err();
+		assert!(msg.contains("oh no"));
+		// the location
+		assert!(msg.contains("server.rs"));
+	}
+}
@@ -1,16 +1,21 @@
 use std::marker::PhantomData;
 
 use chuchi::{
-	extractor::Extractor, extractor_extract, extractor_prepare,
-	extractor_validate, header::RequestHeader,
+	extractor::Extractor,
+	extractor_extract, extractor_prepare, extractor_validate,
+	header::{Method, RequestHeader},
 };
 
 use crate::users::Users;
 
-use super::{Error, Rights, Session, Token, User};
+use super::{ApiToken, ApiTokenScope, Error, Rights, Session, Token, User};
 
-pub struct CheckedUser<RightsCheck = RightsRoot, AuthToken = NormalToken> {
-	pub session: Session,
+/// a user which passed the rights check `RightsCheck`
+///
+/// There is no default check, use [`RightsApp`] for endpoints of an app,
+/// [`RightsRoot`] for admin endpoints or [`RightsAny`].
+pub struct CheckedUser<RightsCheck, AuthToken = NormalToken> {
+	pub auth: Auth,
 	pub user: User,
 	rights_check: PhantomData<RightsCheck>,
 	auth_token: PhantomData<AuthToken>,
@@ -36,17 +41,46 @@ where
 
 		let token = AT::get_token(prepare.header)?;
 
-		let (session, user) = match token {
+		let (auth, user) = match token {
 			TokenKind::Normal(token) => {
-				users.sess_user_from_token(&token).await?
+				let (sess, user) = users.sess_user_from_token(&token).await?;
+				AT::check_session(&sess)?;
+				(Auth::Session(sess), user)
 			}
 			TokenKind::Data(token) => {
-				users.sess_user_from_data_token(&token).await?
+				let (sess, user) =
+					users.sess_user_from_data_token(&token).await?;
+				AT::check_session(&sess)?;
+				(Auth::Session(sess), user)
+			}
+			TokenKind::Api(token) => {
+				if !AT::allows_api_token() {
+					return Err(Error::MissingRights);
+				}
+
+				let (api_token, user) =
+					users.user_from_api_token(&token).await?;
+
+				let read_only = matches!(
+					*prepare.header.method(),
+					Method::GET | Method::HEAD
+				);
+				if !RC::check_scope(&api_token.scope)
+					|| !(read_only || api_token.scope.write)
+				{
+					return Err(Error::MissingRights);
+				}
+
+				(Auth::ApiToken(api_token), user)
 			}
 		};
 
+		if !RC::check(&user.rights) {
+			return Err(Error::MissingRights);
+		}
+
 		Ok(Self {
-			session,
+			auth,
 			user,
 			rights_check: PhantomData,
 			auth_token: PhantomData,
@@ -56,8 +90,32 @@ where
 	extractor_extract!(|extract| { Ok(extract.prepared) });
 }
 
+impl<RC, AT> CheckedUser<RC, AT> {
+	/// returns `None` if an api token was used
+	pub fn session(&self) -> Option<&Session> {
+		match &self.auth {
+			Auth::Session(sess) => Some(sess),
+			Auth::ApiToken(_) => None,
+		}
+	}
+}
+
+/// How the user authenticated
+#[derive(Debug, Clone)]
+pub enum Auth {
+	Session(Session),
+	ApiToken(ApiToken),
+}
+
 pub trait RightsCheck {
 	fn check(rights: &Rights) -> bool;
+
+	/// if an api token with this scope is allowed
+	///
+	/// api tokens are not allowed by default
+	fn check_scope(_scope: &ApiTokenScope) -> bool {
+		false
+	}
 }
 
 pub struct RightsAny;
@@ -66,6 +124,10 @@ impl RightsCheck for RightsAny {
 	fn check(_rights: &Rights) -> bool {
 		true
 	}
+
+	fn check_scope(_scope: &ApiTokenScope) -> bool {
+		true
+	}
 }
 
 pub struct RightsRoot;
@@ -76,22 +138,54 @@ impl RightsCheck for RightsRoot {
 	}
 }
 
+/// an app which can be granted to a user via [`Rights::apps`]
+pub trait AppKey {
+	/// the same key that was passed to `init_fn!`
+	const KEY: &'static str;
+}
+
+/// requires that the user has access to the app `A`
+pub struct RightsApp<A>(PhantomData<A>);
+
+impl<A: AppKey> RightsCheck for RightsApp<A> {
+	fn check(rights: &Rights) -> bool {
+		rights.has_app(A::KEY)
+	}
+
+	fn check_scope(scope: &ApiTokenScope) -> bool {
+		scope.has_app(A::KEY)
+	}
+}
+
 pub enum TokenKind {
 	Normal(Token),
 	Data(Token),
+	Api(Token),
 }
 
 pub trait AuthToken {
 	fn get_token(header: &RequestHeader) -> Result<TokenKind, Error>;
+
+	/// get's called with the session the token belongs to
+	fn check_session(_session: &Session) -> Result<(), Error> {
+		Ok(())
+	}
+
+	/// if an api token returned by `get_token` can be used
+	fn allows_api_token() -> bool {
+		true
+	}
 }
 
 pub struct NormalToken;
 
+/// the auth-token header or an api token
 impl AuthToken for NormalToken {
 	fn get_token(header: &RequestHeader) -> Result<TokenKind, Error> {
 		super::get_token(header)
-			.ok_or(Error::MissingAuthToken)
 			.map(TokenKind::Normal)
+			.or_else(|| super::get_api_token(header).map(TokenKind::Api))
+			.ok_or(Error::MissingAuthToken)
 	}
 }
 
@@ -104,3 +198,51 @@ impl AuthToken for DataToken {
 			.map(TokenKind::Data)
 	}
 }
+
+/// requires that the password of a session was entered recently, which can
+/// be done via the core endpoint `/api/users/reauth`
+///
+/// api tokens are refused since they are never entered again
+pub struct Fresh<AT = NormalToken>(PhantomData<AT>);
+
+impl<AT: AuthToken> AuthToken for Fresh<AT> {
+	fn get_token(header: &RequestHeader) -> Result<TokenKind, Error> {
+		AT::get_token(header)
+	}
+
+	fn check_session(session: &Session) -> Result<(), Error> {
+		AT::check_session(session)?;
+
+		if !session.is_fresh() {
+			return Err(Error::ReauthRequired);
+		}
+
+		Ok(())
+	}
+
+	fn allows_api_token() -> bool {
+		false
+	}
+}
+
+/// refuses sessions in which root impersonates the user, should be used for
+/// destructive actions
+pub struct NoImpersonation<AT = NormalToken>(PhantomData<AT>);
+
+impl<AT: AuthToken> AuthToken for NoImpersonation<AT> {
+	fn get_token(header: &RequestHeader) -> Result<TokenKind, Error> {
+		AT::get_token(header)
+	}
+
+	fn check_session(session: &Session) -> Result<(), Error> {
+		if session.is_impersonated() {
+			return Err(Error::Impersonated);
+		}
+
+		AT::check_session(session)
+	}
+
+	fn allows_api_token() -> bool {
+		AT::allows_api_token()
+	}
+}
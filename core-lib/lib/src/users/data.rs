@@ -1,6 +1,8 @@
 pub use super::timeout::Timeout;
 use crate::ffi;
 
+use std::time::Duration;
+
 use chuchi_postgres::time::DateTime;
 use chuchi_postgres::UniqueId;
 
@@ -19,29 +21,127 @@ pub struct User {
 #[serde(rename_all = "camelCase")]
 pub struct Rights {
 	pub root: bool,
+	/// the apps this user is allowed to use
+	///
+	/// `None` grants access to all apps, which is what users created before
+	/// app rights existed get
+	#[serde(default)]
+	pub apps: Option<Vec<String>>,
+}
+
+impl Rights {
+	/// returns true if the user is allowed to use the app with the given key
+	///
+	/// root always has access to all apps
+	pub fn has_app(&self, app: &str) -> bool {
+		self.root
+			|| self
+				.apps
+				.as_ref()
+				.map(|apps| apps.iter().any(|a| a == app))
+				.unwrap_or(true)
+	}
 }
 
 pub type Token = crypto::token::Token<32>;
 
+/// A long lived token which can be used by scripts via
+/// `Authorization: Bearer {token}`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiToken {
+	pub id: UniqueId,
+	pub user_id: UniqueId,
+	pub name: String,
+	pub scope: ApiTokenScope,
+	pub expires_on: Option<DateTime>,
+	pub created_on: DateTime,
+}
+
+impl ApiToken {
+	pub fn did_expire(&self) -> bool {
+		self.expires_on.is_some_and(|e| e < DateTime::now())
+	}
+}
+
+/// What an api token is allowed to do, this is always limited by the
+/// rights of the user
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiTokenScope {
+	/// the apps the token can be used for, `None` allows all apps the
+	/// user has access to
+	pub apps: Option<Vec<String>>,
+	/// if false only GET requests are allowed
+	pub write: bool,
+}
+
+impl ApiTokenScope {
+	pub fn has_app(&self, app: &str) -> bool {
+		self.apps
+			.as_ref()
+			.map(|apps| apps.iter().any(|a| a == app))
+			.unwrap_or(true)
+	}
+}
+
+/// A household or another group of users, apps can use it to share data
+/// between the members
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Group {
+	pub id: UniqueId,
+	pub name: String,
+	/// the ids of the users in this group
+	pub members: Vec<UniqueId>,
+	pub created_on: DateTime,
+}
+
+impl Group {
+	pub fn has_member(&self, user_id: &UniqueId) -> bool {
+		self.members.contains(user_id)
+	}
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Session {
 	pub token: Token,
 	pub data_token: Token,
+	/// when the session expires
+	///
+	/// if the session has an idle timeout this get's extended every time
+	/// the session is used
 	pub timeout: Timeout,
 	pub created_on: DateTime,
 	pub user_id: UniqueId,
+	/// until when the password counts as recently entered
+	///
+	/// see [`Fresh`](super::Fresh)
+	pub fresh_until: DateTime,
+	/// the root user which uses this session to see what the user sees
+	///
+	/// apps should show this and refuse destructive actions, see
+	/// [`NoImpersonation`](super::NoImpersonation)
+	#[serde(default)]
+	pub impersonated_by: Option<UniqueId>,
 }
 
 impl Session {
 	#[cfg(feature = "i-am-core")]
-	pub fn new(timeout: Timeout, user_id: UniqueId) -> Self {
+	pub fn new(
+		timeout: Timeout,
+		user_id: UniqueId,
+		fresh_until: DateTime,
+	) -> Self {
 		Self {
 			token: Token::new(),
 			data_token: Token::new(),
 			timeout,
 			created_on: DateTime::now(),
 			user_id,
+			fresh_until,
+			impersonated_by: None,
 		}
 	}
 
@@ -49,6 +149,20 @@ impl Session {
 		self.timeout.has_elapsed()
 	}
 
+	/// returns how long the session is valid if it is not used anymore
+	pub fn remaining(&self) -> Duration {
+		self.timeout.remaining()
+	}
+
+	/// returns true if the password was entered recently
+	pub fn is_fresh(&self) -> bool {
+		DateTime::now() < self.fresh_until
+	}
+
+	pub fn is_impersonated(&self) -> bool {
+		self.impersonated_by.is_some()
+	}
+
 	pub fn from_c(inner: ffi::c_session) -> Self {
 		Self {
 			token: inner.token.into_token(),
@@ -56,6 +170,10 @@ impl Session {
 			timeout: Timeout::from_c(inner.timeout),
 			created_on: inner.created_on.to_datetime(),
 			user_id: inner.user_id.to_uid(),
+			fresh_until: inner.fresh_until.to_datetime(),
+			impersonated_by: inner
+				.impersonated
+				.then(|| inner.impersonated_by.to_uid()),
 		}
 	}
 
@@ -66,6 +184,36 @@ impl Session {
 			timeout: self.timeout.into_c(),
 			created_on: ffi::c_datetime::from_datetime(self.created_on),
 			user_id: ffi::c_uid::from_uid(self.user_id),
+			fresh_until: ffi::c_datetime::from_datetime(self.fresh_until),
+			impersonated_by: ffi::c_uid::from_uid(
+				self.impersonated_by
+					.unwrap_or_else(|| UniqueId::from_raw([0; 10])),
+			),
+			impersonated: self.impersonated_by.is_some(),
 		}
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn rights_has_app() {
+		let rights: Rights = serde_json::from_str(r#"{"root":false}"#).unwrap();
+		assert!(rights.has_app("cinema"));
+
+		let rights = Rights {
+			root: false,
+			apps: Some(vec!["cinema".into()]),
+		};
+		assert!(rights.has_app("cinema"));
+		assert!(!rights.has_app("pwvault"));
+
+		let rights = Rights {
+			root: true,
+			apps: Some(vec![]),
+		};
+		assert!(rights.has_app("pwvault"));
+	}
+}
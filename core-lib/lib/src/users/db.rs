@@ -1,8 +1,9 @@
-use super::{Rights, User};
+use super::{ApiToken, ApiTokenScope, Group, Rights, Token, User};
 
 use chuchi_postgres::json::Json;
 use chuchi_postgres::table::TableOwned;
-use chuchi_postgres::{filter, FromRow, ToRow};
+use chuchi_postgres::time::DateTime;
+use chuchi_postgres::{filter, try2, FromRow, ToRow};
 use chuchi_postgres::{Database, Result, TableTempl, UniqueId};
 
 /// should only be used by core itself
@@ -16,6 +17,11 @@ pub struct UnsafeUser {
 	// hashed
 	pub password: String,
 	pub rights: Json<Rights>,
+	/// base32 encoded, only gets checked if `totp_enabled` is set
+	pub totp_secret: Option<String>,
+	pub totp_enabled: bool,
+	/// hashes of the recovery codes which were not used yet
+	pub totp_recovery: Json<Vec<String>>,
 }
 
 impl From<UnsafeUser> for User {
@@ -29,24 +35,161 @@ impl From<UnsafeUser> for User {
 	}
 }
 
+/// should only be used by core itself
+///
+/// The table get's created by core.
+#[derive(Debug, TableTempl, ToRow, FromRow)]
+pub struct UnsafeApiToken {
+	#[index(primary)]
+	pub id: UniqueId,
+	#[index(index)]
+	pub user_id: UniqueId,
+	pub name: String,
+	/// see [`hash_api_token`]
+	#[index(unique)]
+	pub token_hash: String,
+	pub scope: Json<ApiTokenScope>,
+	pub expires_on: Option<DateTime>,
+	pub created_on: DateTime,
+}
+
+impl From<UnsafeApiToken> for ApiToken {
+	fn from(t: UnsafeApiToken) -> Self {
+		Self {
+			id: t.id,
+			user_id: t.user_id,
+			name: t.name,
+			scope: t.scope.0,
+			expires_on: t.expires_on,
+			created_on: t.created_on,
+		}
+	}
+}
+
+/// should only be used by core itself
+///
+/// The table get's created by core.
+#[derive(Debug, TableTempl, ToRow, FromRow)]
+pub struct DbGroup {
+	#[index(primary)]
+	pub id: UniqueId,
+	pub name: String,
+	pub created_on: DateTime,
+}
+
+impl DbGroup {
+	pub fn into_group(self, members: Vec<DbGroupMember>) -> Group {
+		Group {
+			id: self.id,
+			name: self.name,
+			members: members.into_iter().map(|m| m.user_id).collect(),
+			created_on: self.created_on,
+		}
+	}
+}
+
+/// should only be used by core itself
+///
+/// The table get's created by core.
+#[derive(Debug, TableTempl, ToRow, FromRow)]
+pub struct DbGroupMember {
+	#[index(index)]
+	pub group_id: UniqueId,
+	#[index(index)]
+	pub user_id: UniqueId,
+	pub added_on: DateTime,
+}
+
+/// api tokens are random so a fast hash without salt is enough
+pub fn hash_api_token(token: &Token) -> String {
+	crypto::hash::hash(token.to_string()).to_string()
+}
+
 #[derive(Debug, Clone)]
 pub struct Users {
 	/// Todo replace this with a normal table
 	/// so we also don't need to implement TableTempl on UnsafeUser
 	table: TableOwned<UnsafeUser>,
+	api_tokens: TableOwned<UnsafeApiToken>,
+	groups: TableOwned<DbGroup>,
+	group_members: TableOwned<DbGroupMember>,
 }
 
 impl Users {
 	pub async fn new(db: &Database) -> Self {
 		Self {
 			table: db.table_owned("users").create().await,
+			api_tokens: db.table_owned("api_tokens"),
+			groups: db.table_owned("groups"),
+			group_members: db.table_owned("group_members"),
 		}
 	}
 
+	pub async fn api_token(&self, token: &Token) -> Result<Option<ApiToken>> {
+		let token_hash = hash_api_token(token);
+		self.api_tokens
+			.find_one(filter!(&token_hash))
+			.await
+			.map(|opt| opt.map(Into::into))
+	}
+
 	pub async fn by_id(&self, id: &UniqueId) -> Result<Option<User>> {
 		self.table
 			.find_one(filter!(id))
 			.await
 			.map(|opt| opt.map(Into::into))
 	}
+
+	pub async fn group_by_id(&self, id: &UniqueId) -> Result<Option<Group>> {
+		let group = try2!(self.groups.find_one(filter!(id)).await?);
+		let group_id = id;
+		let members = self.group_members.find_many(filter!(group_id)).await?;
+
+		Ok(Some(group.into_group(members)))
+	}
+
+	pub async fn groups_by_user(
+		&self,
+		user_id: &UniqueId,
+	) -> Result<Vec<Group>> {
+		let conn = self.groups.get_connection().await?;
+		// one row per member of every group the user is in
+		let rows: Vec<GroupMemberRow> = conn
+			.connection()
+			.query(
+				"SELECT g.id, g.name, g.created_on, o.user_id AS member_id \
+				FROM group_members m \
+				JOIN groups g ON g.id = m.group_id \
+				JOIN group_members o ON o.group_id = g.id \
+				WHERE m.user_id = $1 \
+				ORDER BY g.id",
+				&[user_id],
+			)
+			.await?;
+
+		let mut groups: Vec<Group> = vec![];
+		for row in rows {
+			match groups.last_mut() {
+				Some(group) if group.id == row.id => {
+					group.members.push(row.member_id)
+				}
+				_ => groups.push(Group {
+					id: row.id,
+					name: row.name,
+					members: vec![row.member_id],
+					created_on: row.created_on,
+				}),
+			}
+		}
+
+		Ok(groups)
+	}
+}
+
+#[derive(Debug, FromRow)]
+struct GroupMemberRow {
+	id: UniqueId,
+	name: String,
+	created_on: DateTime,
+	member_id: UniqueId,
 }
@@ -11,10 +11,11 @@ mod users;
 use std::error::Error as StdError;
 
 pub use checked_user::{
-	CheckedUser, DataToken, NormalToken, RightsAny, RightsRoot,
+	AppKey, Auth, AuthToken, CheckedUser, DataToken, Fresh, NoImpersonation,
+	NormalToken, RightsAny, RightsApp, RightsCheck, RightsRoot, TokenKind,
 };
-pub use data::{Rights, Session, Token, User};
-pub use sessions::Sessions;
+pub use data::{ApiToken, ApiTokenScope, Group, Rights, Session, Token, User};
+pub use sessions::{Sessions, SessionsLookup};
 pub use timeout::Timeout;
 pub use users::Users;
 
@@ -30,6 +31,20 @@ pub fn get_token(header: &RequestHeader) -> Option<Token> {
 	header.value("auth-token").and_then(|t| t.parse().ok())
 }
 
+/// returns the token from `Authorization: Bearer {token}`
+///
+/// the scheme is case-insensitive
+pub fn get_api_token(header: &RequestHeader) -> Option<Token> {
+	let (scheme, token) =
+		header.value("authorization")?.trim().split_once(' ')?;
+
+	if !scheme.eq_ignore_ascii_case("bearer") {
+		return None;
+	}
+
+	token.trim().parse().ok()
+}
+
 pub fn get_token_from_cookie(header: &RequestHeader) -> Option<Token> {
 	header
 		.value("cookie")
@@ -43,7 +58,15 @@ pub enum Error {
 	MissingDataToken,
 	InvalidAuthToken,
 	InvalidDataToken,
+	/// the api token does not exist or has expired
+	InvalidApiToken,
 	InvalidUser,
+	/// the user is valid but is not allowed to access this resource
+	MissingRights,
+	/// the session is valid but the password needs to be entered again
+	ReauthRequired,
+	/// the action is not allowed while root impersonates the user
+	Impersonated,
 	Db(chuchi_postgres::Error),
 }
 
@@ -63,7 +86,11 @@ impl ExtractorError for Error {
 			}
 			Self::InvalidAuthToken
 			| Self::InvalidDataToken
-			| Self::InvalidUser => ClientErrorKind::Forbidden.into(),
+			| Self::InvalidApiToken
+			| Self::InvalidUser
+			| Self::MissingRights
+			| Self::ReauthRequired
+			| Self::Impersonated => ClientErrorKind::Forbidden.into(),
 			Self::Db(_) => ServerErrorKind::InternalServerError.into(),
 		}
 	}
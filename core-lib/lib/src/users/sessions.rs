@@ -4,39 +4,63 @@ use crate::ffi;
 
 use super::{Session, Token};
 
+/// which lookups core provides, depends on the version of core
+#[doc(hidden)]
+pub enum SessionsLookup {
+	V1,
+	/// since 0.2
+	V2(ffi::c_sessions_v2),
+	/// since 0.4
+	V3(ffi::c_sessions_v3),
+}
+
 pub struct Sessions {
 	inner: ffi::c_sessions,
+	lookup: SessionsLookup,
 }
 
 impl Sessions {
-	pub fn new(inner: ffi::c_sessions) -> Self {
-		Self { inner }
+	pub fn new(inner: ffi::c_sessions, lookup: SessionsLookup) -> Self {
+		Self { inner, lookup }
 	}
 
 	pub fn by_token(&self, token: &Token) -> Option<Session> {
-		let mut sess = MaybeUninit::uninit();
-		let token = ffi::c_token::from_token(token.clone());
-		let some =
-			(self.inner.by_token)(self.inner.ctx, token, sess.as_mut_ptr());
-
-		if some {
-			Some(Session::from_c(unsafe { sess.assume_init() }))
-		} else {
-			None
+		match &self.lookup {
+			SessionsLookup::V1 => {
+				self.find(self.inner.by_token, token, |s| s.into_session())
+			}
+			SessionsLookup::V2(l) => {
+				self.find(l.by_token, token, |s| s.into_session())
+			}
+			SessionsLookup::V3(l) => self.find(l.by_token, token, |s| s),
 		}
 	}
 
 	pub fn by_data_token(&self, token: &Token) -> Option<Session> {
+		match &self.lookup {
+			SessionsLookup::V1 => {
+				self.find(self.inner.by_data_token, token, |s| s.into_session())
+			}
+			SessionsLookup::V2(l) => {
+				self.find(l.by_data_token, token, |s| s.into_session())
+			}
+			SessionsLookup::V3(l) => self.find(l.by_data_token, token, |s| s),
+		}
+	}
+
+	fn find<S>(
+		&self,
+		f: ffi::c_session_fn<S>,
+		token: &Token,
+		conv: fn(S) -> ffi::c_session,
+	) -> Option<Session> {
 		let mut sess = MaybeUninit::uninit();
 		let token = ffi::c_token::from_token(token.clone());
-		let some = (self.inner.by_data_token)(
-			self.inner.ctx,
-			token,
-			sess.as_mut_ptr(),
-		);
+		let some = f(self.inner.ctx, token, sess.as_mut_ptr());
 
 		if some {
-			Some(Session::from_c(unsafe { sess.assume_init() }))
+			let sess = unsafe { sess.assume_init() };
+			Some(Session::from_c(conv(sess)))
 		} else {
 			None
 		}
@@ -6,7 +6,7 @@ use serde::de::{Deserializer, Error};
 use serde::ser::Serializer;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Timeout {
 	inner: SystemTime,
 }
@@ -22,6 +22,13 @@ impl Timeout {
 		SystemTime::now() > self.inner
 	}
 
+	/// returns the time left until it elapses
+	pub fn remaining(&self) -> Duration {
+		self.inner
+			.duration_since(SystemTime::now())
+			.unwrap_or_default()
+	}
+
 	/// returns the time from UNIX_EPOCH
 	pub fn as_secs(&self) -> u64 {
 		self.inner
@@ -1,7 +1,7 @@
 use chuchi::Resource;
-use chuchi_postgres::Database;
+use chuchi_postgres::{Database, UniqueId};
 
-use super::{db, Error, Session, Sessions, Token, User};
+use super::{db, ApiToken, Error, Group, Session, Sessions, Token, User};
 
 #[derive(Resource)]
 pub struct Users {
@@ -52,4 +52,57 @@ impl Users {
 
 		Ok((sess, user))
 	}
+
+	pub async fn user_from_api_token(
+		&self,
+		token: &Token,
+	) -> Result<(ApiToken, User), Error> {
+		let api_token = self
+			.db
+			.api_token(token)
+			.await
+			.map_err(Error::Db)?
+			.filter(|t| !t.did_expire())
+			.ok_or(Error::InvalidApiToken)?;
+		let user = self
+			.db
+			.by_id(&api_token.user_id)
+			.await
+			.map_err(Error::Db)?
+			.ok_or(Error::InvalidUser)?;
+
+		Ok((api_token, user))
+	}
+
+	/// returns all groups the user is a member of
+	///
+	/// apps can use this to share data between the members of a household
+	pub async fn groups_by_user(
+		&self,
+		user_id: &UniqueId,
+	) -> Result<Vec<Group>, Error> {
+		self.db.groups_by_user(user_id).await.map_err(Error::Db)
+	}
+
+	pub async fn group_by_id(
+		&self,
+		id: &UniqueId,
+	) -> Result<Option<Group>, Error> {
+		self.db.group_by_id(id).await.map_err(Error::Db)
+	}
+
+	/// returns true if both users are members of at least one common group
+	pub async fn share_group(
+		&self,
+		a: &UniqueId,
+		b: &UniqueId,
+	) -> Result<bool, Error> {
+		if a == b {
+			return Ok(true);
+		}
+
+		let groups = self.groups_by_user(a).await?;
+
+		Ok(groups.iter().any(|g| g.has_member(b)))
+	}
 }
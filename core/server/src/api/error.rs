@@ -1,16 +1,40 @@
+use crate::preferences::PreferenceError;
+use crate::users::oidc::OidcError;
+use crate::users::password::PolicyError;
+use crate::users::Token;
+
 use std::fmt;
 
 use serde::{Deserialize, Serialize};
 
+use chuchi_postgres::time::DateTime;
+
 use chuchi::api::error::{self, ApiError, StatusCode};
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Error {
 	LoginIncorrect,
+	/// the password was correct but a totp code is required to finish the
+	/// login, see `LoginTotpReq`
+	TotpRequired(Token),
+	TotpIncorrect,
+	/// too many failed login attempts, retry after the given time
+	TooManyAttempts(DateTime),
 	MissingAuthToken,
 	InvalidAuthToken,
 	MissingDataToken,
 	InvalidDataToken,
+	MissingRights,
+	/// the password needs to be entered again, see `ReauthReq`
+	ReauthRequired,
+	/// the action is not allowed while root impersonates the user
+	Impersonated,
+	UsernameTaken,
+	/// the new password needs at least the given amount of characters
+	PasswordTooShort(usize),
+	/// the new password is too common or equal to the username
+	PasswordNotAllowed,
+	NotFound,
 	Internal(String),
 	Request(String),
 }
@@ -34,12 +58,22 @@ impl ApiError for Error {
 	fn status_code(&self) -> StatusCode {
 		match self {
 			Self::LoginIncorrect
+			| Self::TotpRequired(_)
+			| Self::TotpIncorrect
 			| Self::MissingAuthToken
 			| Self::InvalidAuthToken
 			| Self::MissingDataToken
-			| Self::InvalidDataToken => StatusCode::FORBIDDEN,
+			| Self::InvalidDataToken
+			| Self::MissingRights
+			| Self::ReauthRequired
+			| Self::Impersonated => StatusCode::FORBIDDEN,
+			Self::UsernameTaken => StatusCode::CONFLICT,
+			Self::TooManyAttempts(_) => StatusCode::TOO_MANY_REQUESTS,
+			Self::NotFound => StatusCode::NOT_FOUND,
 			Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
-			Self::Request(_) => StatusCode::BAD_REQUEST,
+			Self::PasswordTooShort(_)
+			| Self::PasswordNotAllowed
+			| Self::Request(_) => StatusCode::BAD_REQUEST,
 		}
 	}
 }
@@ -57,3 +91,42 @@ impl From<chuchi_postgres::Error> for Error {
 		Self::Internal(e.to_string())
 	}
 }
+
+impl From<OidcError> for Error {
+	fn from(e: OidcError) -> Self {
+		match e {
+			OidcError::NotConfigured => {
+				Self::Request("oidc is not configured".into())
+			}
+			OidcError::InvalidState | OidcError::InvalidToken(_) => {
+				Self::LoginIncorrect
+			}
+			OidcError::Provider(e) => Self::Internal(e),
+		}
+	}
+}
+
+impl From<PolicyError> for Error {
+	fn from(e: PolicyError) -> Self {
+		match e {
+			PolicyError::TooShort(min) => Self::PasswordTooShort(min),
+			PolicyError::NotAllowed => Self::PasswordNotAllowed,
+		}
+	}
+}
+
+impl From<PreferenceError> for Error {
+	fn from(e: PreferenceError) -> Self {
+		match e {
+			PreferenceError::Database(e) => e.into(),
+			PreferenceError::InvalidKey => Self::Request("invalid key".into()),
+			PreferenceError::TooLarge(max) => {
+				Self::Request(format!("value larger than {max} bytes"))
+			}
+			PreferenceError::InvalidValue => {
+				Self::Request("invalid value".into())
+			}
+			e @ PreferenceError::AppLoading => Self::Internal(e.to_string()),
+		}
+	}
+}
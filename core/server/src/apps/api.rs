@@ -4,22 +4,53 @@ use serde::{Deserialize, Serialize};
 
 use chuchi::api::{Method, Request};
 
+use chuchi_postgres::time::DateTime;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct App {
 	pub key: String,
 	pub js_entry: Option<String>,
 	pub css_entry: Option<String>,
+	pub version: Option<String>,
+	pub display_name: Option<String>,
+	/// path relative to the assets of the app
+	pub icon: Option<String>,
+	pub description: Option<String>,
+	/// apps with a lower priority get listed first
+	pub priority: i32,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct AppsReq {}
 
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", tag = "kind", content = "error")]
+pub enum AppState {
+	Loading,
+	Running,
+	Terminating,
+	/// contains why the app could not be loaded, it get's retried later
+	Failed(String),
+}
+
+/// An app library and what core is doing with it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AppFile {
+	pub file: String,
+	/// only known once the app was initialized
+	pub key: Option<String>,
+	pub state: AppState,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Apps {
 	pub apps: Vec<App>,
+	/// only filled for root
+	pub files: Vec<AppFile>,
 }
 
 impl Request for AppsReq {
@@ -28,4 +59,36 @@ impl Request for AppsReq {
 
 	const PATH: &'static str = "/api/apps/list";
 	const METHOD: Method = Method::GET;
+	const HEADERS: &'static [&'static str] = &["auth-token"];
+}
+
+/// An app which panicked
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Crash {
+	pub key: String,
+	pub file: String,
+	/// contains the location of the panic
+	pub message: String,
+	pub crashed_on: DateTime,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CrashesReq {}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Crashes {
+	/// the newest first
+	pub list: Vec<Crash>,
+}
+
+impl Request for CrashesReq {
+	type Response = Crashes;
+	type Error = Error;
+
+	const PATH: &'static str = "/api/apps/admin/crashes";
+	const METHOD: Method = Method::GET;
+	const HEADERS: &'static [&'static str] = &["auth-token"];
 }
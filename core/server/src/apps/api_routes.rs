@@ -1,17 +1,49 @@
-use super::api::{Apps, AppsReq};
+use super::api::{Apps, AppsReq, Crashes, CrashesReq};
 use crate::api::Result;
+use crate::users::api_routes::{root_from_req, sess_user_from_req};
+use crate::users::db::Users;
 
+use chuchi::header::RequestHeader;
 use chuchi::Chuchi;
 
 use chuchi::api;
 
 #[api(AppsReq)]
-async fn apps_route(apps: &super::Apps) -> Result<Apps> {
+async fn apps_route(
+	header: &RequestHeader,
+	users: &Users,
+	apps: &super::Apps,
+) -> Result<Apps> {
+	let (_, user) = sess_user_from_req(header, users).await?;
+
 	Ok(Apps {
-		apps: apps.to_api_apps(),
+		apps: apps
+			.to_api_apps()
+			.into_iter()
+			.filter(|a| user.rights.has_app(&a.key))
+			.collect(),
+		files: if user.rights.root {
+			apps.to_api_files()
+		} else {
+			vec![]
+		},
+	})
+}
+
+#[api(CrashesReq)]
+async fn admin_crashes(
+	header: &RequestHeader,
+	users: &Users,
+	apps: &super::Apps,
+) -> Result<Crashes> {
+	root_from_req(header, users).await?;
+
+	Ok(Crashes {
+		list: apps.to_api_crashes(),
 	})
 }
 
 pub fn add_routes(server: &mut Chuchi) {
 	server.add_route(apps_route);
+	server.add_route(admin_crashes);
 }
@@ -1,28 +1,38 @@
 use super::{prog, MODULE_EXTENSION};
+use crate::preferences::Preferences;
 use crate::tempfile::TempFile;
 use crate::Users;
 
 use std::mem::MaybeUninit;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::{fmt, io};
 
 use tokio::runtime::Handle;
 use tokio::time::{self, Duration};
 
-use core_lib::ffi;
 use core_lib::stream::Connector;
+use core_lib::{ffi, CoreVersion, Manifest, PREFERENCES_VERSION};
 
 use libloading::Library;
 
 pub const RUNNNIG: usize = 0;
 pub const TERMINATING: usize = 1;
 pub const TERMINATED: usize = 2;
+/// like terminated but the app panicked, see [`AppLib::crash`]
+pub const CRASHED: usize = 3;
+
+/// the message and location of the panic
+pub type CrashMsg = Arc<Mutex<Option<String>>>;
 
 pub struct AppLib {
 	pub connector: Connector,
 	pub name: &'static str,
 	pub js_entry: &'static str,
 	pub css_entry: &'static str,
+	pub manifest: Manifest,
 	pub terminated: prog::Receiver,
+	/// get's set before terminated changes to CRASHED
+	pub crash: CrashMsg,
 	pub terminator: Terminator,
 }
 
@@ -33,79 +43,183 @@ struct Lib {
 	file: TempFile,
 }
 
+#[derive(Debug)]
+pub enum LoadError {
+	Io(io::Error),
+	Library(libloading::Error),
+	/// the library does not export `c_init`
+	MissingInit(libloading::Error),
+	/// the app was built against a core-lib with another major version
+	IncompatibleAbi {
+		app: CoreVersion,
+		core: CoreVersion,
+	},
+}
+
+impl fmt::Display for LoadError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Self::Io(e) => write!(f, "could not copy the library: {e}"),
+			Self::Library(e) => write!(f, "could not load the library: {e}"),
+			Self::MissingInit(e) => write!(f, "c_init not found: {e}"),
+			Self::IncompatibleAbi { app, core } => write!(
+				f,
+				"the app was built for abi {app} which core {core} does not \
+				 support"
+			),
+		}
+	}
+}
+
+impl std::error::Error for LoadError {}
+
+impl From<io::Error> for LoadError {
+	fn from(e: io::Error) -> Self {
+		Self::Io(e)
+	}
+}
+
 impl AppLib {
-	pub fn new(path: &str, cfg: &str, users: &Users) -> Self {
-		let file = TempFile::new(MODULE_EXTENSION).unwrap();
-		std::fs::copy(path, &file).unwrap();
+	/// the abi version get's checked before the app is initialized
+	pub fn new(
+		path: &str,
+		cfg: &str,
+		users: &Users,
+		preferences: &Preferences,
+	) -> Result<Self, LoadError> {
+		let file = TempFile::new(MODULE_EXTENSION)?;
+		std::fs::copy(path, &file)?;
 
 		let lib = Arc::new(Lib {
-			lib: unsafe { Library::new(file.as_path()).unwrap() },
+			lib: unsafe {
+				Library::new(file.as_path()).map_err(LoadError::Library)?
+			},
 			file,
 		});
+
+		// apps built before the symbol existed have a compatible layout
+		let abi_version = unsafe {
+			lib.lib
+				.get::<*const ffi::c_core_version>(b"c_abi_version")
+				.map(|v| CoreVersion::from_c(&**v))
+				.unwrap_or(CoreVersion { major: 0, minor: 1 })
+		};
+		if !abi_version.is_compatible(&CoreVersion::CURRENT) {
+			return Err(LoadError::IncompatibleAbi {
+				app: abi_version,
+				core: CoreVersion::CURRENT,
+			});
+		}
+
+		let c_init = unsafe {
+			*lib.lib
+				.get::<ffi::c_init_fn>(b"c_init")
+				.map_err(LoadError::MissingInit)?
+		};
+
 		let (term_tx, term_rx) = prog::channel(RUNNNIG);
 
+		let crash = CrashMsg::default();
+
 		let terminated_ctx = Box::new(TerminatedCtx {
 			lib: lib.clone(),
 			notify: term_tx.clone(),
+			crash: crash.clone(),
 			handle: Handle::current(),
 		});
 
-		let c_init =
-			unsafe { lib.lib.get::<ffi::c_init_fn>(b"c_init").unwrap() };
-
 		// setup terminated
 		extern "C" fn terminated_fn(ctx: *mut u8) {
 			let ctx = unsafe { Box::from_raw(ctx as *mut TerminatedCtx) };
-			ctx.notify.send(TERMINATED);
-
-			ctx.handle.clone().spawn(async move {
-				time::sleep(Duration::from_secs(2)).await;
+			ctx.close(TERMINATED);
+		}
 
-				if let Ok(lib) = Arc::try_unwrap(ctx.lib) {
-					if let Err(e) = lib.lib.close() {
-						eprintln!("closing lib failed with {e:?}");
-					}
-				}
-			});
+		extern "C" fn crashed_fn(ctx: *mut u8, msg: ffi::c_string) {
+			let ctx = unsafe { Box::from_raw(ctx as *mut TerminatedCtx) };
+			let msg = unsafe { msg.into_string() };
+			*ctx.crash.lock().unwrap() = Some(msg);
+			ctx.close(CRASHED);
 		}
+
 		let c_terminated = ffi::c_terminated {
 			ctx: Box::into_raw(terminated_ctx) as *mut u8,
 			terminated: terminated_fn,
 		};
 
+		// the name is only known after init, until then calls fail
+		let app_name = Arc::new(OnceLock::new());
+		// older apps never call free
+		let c_preferences = if abi_version >= PREFERENCES_VERSION {
+			preferences.to_c(app_name.clone())
+		} else {
+			Default::default()
+		};
+
 		let mut core = ffi::c_core {
 			config: ffi::c_str::from_str(cfg),
-			version: ffi::c_core_version { major: 0, minor: 1 },
+			version: CoreVersion::CURRENT.to_c(),
 			sessions: users.to_sessions_c(),
 			terminated: c_terminated,
+			sessions_v2: users.to_sessions_v2_c(),
+			preferences: c_preferences,
+			sessions_v3: users.to_sessions_v3_c(),
+			crashed: crashed_fn,
+			preferences_v2: Preferences::to_c_v2(),
 		};
 
-		let mut app = MaybeUninit::uninit();
+		// apps built against an older version don't write the manifest
+		let mut app = MaybeUninit::<ffi::c_app>::zeroed();
 
 		c_init(&mut core as *mut _, app.as_mut_ptr());
 
 		let app = unsafe { app.assume_init() };
+		let name = unsafe { app.name.to_static_str() };
+		let _ = app_name.set(name.to_string());
+		let manifest = if app.has_manifest {
+			unsafe { Manifest::from_c(&app.manifest) }
+		} else {
+			Manifest::new()
+		};
 
-		Self {
+		Ok(Self {
 			connector: Connector::new(app.listener),
-			name: unsafe { app.name.to_static_str() },
+			name,
 			js_entry: unsafe { app.js_entry.to_static_str() },
 			css_entry: unsafe { app.css_entry.to_static_str() },
+			manifest,
 			terminated: term_rx,
+			crash,
 			terminator: Terminator {
 				inner: app.terminator,
 				notify: term_tx,
 			},
-		}
+		})
 	}
 }
 
 struct TerminatedCtx {
 	lib: Arc<Lib>,
 	notify: prog::Sender,
+	crash: CrashMsg,
 	handle: Handle,
 }
 
+impl TerminatedCtx {
+	fn close(self: Box<Self>, state: usize) {
+		self.notify.send(state);
+
+		self.handle.clone().spawn(async move {
+			time::sleep(Duration::from_secs(2)).await;
+
+			if let Ok(lib) = Arc::try_unwrap(self.lib) {
+				if let Err(e) = lib.lib.close() {
+					eprintln!("closing lib failed with {e:?}");
+				}
+			}
+		});
+	}
+}
+
 pub struct Terminator {
 	inner: ffi::c_terminator,
 	notify: prog::Sender,
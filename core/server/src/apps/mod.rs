@@ -3,15 +3,19 @@ pub mod api_routes;
 mod app_lib;
 pub mod route;
 
-use app_lib::{AppLib, Terminator};
+use api::AppState;
+use app_lib::{AppLib, CrashMsg, Terminator};
 use chuchi::resources::Resources;
 use chuchi::routes::HyperRequest;
 use chuchi::Resource;
 
+use crate::audit::{Audit, Event, EventKind};
+use crate::preferences::Preferences;
 use crate::Users;
 
 use std::borrow::Borrow;
-use std::collections::HashMap;
+use std::cmp::Reverse;
+use std::collections::{HashMap, VecDeque};
 use std::convert::Infallible;
 use std::future::{self, ready, Future};
 use std::pin::Pin;
@@ -21,7 +25,7 @@ use std::time::{Instant, SystemTime};
 use std::{io, mem};
 
 use tokio::fs;
-use tokio::task::JoinHandle;
+use tokio::task::{self, JoinHandle};
 use tokio::time::{self, Duration};
 
 use http::uri::{Authority, Scheme, Uri};
@@ -31,12 +35,23 @@ use hyper_util::rt::TokioExecutor;
 
 use core_lib::progress_channel as prog;
 use core_lib::stream::{Connector, Stream};
+use core_lib::Manifest;
+
+use chuchi_postgres::time::DateTime;
 
 use serde::{Deserialize, Serialize};
 
 type HyperResponse = hyper::Response<Incoming>;
 
 const MIN_RUNTIME: Duration = Duration::from_secs(4);
+const RETRY_MIN: Duration = Duration::from_secs(10);
+const RETRY_MAX: Duration = Duration::from_secs(10 * 60);
+/// an app which crashes this often within the window only get's restarted
+/// once it's file changes
+const CRASH_LIMIT: usize = 5;
+const CRASH_WINDOW: Duration = Duration::from_secs(10 * 60);
+/// how many crashes are kept per app
+const CRASH_HISTORY: usize = 20;
 
 #[cfg(unix)]
 const MODULE_EXTENSION: &str = "so";
@@ -70,46 +85,138 @@ impl Apps {
 
 	pub fn to_api_apps(&self) -> Vec<api::App> {
 		let inner = self.inner.read().unwrap();
-		inner
+		let mut list: Vec<_> = inner
 			.inner
 			.values()
 			.map(|a| {
 				let inner = &a.inner;
+				let manifest = &inner.manifest;
 				api::App {
 					key: inner.name.to_string(),
-					js_entry: Some(inner.js_entry.to_string())
-						.filter(|j| !j.is_empty()),
-					css_entry: Some(inner.css_entry.to_string())
-						.filter(|c| !c.is_empty()),
+					js_entry: non_empty(inner.js_entry),
+					css_entry: non_empty(inner.css_entry),
+					version: non_empty(manifest.version),
+					display_name: non_empty(manifest.display_name),
+					icon: non_empty(manifest.icon),
+					description: non_empty(manifest.description),
+					priority: manifest.priority,
 				}
 			})
-			.collect()
+			.collect();
+
+		list.sort_by(|a, b| {
+			let a_name = a.display_name.as_ref().unwrap_or(&a.key);
+			let b_name = b.display_name.as_ref().unwrap_or(&b.key);
+			a.priority.cmp(&b.priority).then_with(|| a_name.cmp(b_name))
+		});
+
+		list
 	}
 
+	/// replaces a running instance of the same app, requests which already
+	/// started stay with the old instance
 	fn insert(&self, app: impl Into<App>) {
 		let mut inner = self.inner.write().unwrap();
 		let app = app.into();
-		inner.inner.insert(app.inner.name, app);
+		inner.inner.insert(app.inner.name.to_string(), app);
+	}
+
+	/// does nothing if the app was already replaced by another instance
+	fn remove(&self, name: &str, id: u64) {
+		let mut inner = self.inner.write().unwrap();
+		if inner.inner.get(name).is_some_and(|a| a.inner.id == id) {
+			inner.inner.remove(name);
+		}
+	}
+
+	/// the state of every app library, includes the ones which are not
+	/// running
+	pub fn to_api_files(&self) -> Vec<api::AppFile> {
+		let inner = self.inner.read().unwrap();
+		let mut list: Vec<_> = inner
+			.files
+			.iter()
+			.map(|(file, f)| api::AppFile {
+				file: file.clone(),
+				key: f.name.clone(),
+				state: f.state.clone(),
+			})
+			.collect();
+		list.sort_by(|a, b| a.file.cmp(&b.file));
+
+		list
+	}
+
+	fn set_state(&self, file: &str, name: Option<String>, state: AppState) {
+		let mut inner = self.inner.write().unwrap();
+		inner
+			.files
+			.insert(file.to_string(), FileState { name, state });
 	}
 
-	fn remove(&self, name: &str) {
+	fn remove_state(&self, file: &str) {
 		let mut inner = self.inner.write().unwrap();
-		inner.inner.remove(name);
+		inner.files.remove(file);
+	}
+
+	/// the newest crashes first
+	pub fn to_api_crashes(&self) -> Vec<api::Crash> {
+		let inner = self.inner.read().unwrap();
+		let mut list: Vec<_> =
+			inner.crashes.values().flatten().cloned().collect();
+		list.sort_by_key(|c| Reverse(c.crashed_on));
+
+		list
 	}
+
+	fn add_crash(&self, name: String, file: &str, message: String) {
+		let mut inner = self.inner.write().unwrap();
+		let crashes = inner.crashes.entry(name.clone()).or_default();
+		if crashes.len() >= CRASH_HISTORY {
+			crashes.pop_front();
+		}
+
+		crashes.push_back(api::Crash {
+			key: name,
+			file: file.into(),
+			message,
+			crashed_on: DateTime::now(),
+		});
+	}
+}
+
+fn non_empty(s: &str) -> Option<String> {
+	Some(s.to_string()).filter(|s| !s.is_empty())
 }
 
 struct AppsInner {
-	inner: HashMap<&'static str, App>,
+	/// the library of a replaced app get's unloaded so the name needs to be
+	/// owned
+	inner: HashMap<String, App>,
+	files: HashMap<String, FileState>,
+	/// the oldest crash first, the library of a crashed app get's unloaded
+	/// so the name needs to be owned
+	crashes: HashMap<String, VecDeque<api::Crash>>,
 }
 
 impl AppsInner {
 	fn new() -> Self {
 		Self {
 			inner: HashMap::new(),
+			files: HashMap::new(),
+			crashes: HashMap::new(),
 		}
 	}
 }
 
+struct FileState {
+	/// is only known once the app was initialized
+	///
+	/// owned since the library might already be unloaded
+	name: Option<String>,
+	state: AppState,
+}
+
 #[derive(Clone)]
 pub struct App {
 	inner: Arc<AppInner>,
@@ -157,9 +264,12 @@ impl tower_service::Service<Uri> for App {
 }
 
 struct AppInner {
+	/// differs between instances loaded from the same file
+	id: u64,
 	name: &'static str,
 	js_entry: &'static str,
 	css_entry: &'static str,
+	manifest: Manifest,
 	connector: Connector,
 }
 
@@ -173,23 +283,46 @@ async fn dir_files(path: &str) -> io::Result<Vec<String>> {
 			continue;
 		}
 
-		let name = entry.file_name().into_string().unwrap();
 		let mut path = entry.path();
-		path.push(name);
+		path.push(entry.file_name());
 		path.set_extension(MODULE_EXTENSION);
 
-		v.push(path.into_os_string().into_string().unwrap());
+		match path.into_os_string().into_string() {
+			Ok(path) => v.push(path),
+			Err(path) => eprintln!("ignoring app {path:?} not valid utf8"),
+		}
 	}
 
 	Ok(v)
 }
 
+/// The instance of an app which currently serves requests
 struct AppMetadata {
+	id: u64,
+	name: &'static str,
 	last_modified: SystemTime,
 	inserted: Instant,
 	terminator: Option<Terminator>,
 }
 
+/// An app which could not be loaded
+struct FailedApp {
+	/// `None` if the file could not be read
+	last_modified: Option<SystemTime>,
+	attempts: u32,
+	/// `None` if the app should only be loaded again once the file changes
+	retry_at: Option<Instant>,
+}
+
+/// how long to wait before loading a failed app again, doubles with every
+/// attempt
+fn retry_backoff(attempts: u32) -> Duration {
+	RETRY_MIN
+		.checked_mul(1 << attempts.saturating_sub(1).min(16))
+		.unwrap_or(RETRY_MAX)
+		.min(RETRY_MAX)
+}
+
 pub(crate) fn bg_task(cfg: &AppsConf, data: Resources) -> JoinHandle<()> {
 	let cfg = cfg.clone();
 	tokio::spawn(async move {
@@ -197,64 +330,197 @@ pub(crate) fn bg_task(cfg: &AppsConf, data: Resources) -> JoinHandle<()> {
 		intv.set_missed_tick_behavior(time::MissedTickBehavior::Skip);
 		let apps = data.get::<Apps>().unwrap();
 		let users = data.get::<Users>().unwrap();
+		let audit = data.get::<Audit>().unwrap();
+		let preferences = data.get::<Preferences>().unwrap();
 		let cfg_string = data.get::<crate::ConfigString>().unwrap();
 
 		let mut raw_apps: HashMap<String, AppMetadata> = HashMap::new();
+		let mut failed: HashMap<String, FailedApp> = HashMap::new();
+		// when the apps crashed within the CRASH_WINDOW
+		let mut crashes: HashMap<String, Vec<Instant>> = HashMap::new();
 
 		let mut notifiers = Notifiers::new();
+		let mut next_id = 0;
 
 		loop {
-			let mut files = if let Some(path) = &cfg.dir {
-				dir_files(path).await.unwrap()
-			} else {
-				vec![]
+			let mut files = match &cfg.dir {
+				Some(path) => dir_files(path).await.unwrap_or_else(|e| {
+					eprintln!("could not read apps dir {path:?} {e}");
+					vec![]
+				}),
+				None => vec![],
 			};
 
 			files.extend_from_slice(&cfg.files);
 
-			for file in files {
-				let metadata = fs::metadata(&file).await.unwrap();
-				let modified = metadata.modified().unwrap();
+			// forget about failed apps which are no longer configured
+			failed.retain(|file, _| {
+				let keep = files.contains(file);
+				if !keep {
+					apps.remove_state(file);
+				}
+				keep
+			});
+
+			for file in &files {
+				let modified =
+					fs::metadata(file).await.and_then(|m| m.modified());
+				let last_modified = modified.as_ref().ok().copied();
 
-				if let Some(raw_app) = raw_apps.get_mut(&file) {
-					if raw_app.last_modified == modified {
+				// a running app keeps running if the file was removed
+				let running = raw_apps.get(file);
+				if running.is_some_and(|r| {
+					last_modified.is_none_or(|m| m == r.last_modified)
+				}) {
+					continue;
+				}
+				// the new version get's loaded while the old one still
+				// serves requests
+				let reload = running.is_some();
+
+				// a changed file get's retried immediately
+				let prev = failed
+					.get(file)
+					.filter(|f| f.last_modified == last_modified);
+				if prev.is_some_and(|f| {
+					f.retry_at.is_none_or(|retry_at| Instant::now() < retry_at)
+				}) {
+					continue;
+				}
+				let attempts = prev.map(|f| f.attempts).unwrap_or(0);
+
+				let res = match modified {
+					Ok(modified) => {
+						if !reload {
+							apps.set_state(file, None, AppState::Loading);
+						}
+
+						// initializing an app can take a while
+						let (path, cfg, users, preferences) = (
+							file.clone(),
+							cfg_string.0.clone(),
+							users.clone(),
+							preferences.clone(),
+						);
+						task::spawn_blocking(move || {
+							AppLib::new(&path, &cfg, &users, &preferences)
+						})
+						.await
+						.map_err(|e| format!("loading panicked: {e}"))
+						.and_then(|res| res.map_err(|e| e.to_string()))
+						.map(|lib| (lib, modified))
+					}
+					Err(e) => Err(format!("could not read the file: {e}")),
+				};
+
+				let (lib, modified) = match res {
+					Ok(res) => res,
+					Err(e) if reload => {
+						eprintln!(
+							"failed to reload {file:?} {e}, keeping the old \
+							 version"
+						);
+						audit
+							.log(
+								Event::new(EventKind::AppFailed)
+									.details(format!("{file}: {e}")),
+							)
+							.await;
+
+						// only try again once the file changes
+						if let (Some(running), Some(m)) =
+							(raw_apps.get_mut(file), last_modified)
+						{
+							running.last_modified = m;
+						}
 						continue;
 					}
+					Err(e) => {
+						let attempts = attempts + 1;
+						let backoff = retry_backoff(attempts);
+						eprintln!(
+							"failed to load {file:?} {e}, retrying in {}s",
+							backoff.as_secs()
+						);
+
+						// don't fill the log with retries
+						if attempts == 1 {
+							audit
+								.log(
+									Event::new(EventKind::AppFailed)
+										.details(format!("{file}: {e}")),
+								)
+								.await;
+						}
 
-					// the app already existed but was changed terminate
-					if let Some(terminator) = raw_app.terminator.take() {
-						terminator.terminate();
+						apps.set_state(file, None, AppState::Failed(e));
+						failed.insert(
+							file.clone(),
+							FailedApp {
+								last_modified,
+								attempts,
+								retry_at: Some(Instant::now() + backoff),
+							},
+						);
+						continue;
 					}
+				};
 
-					continue;
-				}
+				failed.remove(file);
 
-				// now create the AppLib
-				let lib = AppLib::new(&file, &cfg_string.0, &users);
+				let id = next_id;
+				next_id += 1;
 
 				eprintln!("enabling {:?} with file {file:?}", lib.name);
-
-				raw_apps.insert(
+				audit
+					.log(
+						Event::new(EventKind::AppLoaded)
+							.details(format!("{} from {file}", lib.name)),
+					)
+					.await;
+
+				let old = raw_apps.insert(
 					file.clone(),
 					AppMetadata {
+						id,
+						name: lib.name,
 						last_modified: modified,
 						inserted: Instant::now(),
 						terminator: Some(lib.terminator),
 					},
 				);
 
+				// switches the routing to the new instance
 				apps.insert(AppInner {
+					id,
 					name: lib.name,
 					js_entry: lib.js_entry,
 					css_entry: lib.css_entry,
+					manifest: lib.manifest,
 					connector: lib.connector,
 				});
+				apps.set_state(
+					file,
+					Some(lib.name.to_string()),
+					AppState::Running,
+				);
 
 				notifiers.push(NotifiedApp {
+					id,
 					name: lib.name,
-					file: file,
+					file: file.clone(),
 					notify: lib.terminated,
+					crash: lib.crash,
 				});
+
+				// the old instance can now finish the requests it is handling
+				if let Some(old) = old {
+					eprintln!("draining {:?} from file {file:?}", old.name);
+					apps.remove(old.name, old.id);
+					if let Some(terminator) = old.terminator {
+						terminator.terminate();
+					}
+				}
 			}
 
 			tokio::select! {
@@ -262,24 +528,95 @@ pub(crate) fn bg_task(cfg: &AppsConf, data: Resources) -> JoinHandle<()> {
 				idx = notifiers.notified(), if !notifiers.is_empty() => {
 					let app = notifiers.get(idx);
 					let state = app.notify.val();
+					// a replaced instance no longer affects the file
+					let current = raw_apps
+						.get(&app.file)
+						.is_some_and(|r| r.id == app.id);
 
 					// Termination sent
 					if state == app_lib::TERMINATING {
 						eprintln!("app {:?} terminating", app.name);
 
 						// remove it from the app list
-						apps.remove(app.name);
+						if current {
+							apps.remove(app.name, app.id);
+							apps.set_state(
+								&app.file,
+								Some(app.name.to_string()),
+								AppState::Terminating,
+							);
+						}
+					// Crashed
+					} else if state == app_lib::CRASHED {
+						let app = notifiers.take(idx);
+						// the library get's unloaded shortly after it crashed
+						let name = app.name.to_string();
+						let msg = app.crash.lock().unwrap().take().unwrap_or_default();
+						eprintln!("app {name:?} crashed {msg}");
+
+						apps.add_crash(name.clone(), &app.file, msg.clone());
+						audit
+							.log(
+								Event::new(EventKind::AppCrashed)
+									.details(format!("{name} from {}: {msg}", app.file)),
+							)
+							.await;
+
+						// a drained instance does not get restarted
+						if current {
+							apps.remove(&name, app.id);
+
+							let recent = crashes.entry(app.file.clone()).or_default();
+							recent.retain(|c| c.elapsed() < CRASH_WINDOW);
+							recent.push(Instant::now());
+
+							// stop restarting an app which crashes right away
+							let (retry_at, error) = if recent.len() < CRASH_LIMIT {
+								let backoff = retry_backoff(recent.len() as u32);
+								(
+									Some(Instant::now() + backoff),
+									format!("crashed, restarting in {}s: {msg}", backoff.as_secs()),
+								)
+							} else {
+								(
+									None,
+									format!("crashed {} times, waiting for a new file: {msg}", recent.len()),
+								)
+							};
+
+							apps.set_state(&app.file, Some(name), AppState::Failed(error));
+							let metadata = raw_apps.remove(&app.file);
+							failed.insert(
+								app.file,
+								FailedApp {
+									last_modified: metadata.map(|m| m.last_modified),
+									attempts: 0,
+									retry_at,
+								},
+							);
+						}
 					// Terminated
 					} else if state >= app_lib::TERMINATED {
 						eprintln!("app {:?} terminated", app.name);
 						let app = notifiers.take(idx);
 
-						apps.remove(app.name);
-
-						let metadata = raw_apps.remove(&app.file).unwrap();
-						if metadata.inserted.elapsed() < MIN_RUNTIME {
-							// todo this blocks the entire app finding "process"
-							time::sleep(Duration::from_secs(2)).await;
+						audit
+							.log(
+								Event::new(EventKind::AppUnloaded)
+									.details(format!("{} from {}", app.name, app.file)),
+							)
+							.await;
+
+						if current {
+							apps.remove(app.name, app.id);
+							// it get's loaded again on the next tick
+							apps.remove_state(&app.file);
+
+							let inserted = raw_apps.remove(&app.file).map(|m| m.inserted);
+							if inserted.is_some_and(|i| i.elapsed() < MIN_RUNTIME) {
+								// todo this blocks the entire app finding "process"
+								time::sleep(Duration::from_secs(2)).await;
+							}
 						}
 					}
 				}
@@ -289,9 +626,11 @@ pub(crate) fn bg_task(cfg: &AppsConf, data: Resources) -> JoinHandle<()> {
 }
 
 struct NotifiedApp {
+	pub id: u64,
 	pub name: &'static str,
 	pub file: String,
 	pub notify: prog::Receiver,
+	pub crash: CrashMsg,
 }
 
 struct Notifiers {
@@ -364,6 +703,15 @@ impl Unpin for Notified<'_> {}
 mod tests {
 	use super::*;
 
+	#[test]
+	fn backoff() {
+		assert_eq!(retry_backoff(1), RETRY_MIN);
+		assert_eq!(retry_backoff(2), RETRY_MIN * 2);
+		assert_eq!(retry_backoff(4), RETRY_MIN * 8);
+		assert_eq!(retry_backoff(10), RETRY_MAX);
+		assert_eq!(retry_backoff(u32::MAX), RETRY_MAX);
+	}
+
 	#[test]
 	fn test_notifiers() {
 		let rt = tokio::runtime::Builder::new_current_thread()
@@ -375,9 +723,11 @@ mod tests {
 			let (tx, rx) = prog::channel(0);
 
 			let app = NotifiedApp {
+				id: 0,
 				name: "hey",
 				file: "hey".into(),
 				notify: rx.clone(),
+				crash: CrashMsg::default(),
 			};
 
 			let mut notifiers = Notifiers::new();
@@ -0,0 +1,43 @@
+use super::{Event, EventKind};
+use crate::api::Error;
+
+use serde::{Deserialize, Serialize};
+
+use chuchi_postgres::time::DateTime;
+use chuchi_postgres::UniqueId;
+
+use chuchi::api::{Method, Request};
+
+/// all filters are optional
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EventsReq {
+	pub user_id: Option<UniqueId>,
+	pub kind: Option<EventKind>,
+	/// inclusive
+	pub from: Option<DateTime>,
+	/// exclusive
+	pub to: Option<DateTime>,
+	#[serde(default)]
+	pub offset: usize,
+	/// defaults to 50, at most 500
+	pub limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Events {
+	/// the newest events first
+	pub list: Vec<Event>,
+	/// if there are more events after this page
+	pub more: bool,
+}
+
+impl Request for EventsReq {
+	type Response = Events;
+	type Error = Error;
+
+	const PATH: &'static str = "/api/audit/events";
+	const METHOD: Method = Method::GET;
+	const HEADERS: &'static [&'static str] = &["auth-token"];
+}
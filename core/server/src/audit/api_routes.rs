@@ -0,0 +1,46 @@
+use super::api::{Events, EventsReq};
+use super::{Audit, Query};
+use crate::api::Result;
+use crate::users::api_routes::root_from_req;
+use crate::users::db::Users;
+
+use chuchi::header::RequestHeader;
+use chuchi::Chuchi;
+
+use chuchi::api;
+
+const DEFAULT_LIMIT: usize = 50;
+const MAX_LIMIT: usize = 500;
+
+#[api(EventsReq)]
+async fn events(
+	req: EventsReq,
+	header: &RequestHeader,
+	users: &Users,
+	audit: &Audit,
+) -> Result<Events> {
+	root_from_req(header, users).await?;
+
+	let limit = req.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+
+	// request one more to know if there is another page
+	let mut list = audit
+		.query(&Query {
+			user_id: req.user_id,
+			kind: req.kind,
+			from: req.from,
+			to: req.to,
+			offset: req.offset,
+			limit: limit + 1,
+		})
+		.await?;
+
+	let more = list.len() > limit;
+	list.truncate(limit);
+
+	Ok(Events { list, more })
+}
+
+pub fn add_routes(server: &mut Chuchi) {
+	server.add_route(events);
+}
@@ -0,0 +1,12 @@
+CREATE TABLE audit_log (
+    id TEXT PRIMARY KEY,
+    created_on TIMESTAMP NOT NULL,
+    kind TEXT NOT NULL,
+    user_id TEXT,
+    username TEXT,
+    ip TEXT,
+    details TEXT
+);
+CREATE INDEX idx_audit_log_created_on ON audit_log (created_on);
+CREATE INDEX idx_audit_log_user_id ON audit_log (user_id);
+CREATE INDEX idx_audit_log_kind ON audit_log (kind);
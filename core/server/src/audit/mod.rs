@@ -0,0 +1,317 @@
+//! An append only log of security relevant events
+//!
+//! The log never references other tables, entries stay even if the user
+//! get's deleted.
+
+pub mod api;
+pub mod api_routes;
+
+use crate::users::proxy::client_ip;
+use crate::users::User;
+
+use std::fmt;
+use std::str::FromStr;
+
+use chuchi::header::RequestHeader;
+use chuchi::resources::Resources;
+use chuchi::Resource;
+use chuchi_postgres::filter::{
+	Filter, Operator, Param, WhereOperation, WherePart,
+};
+use chuchi_postgres::table::TableOwned;
+use chuchi_postgres::time::DateTime;
+use chuchi_postgres::UniqueId;
+use chuchi_postgres::{whr, Database, FromRow, Result, TableTempl, ToRow};
+
+use core_lib::migration_files;
+
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinHandle;
+use tokio::time::{self, Duration};
+
+use tracing::error;
+
+const MIGRATIONS: &[(&str, &str)] = migration_files!("core-audit-create");
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct AuditConf {
+	/// days after which entries get deleted, 0 keeps them forever
+	pub retention: u64,
+}
+
+impl Default for AuditConf {
+	fn default() -> Self {
+		Self { retention: 365 }
+	}
+}
+
+macro_rules! event_kinds {
+	($($kind:ident => $s:literal),* $(,)?) => {
+		#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+		#[serde(rename_all = "camelCase")]
+		pub enum EventKind {
+			$($kind),*
+		}
+
+		impl EventKind {
+			pub fn as_str(&self) -> &'static str {
+				match self {
+					$(Self::$kind => $s),*
+				}
+			}
+		}
+
+		impl FromStr for EventKind {
+			type Err = ();
+
+			fn from_str(s: &str) -> std::result::Result<Self, ()> {
+				match s {
+					$($s => Ok(Self::$kind),)*
+					_ => Err(()),
+				}
+			}
+		}
+	};
+}
+
+event_kinds! {
+	Login => "login",
+	LoginFailed => "loginFailed",
+	Logout => "logout",
+	SessionRenewed => "sessionRenewed",
+	Reauthenticated => "reauthenticated",
+	PasswordChanged => "passwordChanged",
+	TotpEnabled => "totpEnabled",
+	TotpDisabled => "totpDisabled",
+	ApiTokenCreated => "apiTokenCreated",
+	ApiTokenDeleted => "apiTokenDeleted",
+	UserCreated => "userCreated",
+	UserEdited => "userEdited",
+	UserDeleted => "userDeleted",
+	PasswordReset => "passwordReset",
+	LockoutCleared => "lockoutCleared",
+	InviteCreated => "inviteCreated",
+	InviteDeleted => "inviteDeleted",
+	InviteAccepted => "inviteAccepted",
+	ImpersonationStarted => "impersonationStarted",
+	ImpersonationEnded => "impersonationEnded",
+	GroupCreated => "groupCreated",
+	GroupEdited => "groupEdited",
+	GroupDeleted => "groupDeleted",
+	GroupMemberAdded => "groupMemberAdded",
+	GroupMemberRemoved => "groupMemberRemoved",
+	AppLoaded => "appLoaded",
+	AppUnloaded => "appUnloaded",
+	AppFailed => "appFailed",
+	AppCrashed => "appCrashed",
+}
+
+impl fmt::Display for EventKind {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str(self.as_str())
+	}
+}
+
+/// An entry of the audit log
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Event {
+	pub id: UniqueId,
+	pub created_on: DateTime,
+	pub kind: EventKind,
+	/// the user which did something or to whom something happened
+	pub user_id: Option<UniqueId>,
+	/// the username at the time of the event
+	pub username: Option<String>,
+	pub ip: Option<String>,
+	pub details: Option<String>,
+}
+
+impl Event {
+	pub fn new(kind: EventKind) -> Self {
+		Self {
+			id: UniqueId::new(),
+			created_on: DateTime::now(),
+			kind,
+			user_id: None,
+			username: None,
+			ip: None,
+			details: None,
+		}
+	}
+
+	pub fn user(mut self, user: &User) -> Self {
+		self.user_id = Some(user.id);
+		self.username = Some(user.username.clone());
+		self
+	}
+
+	pub fn username(mut self, username: impl Into<String>) -> Self {
+		self.username = Some(username.into());
+		self
+	}
+
+	pub fn header(mut self, header: &RequestHeader) -> Self {
+		self.ip = Some(client_ip(header).to_string());
+		self
+	}
+
+	pub fn details(mut self, details: impl Into<String>) -> Self {
+		self.details = Some(details.into());
+		self
+	}
+}
+
+/// An event like it is stored in the database
+#[derive(Debug, TableTempl, FromRow, ToRow)]
+struct DbEvent {
+	#[index(primary)]
+	id: UniqueId,
+	#[index(index)]
+	created_on: DateTime,
+	#[index(index)]
+	kind: String,
+	#[index(index)]
+	user_id: Option<UniqueId>,
+	username: Option<String>,
+	ip: Option<String>,
+	details: Option<String>,
+}
+
+impl DbEvent {
+	fn from_event(ev: Event) -> Self {
+		Self {
+			id: ev.id,
+			created_on: ev.created_on,
+			kind: ev.kind.as_str().into(),
+			user_id: ev.user_id,
+			username: ev.username,
+			ip: ev.ip,
+			details: ev.details,
+		}
+	}
+
+	fn into_event(self) -> Option<Event> {
+		Some(Event {
+			id: self.id,
+			created_on: self.created_on,
+			kind: self.kind.parse().ok()?,
+			user_id: self.user_id,
+			username: self.username,
+			ip: self.ip,
+			details: self.details,
+		})
+	}
+}
+
+/// Filters for [`Audit::query`], all filters are optional
+#[derive(Debug, Clone, Default)]
+pub struct Query {
+	pub user_id: Option<UniqueId>,
+	pub kind: Option<EventKind>,
+	pub from: Option<DateTime>,
+	pub to: Option<DateTime>,
+	pub offset: usize,
+	pub limit: usize,
+}
+
+#[derive(Debug, Clone, Resource)]
+pub struct Audit {
+	table: TableOwned<DbEvent>,
+}
+
+impl Audit {
+	/// ## Panics
+	/// if the migrations fail
+	pub async fn new(db: &Database) -> Self {
+		let migrations = db.migrations();
+		let mut conn = db.get().await.unwrap();
+
+		for (name, sql) in MIGRATIONS {
+			migrations
+				.add(&mut conn, name, sql)
+				.await
+				.expect("failed to run migration");
+		}
+
+		Self {
+			table: db.table_owned("audit_log"),
+		}
+	}
+
+	/// stores the event, failing to store it only get's logged since it
+	/// should never abort the action which caused it
+	pub async fn log(&self, event: Event) {
+		let kind = event.kind;
+		if let Err(e) = self.table.insert_one(&DbEvent::from_event(event)).await
+		{
+			error!("failed to store audit event {kind}: {e}");
+		}
+	}
+
+	/// returns the newest events first
+	pub async fn query(&self, query: &Query) -> Result<Vec<Event>> {
+		let kind = query.kind.map(|k| k.as_str());
+
+		let mut filter = Filter::new();
+		let mut push = |column: &'static str, kind, param| {
+			if !filter.params.is_empty() {
+				filter.whr.push(WherePart::And);
+			}
+			filter.whr.push(WhereOperation {
+				kind,
+				column: column.into(),
+			});
+			filter.params.push(param);
+		};
+
+		if let Some(user_id) = &query.user_id {
+			push("user_id", Operator::Eq, Param::new("user_id", user_id));
+		}
+		if let Some(kind) = &kind {
+			push("kind", Operator::Eq, Param::new("kind", kind));
+		}
+		if let Some(from) = &query.from {
+			push("created_on", Operator::Gte, Param::new("created_on", from));
+		}
+		if let Some(to) = &query.to {
+			push("created_on", Operator::Lt, Param::new("created_on", to));
+		}
+
+		filter.order_by.push_desc("created_on");
+		filter.limit.set_fixed(query.limit);
+		filter.offset.set_fixed(query.offset);
+
+		let events = self.table.find_many(filter).await?;
+
+		Ok(events.into_iter().filter_map(DbEvent::into_event).collect())
+	}
+
+	/// deletes all events older than the retention
+	pub async fn cleanup(&self, cfg: &AuditConf) -> Result<()> {
+		if cfg.retention == 0 {
+			return Ok(());
+		}
+
+		let before =
+			DateTime::now() - Duration::from_secs(cfg.retention * 24 * 60 * 60);
+		self.table.delete(whr!("created_on" < &before)).await
+	}
+}
+
+pub(crate) fn bg_task(cfg: &AuditConf, data: Resources) -> JoinHandle<()> {
+	let cfg = cfg.clone();
+	tokio::spawn(async move {
+		let mut intv = time::interval(Duration::from_secs(60 * 60));
+		let audit = data.get::<Audit>().unwrap();
+
+		loop {
+			intv.tick().await;
+
+			if let Err(e) = audit.cleanup(&cfg).await {
+				error!("audit log cleanup failed {e}");
+			}
+		}
+	})
+}
@@ -0,0 +1,276 @@
+//! User management subcommands
+//!
+//! They work directly against the database, which allows to recover an
+//! install where no root can login anymore. Passwords are asked for on the
+//! terminal or read from the first line of stdin if not given as an argument.
+
+use std::io::{self, IsTerminal};
+
+use crate::users::db::Users;
+use crate::users::{Rights, User};
+
+use clap::{Parser, ValueEnum};
+
+#[derive(Debug, Parser)]
+pub enum SubCommand {
+	CreateUser(CreateUser),
+	ListUsers(ListUsers),
+	/// deletes the user and all of their sessions
+	DeleteUser(UserArg),
+	/// sets a new password and revokes all sessions of the user
+	SetPassword(SetPassword),
+	SetRoot(UserArg),
+	UnsetRoot(UserArg),
+	/// changes the username and/or the display name
+	RenameUser(RenameUser),
+	/// revokes all sessions of the user
+	RevokeSessions(UserArg),
+}
+
+#[derive(Debug, Parser)]
+pub struct CreateUser {
+	username: String,
+	name: String,
+	/// if missing the password is asked for, prefer that since arguments
+	/// might end up in the shell history
+	password: Option<String>,
+	#[clap(long)]
+	root: bool,
+	/// only allow access to this app, can be repeated
+	///
+	/// if no app is given the user can access all apps
+	#[clap(long = "app")]
+	apps: Vec<String>,
+	#[clap(flatten)]
+	output: Output,
+}
+
+#[derive(Debug, Parser)]
+pub struct ListUsers {
+	#[clap(flatten)]
+	output: Output,
+}
+
+#[derive(Debug, Parser)]
+pub struct UserArg {
+	username: String,
+	#[clap(flatten)]
+	output: Output,
+}
+
+#[derive(Debug, Parser)]
+pub struct SetPassword {
+	username: String,
+}
+
+#[derive(Debug, Parser)]
+pub struct RenameUser {
+	username: String,
+	/// the new username
+	#[clap(long)]
+	new_username: Option<String>,
+	/// the new display name
+	#[clap(long)]
+	name: Option<String>,
+	#[clap(flatten)]
+	output: Output,
+}
+
+#[derive(Debug, Parser)]
+struct Output {
+	#[clap(long, value_enum, default_value_t = Format::Table)]
+	format: Format,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Format {
+	Table,
+	Json,
+}
+
+impl Output {
+	fn print(&self, users: &[User]) {
+		match self.format {
+			Format::Table => print_table(users),
+			Format::Json => println!(
+				"{}",
+				serde_json::to_string_pretty(users)
+					.expect("failed to serialize users")
+			),
+		}
+	}
+}
+
+fn print_table(users: &[User]) {
+	let header = ["id", "username", "name", "root", "apps"]
+		.map(ToString::to_string)
+		.to_vec();
+
+	let rows: Vec<Vec<String>> = users
+		.iter()
+		.map(|u| {
+			vec![
+				u.id.to_string(),
+				u.username.clone(),
+				u.name.clone(),
+				u.rights.root.to_string(),
+				match &u.rights.apps {
+					Some(apps) => apps.join(","),
+					None => "all".into(),
+				},
+			]
+		})
+		.collect();
+
+	let mut widths: Vec<_> = header.iter().map(|h| h.len()).collect();
+	for row in &rows {
+		for (w, col) in widths.iter_mut().zip(row) {
+			*w = (*w).max(col.chars().count());
+		}
+	}
+
+	for row in [header].iter().chain(&rows) {
+		let line: Vec<_> = row
+			.iter()
+			.zip(&widths)
+			.map(|(col, w)| format!("{col:w$}"))
+			.collect();
+		println!("{}", line.join("  ").trim_end());
+	}
+}
+
+fn exit(msg: impl AsRef<str>) -> ! {
+	eprintln!("{}", msg.as_ref());
+	std::process::exit(1);
+}
+
+/// usernames are stored lowercase like the api does
+fn normalize_username(username: &str) -> String {
+	let username = username.trim().to_lowercase();
+	if username.is_empty() {
+		exit("username cannot be empty");
+	}
+
+	username
+}
+
+async fn check_username(users: &Users, username: &str) -> String {
+	let username = normalize_username(username);
+	if users.by_username(&username).await.unwrap().is_some() {
+		exit(format!("username {username} is already taken"));
+	}
+
+	username
+}
+
+/// reads the password if none was given and checks it against the policy
+fn read_password(
+	users: &Users,
+	username: &str,
+	given: Option<String>,
+) -> String {
+	let password = if let Some(password) = given {
+		Ok(password)
+	} else if io::stdin().is_terminal() {
+		rpassword::prompt_password("password: ")
+	} else {
+		let mut line = String::new();
+		io::stdin()
+			.read_line(&mut line)
+			.map(|_| line.trim_end_matches(['\r', '\n']).to_string())
+	}
+	.expect("failed to read password");
+
+	if let Err(e) = users.check_policy(username, &password) {
+		exit(format!("password not allowed {e:?}"));
+	}
+
+	password
+}
+
+async fn user_by_username(users: &Users, username: &str) -> User {
+	let username = normalize_username(username);
+	match users.by_username(&username).await.unwrap() {
+		Some(user) => user,
+		None => exit(format!("user {username} not found")),
+	}
+}
+
+pub async fn run(subcmd: SubCommand, users: &Users) {
+	match subcmd {
+		SubCommand::CreateUser(create_user) => {
+			let rights = Rights {
+				root: create_user.root,
+				apps: Some(create_user.apps).filter(|a| !a.is_empty()),
+			};
+
+			let username = check_username(users, &create_user.username).await;
+			if create_user.name.trim().is_empty() {
+				exit("name cannot be empty");
+			}
+			let password =
+				read_password(users, &username, create_user.password);
+
+			let user = users
+				.insert(username, create_user.name, password, rights)
+				.await
+				.unwrap();
+			create_user.output.print(&[user]);
+		}
+		SubCommand::ListUsers(list) => {
+			let mut all = users.all().await.unwrap();
+			all.sort_by(|a, b| a.username.cmp(&b.username));
+			list.output.print(&all);
+		}
+		SubCommand::DeleteUser(arg) => {
+			let user = user_by_username(users, &arg.username).await;
+			users.delete(&user.id).await.unwrap();
+			users.sessions_notify().await.unwrap();
+			arg.output.print(&[user]);
+		}
+		SubCommand::SetPassword(set) => {
+			let user = user_by_username(users, &set.username).await;
+			let password = read_password(users, &user.username, None);
+			users.set_password(&user.id, &password).await.unwrap();
+			// the old password might be known by someone else
+			users.sessions_remove_by_user(&user.id).await.unwrap();
+			users.sessions_notify().await.unwrap();
+			println!("password of {} changed", user.username);
+		}
+		SubCommand::SetRoot(arg) => set_root(users, arg, true).await,
+		SubCommand::UnsetRoot(arg) => set_root(users, arg, false).await,
+		SubCommand::RenameUser(rename) => {
+			let mut user = user_by_username(users, &rename.username).await;
+
+			if let Some(username) = rename.new_username {
+				user.username = check_username(users, &username).await;
+			}
+			if let Some(name) = rename.name {
+				user.name = name;
+			}
+
+			users
+				.edit(&user.id, &user.username, &user.name, user.rights.clone())
+				.await
+				.unwrap();
+			rename.output.print(&[user]);
+		}
+		SubCommand::RevokeSessions(arg) => {
+			let user = user_by_username(users, &arg.username).await;
+			users.sessions_remove_by_user(&user.id).await.unwrap();
+			users.sessions_notify().await.unwrap();
+			arg.output.print(&[user]);
+		}
+	}
+}
+
+async fn set_root(users: &Users, arg: UserArg, root: bool) {
+	let mut user = user_by_username(users, &arg.username).await;
+	user.rights.root = root;
+
+	users
+		.edit(&user.id, &user.username, &user.name, user.rights.clone())
+		.await
+		.unwrap();
+	arg.output.print(&[user]);
+}
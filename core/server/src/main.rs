@@ -1,8 +1,11 @@
 mod api;
 mod apps;
+mod audit;
+mod cli;
 mod cors;
 #[cfg(not(debug_assertions))]
 mod index;
+mod preferences;
 mod tempfile;
 mod users;
 
@@ -10,8 +13,8 @@ mod assets {
 	include!(concat!(env!("OUT_DIR"), "/assets_routes.rs"));
 }
 
+use cli::SubCommand;
 use users::db::Users;
-use users::Rights;
 
 use tokio::fs;
 
@@ -31,26 +34,16 @@ struct Args {
 	config: String,
 }
 
-#[derive(Debug, Parser)]
-enum SubCommand {
-	CreateUser(CreateUser),
-}
-
-#[derive(Debug, Parser)]
-struct CreateUser {
-	username: String,
-	name: String,
-	password: String,
-	#[clap(long)]
-	root: bool,
-}
-
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct Config {
 	#[serde(rename = "listen-on")]
 	listen_on: String,
 	database: DbConf,
 	apps: apps::AppsConf,
+	#[serde(default)]
+	users: users::UsersConf,
+	#[serde(default)]
+	audit: audit::AuditConf,
 }
 
 struct ConfigString(String);
@@ -82,36 +75,39 @@ async fn main() {
 	.await
 	.expect("failed to connect to database");
 
-	let users = Users::new(&db).await;
-
-	match args.subcmd {
-		Some(SubCommand::CreateUser(create_user)) => {
-			let rights = Rights {
-				root: create_user.root,
-			};
-
-			let user = users
-				.insert(
-					create_user.username,
-					create_user.name,
-					create_user.password,
-					rights,
-				)
-				.await
-				.unwrap();
-			println!("created user {user:?}");
-			return;
-		}
-		None => {}
+	let users = Users::new(&db, &cfg.users).await;
+
+	if let Some(subcmd) = args.subcmd {
+		cli::run(subcmd, &users).await;
+		return;
 	}
 
 	let mut server = chuchi::build(&cfg.listen_on).await.unwrap();
 
 	server.add_resource(users);
+	server.add_resource(audit::Audit::new(&db).await);
+	server.add_resource(preferences::Preferences::new(&db).await);
+	server.add_resource(users::totp::Totp::new());
+	server.add_resource(users::oidc::Oidc::new(cfg.users.oidc.clone()));
+	// the address of the client is resolved everywhere, not only when
+	// logging in through the proxy
+	users::proxy::set_trusted_proxies(
+		cfg.users
+			.proxy
+			.as_ref()
+			.map(|p| p.trusted_proxies.clone())
+			.unwrap_or_default(),
+	);
+	server.add_resource(users::proxy::Proxy::new(cfg.users.proxy.clone()));
+	server.add_resource(users::limiter::LoginLimiter::new(
+		cfg.users.login_limits.clone(),
+	));
 	server.add_resource(apps::Apps::new());
 	server.add_resource(cfg_string);
 	assets::add_routes(&mut server);
 	users::api_routes::add_routes(&mut server);
+	audit::api_routes::add_routes(&mut server);
+	preferences::api_routes::add_routes(&mut server);
 	server.add_raw_route(apps::route::AppsApiRoute);
 	server.add_raw_route(apps::route::AppsAssetsRoute);
 	apps::api_routes::add_routes(&mut server);
@@ -125,6 +121,8 @@ async fn main() {
 
 	tokio::try_join!(
 		users::bg_task(data.clone()),
+		users::listen_task(&cfg.database, data.clone()),
+		audit::bg_task(&cfg.audit, data.clone()),
 		apps::bg_task(&cfg.apps, data.clone()),
 		tokio::spawn(async move {
 			server.run().await.unwrap();
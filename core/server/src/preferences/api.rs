@@ -0,0 +1,56 @@
+use crate::api::Error;
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use chuchi::api::{Method, Request};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PreferencesReq {}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AppPreferences {
+	pub values: BTreeMap<String, Value>,
+}
+
+impl Request for PreferencesReq {
+	type Response = AppPreferences;
+	type Error = Error;
+
+	const PATH: &'static str = "/api/preferences/{app}";
+	const METHOD: Method = Method::GET;
+	const HEADERS: &'static [&'static str] = &["auth-token"];
+}
+
+/// creates or overrides the preference
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SetPreferenceReq {
+	pub value: Value,
+}
+
+impl Request for SetPreferenceReq {
+	type Response = ();
+	type Error = Error;
+
+	const PATH: &'static str = "/api/preferences/{app}/{key}";
+	const METHOD: Method = Method::POST;
+	const HEADERS: &'static [&'static str] = &["auth-token"];
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeletePreferenceReq;
+
+impl Request for DeletePreferenceReq {
+	type Response = ();
+	type Error = Error;
+
+	const PATH: &'static str = "/api/preferences/{app}/{key}";
+	const METHOD: Method = Method::DELETE;
+	const HEADERS: &'static [&'static str] = &["auth-token"];
+}
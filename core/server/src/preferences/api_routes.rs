@@ -0,0 +1,88 @@
+use super::api::{
+	AppPreferences, DeletePreferenceReq, PreferencesReq, SetPreferenceReq,
+};
+use super::{Preferences, CORE_APP};
+use crate::api::{Error, Result};
+use crate::users::api_routes::{own_sess_user_from_req, sess_user_from_req};
+use crate::users::db::Users;
+use crate::users::User;
+
+use chuchi::extractor::PathParam;
+use chuchi::header::RequestHeader;
+use chuchi::Chuchi;
+
+use chuchi::api;
+
+/// root can't change preferences while impersonating a user
+async fn user_with_app(
+	app: &str,
+	write: bool,
+	header: &RequestHeader,
+	users: &Users,
+) -> Result<User> {
+	let (_, user) = if write {
+		own_sess_user_from_req(header, users).await?
+	} else {
+		sess_user_from_req(header, users).await?
+	};
+
+	if app != CORE_APP && !user.rights.has_app(app) {
+		return Err(Error::MissingRights);
+	}
+
+	Ok(user)
+}
+
+#[api(PreferencesReq)]
+async fn preferences(
+	app: &PathParam<str>,
+	header: &RequestHeader,
+	users: &Users,
+	prefs: &Preferences,
+) -> Result<AppPreferences> {
+	let user = user_with_app(app, false, header, users).await?;
+
+	let values = prefs.all(app, &user.id).await?;
+
+	Ok(AppPreferences { values })
+}
+
+#[api(SetPreferenceReq)]
+async fn set_preference(
+	app: &PathParam<str>,
+	key: &PathParam<str>,
+	req: SetPreferenceReq,
+	header: &RequestHeader,
+	users: &Users,
+	prefs: &Preferences,
+) -> Result<()> {
+	let user = user_with_app(app, true, header, users).await?;
+
+	prefs
+		.set(app, &user.id, key, req.value)
+		.await
+		.map_err(Into::into)
+}
+
+#[api(DeletePreferenceReq)]
+async fn delete_preference(
+	app: &PathParam<str>,
+	key: &PathParam<str>,
+	header: &RequestHeader,
+	users: &Users,
+	prefs: &Preferences,
+) -> Result<()> {
+	let user = user_with_app(app, true, header, users).await?;
+
+	if !prefs.remove(app, &user.id, key).await? {
+		return Err(Error::NotFound);
+	}
+
+	Ok(())
+}
+
+pub fn add_routes(server: &mut Chuchi) {
+	server.add_route(preferences);
+	server.add_route(set_preference);
+	server.add_route(delete_preference);
+}
@@ -0,0 +1,9 @@
+CREATE TABLE preferences (
+    id TEXT PRIMARY KEY,
+    app TEXT NOT NULL,
+    user_id TEXT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    value JSON NOT NULL,
+    updated_on TIMESTAMP NOT NULL,
+    UNIQUE (app, user_id, name)
+);
@@ -0,0 +1,394 @@
+//! Small per-user settings of the apps
+//!
+//! Every app get's it's own namespace, the core ui uses [`CORE_APP`]. Apps
+//! access them via `core_lib::preferences`.
+
+pub mod api;
+pub mod api_routes;
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::{Arc, OnceLock};
+
+use chuchi::Resource;
+use chuchi_postgres::json::Json;
+use chuchi_postgres::table::TableOwned;
+use chuchi_postgres::time::DateTime;
+use chuchi_postgres::UniqueId;
+use chuchi_postgres::{filter, Database, FromRow, TableTempl, ToRow};
+
+use core_lib::ffi;
+use core_lib::migration_files;
+
+use serde_json::Value;
+use tokio::runtime::Handle;
+
+const MIGRATIONS: &[(&str, &str)] = migration_files!("core-preferences-create");
+
+/// the namespace of the core ui
+pub const CORE_APP: &str = "core";
+
+pub const MAX_KEY_LEN: usize = 64;
+/// of the json encoded value in bytes
+pub const MAX_VALUE_LEN: usize = 16 * 1024;
+
+#[derive(Debug)]
+pub enum PreferenceError {
+	Database(chuchi_postgres::Error),
+	/// keys need to be at most [`MAX_KEY_LEN`] long and only contain ascii
+	/// letters, digits, `-`, `_` or `.`
+	InvalidKey,
+	/// contains the maximum size in bytes
+	TooLarge(usize),
+	/// the value is not valid json
+	InvalidValue,
+	/// the app was not yet initialized by core
+	AppLoading,
+}
+
+impl fmt::Display for PreferenceError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		fmt::Debug::fmt(self, f)
+	}
+}
+
+impl std::error::Error for PreferenceError {}
+
+impl From<chuchi_postgres::Error> for PreferenceError {
+	fn from(e: chuchi_postgres::Error) -> Self {
+		Self::Database(e)
+	}
+}
+
+type Result<T> = std::result::Result<T, PreferenceError>;
+
+pub fn valid_key(key: &str) -> bool {
+	!key.is_empty()
+		&& key.len() <= MAX_KEY_LEN
+		&& key.bytes().all(|b| {
+			b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.')
+		})
+}
+
+#[derive(Debug, TableTempl, FromRow, ToRow)]
+struct DbPreference {
+	#[index(primary)]
+	id: UniqueId,
+	app: String,
+	#[index(index)]
+	user_id: UniqueId,
+	name: String,
+	value: Json<Value>,
+	updated_on: DateTime,
+}
+
+#[derive(Debug, Clone, Resource)]
+pub struct Preferences {
+	table: TableOwned<DbPreference>,
+}
+
+impl Preferences {
+	/// ## Panics
+	/// if the migrations fail
+	pub async fn new(db: &Database) -> Self {
+		let migrations = db.migrations();
+		let mut conn = db.get().await.unwrap();
+
+		for (name, sql) in MIGRATIONS {
+			migrations
+				.add(&mut conn, name, sql)
+				.await
+				.expect("failed to run migration");
+		}
+
+		Self {
+			table: db.table_owned("preferences"),
+		}
+	}
+
+	/// returns all preferences of the user for this app
+	pub async fn all(
+		&self,
+		app: &str,
+		user_id: &UniqueId,
+	) -> Result<BTreeMap<String, Value>> {
+		let list = self.table.find_many(filter!(&app AND user_id)).await?;
+
+		Ok(list.into_iter().map(|p| (p.name, p.value.0)).collect())
+	}
+
+	pub async fn get(
+		&self,
+		app: &str,
+		user_id: &UniqueId,
+		key: &str,
+	) -> Result<Option<Value>> {
+		let name = key;
+		let pref = self
+			.table
+			.find_one(filter!(&app AND user_id AND &name))
+			.await?;
+
+		Ok(pref.map(|p| p.value.0))
+	}
+
+	pub async fn set(
+		&self,
+		app: &str,
+		user_id: &UniqueId,
+		key: &str,
+		value: Value,
+	) -> Result<()> {
+		if !valid_key(key) {
+			return Err(PreferenceError::InvalidKey);
+		}
+
+		let len = serde_json::to_string(&value)
+			.map_err(|_| PreferenceError::InvalidValue)?
+			.len();
+		if len > MAX_VALUE_LEN {
+			return Err(PreferenceError::TooLarge(MAX_VALUE_LEN));
+		}
+
+		let conn = self.table.get_connection().await?;
+		conn.connection()
+			.execute(
+				"INSERT INTO preferences \
+				(id, app, user_id, name, value, updated_on) \
+				VALUES ($1, $2, $3, $4, $5, $6) \
+				ON CONFLICT (app, user_id, name) \
+				DO UPDATE SET value = $5, updated_on = $6",
+				&[
+					&UniqueId::new(),
+					&app,
+					user_id,
+					&key,
+					&Json(value),
+					&DateTime::now(),
+				],
+			)
+			.await?;
+
+		Ok(())
+	}
+
+	/// returns false if the preference did not exist
+	pub async fn remove(
+		&self,
+		app: &str,
+		user_id: &UniqueId,
+		key: &str,
+	) -> Result<bool> {
+		let conn = self.table.get_connection().await?;
+		let deleted = conn
+			.connection()
+			.execute(
+				"DELETE FROM preferences \
+				WHERE app = $1 AND user_id = $2 AND name = $3",
+				&[&app, user_id, &key],
+			)
+			.await?;
+
+		Ok(deleted > 0)
+	}
+
+	/// the calls get executed on the current runtime, they use the
+	/// namespace of `app` which get's set once the app is initialized
+	pub fn to_c(&self, app: Arc<OnceLock<String>>) -> ffi::c_preferences {
+		let ctx = Box::new(FfiCtx {
+			preferences: self.clone(),
+			handle: Handle::current(),
+			app,
+		});
+
+		// the app passed by the app is ignored, else it could access the
+		// preferences of every other app
+
+		extern "C" fn get(
+			ctx: *const u8,
+			_app: ffi::c_str,
+			user_id: ffi::c_uid,
+			key: ffi::c_str,
+			done: ffi::c_preference_done,
+		) {
+			c_get(ctx, user_id, key, done)
+		}
+
+		extern "C" fn set(
+			ctx: *const u8,
+			_app: ffi::c_str,
+			user_id: ffi::c_uid,
+			key: ffi::c_str,
+			value: ffi::c_str,
+			done: ffi::c_preference_done,
+		) {
+			c_set(ctx, user_id, key, value, done)
+		}
+
+		extern "C" fn remove(
+			ctx: *const u8,
+			_app: ffi::c_str,
+			user_id: ffi::c_uid,
+			key: ffi::c_str,
+			done: ffi::c_preference_done,
+		) {
+			c_remove(ctx, user_id, key, done)
+		}
+
+		extern "C" fn free(ctx: *const u8) {
+			drop(unsafe { Box::from_raw(ctx as *mut FfiCtx) });
+		}
+
+		ffi::c_preferences {
+			ctx: Box::into_raw(ctx) as *const u8,
+			get,
+			set,
+			remove,
+			free,
+		}
+	}
+
+	/// the calls get called with the ctx of [`Self::to_c`]
+	pub fn to_c_v2() -> ffi::c_preferences_v2 {
+		ffi::c_preferences_v2 {
+			get: c_get,
+			set: c_set,
+			remove: c_remove,
+		}
+	}
+}
+
+struct FfiCtx {
+	preferences: Preferences,
+	handle: Handle,
+	app: Arc<OnceLock<String>>,
+}
+
+impl FfiCtx {
+	/// ## Safety
+	/// ctx needs to come from [`Preferences::to_c`] and not be freed
+	unsafe fn from_ptr<'a>(ctx: *const u8) -> &'a Self {
+		unsafe { &*(ctx as *const Self) }
+	}
+
+	fn app(&self) -> Result<String> {
+		self.app.get().cloned().ok_or(PreferenceError::AppLoading)
+	}
+}
+
+extern "C" fn c_get(
+	ctx: *const u8,
+	user_id: ffi::c_uid,
+	key: ffi::c_str,
+	done: ffi::c_preference_done,
+) {
+	let ctx = unsafe { FfiCtx::from_ptr(ctx) };
+	let prefs = ctx.preferences.clone();
+	let user_id = user_id.to_uid();
+	let key = unsafe { key.to_str().to_string() };
+	let done = Done(done);
+
+	let app = match ctx.app() {
+		Ok(app) => app,
+		Err(e) => return done.send(Err(e)),
+	};
+
+	ctx.handle.spawn(async move {
+		let res = prefs.get(&app, &user_id, &key).await;
+		done.send(res.map(|v| v.map(|v| v.to_string())));
+	});
+}
+
+extern "C" fn c_set(
+	ctx: *const u8,
+	user_id: ffi::c_uid,
+	key: ffi::c_str,
+	value: ffi::c_str,
+	done: ffi::c_preference_done,
+) {
+	let ctx = unsafe { FfiCtx::from_ptr(ctx) };
+	let prefs = ctx.preferences.clone();
+	let user_id = user_id.to_uid();
+	let key = unsafe { key.to_str().to_string() };
+	let value = serde_json::from_str(unsafe { value.to_str() });
+	let done = Done(done);
+
+	let app = match ctx.app() {
+		Ok(app) => app,
+		Err(e) => return done.send(Err(e)),
+	};
+
+	let Ok(value) = value else {
+		done.send(Err(PreferenceError::InvalidValue));
+		return;
+	};
+
+	ctx.handle.spawn(async move {
+		let res = prefs.set(&app, &user_id, &key, value).await;
+		done.send(res.map(|_| None));
+	});
+}
+
+extern "C" fn c_remove(
+	ctx: *const u8,
+	user_id: ffi::c_uid,
+	key: ffi::c_str,
+	done: ffi::c_preference_done,
+) {
+	let ctx = unsafe { FfiCtx::from_ptr(ctx) };
+	let prefs = ctx.preferences.clone();
+	let user_id = user_id.to_uid();
+	let key = unsafe { key.to_str().to_string() };
+	let done = Done(done);
+
+	let app = match ctx.app() {
+		Ok(app) => app,
+		Err(e) => return done.send(Err(e)),
+	};
+
+	ctx.handle.spawn(async move {
+		let res = prefs.remove(&app, &user_id, &key).await;
+		done.send(res.map(|_| None));
+	});
+}
+
+struct Done(ffi::c_preference_done);
+
+impl Done {
+	fn send(self, res: Result<Option<String>>) {
+		let (error, found, value) = match res {
+			Ok(Some(v)) => (ffi::c_error::ok(), true, v),
+			Ok(None) => (ffi::c_error::ok(), false, String::new()),
+			Err(e) => (
+				ffi::c_error::new(ffi::C_ERROR_OTHER, e.to_string()),
+				false,
+				String::new(),
+			),
+		};
+
+		(self.0.done)(
+			self.0.ctx,
+			error,
+			found,
+			ffi::c_string::from_string(value),
+		);
+	}
+}
+
+unsafe impl Send for Done {}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn keys() {
+		assert!(valid_key("subtitle-language"));
+		assert!(valid_key("drawer.order_2"));
+		assert!(!valid_key(""));
+		assert!(!valid_key("with space"));
+		assert!(!valid_key("ä"));
+		assert!(valid_key(&"a".repeat(MAX_KEY_LEN)));
+		assert!(!valid_key(&"a".repeat(MAX_KEY_LEN + 1)));
+	}
+}
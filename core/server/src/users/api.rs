@@ -1,8 +1,15 @@
-use super::{Session, User};
+use super::limiter::{LimitKind, Lockout};
+use super::{
+	ApiToken, ApiTokenScope, Group, Invite, Rights, Session, Timeout, Token,
+	User,
+};
 use crate::api::Error;
 
 use serde::{Deserialize, Serialize};
 
+use chuchi_postgres::time::DateTime;
+use chuchi_postgres::UniqueId;
+
 use chuchi::api::{Method, Request};
 
 // Login
@@ -12,6 +19,12 @@ use chuchi::api::{Method, Request};
 pub struct LoginReq {
 	pub username: String,
 	pub password: String,
+	/// a name for the device which get's shown in the devices list
+	#[serde(default)]
+	pub device: Option<String>,
+	/// keeps the session for longer and without an idle timeout
+	#[serde(default)]
+	pub remember: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -29,6 +42,161 @@ impl Request for LoginReq {
 	const METHOD: Method = Method::POST;
 }
 
+// Login with a totp code
+//
+// If the user has totp enabled the login request returns the error
+// `TotpRequired` with a token which needs to be sent here together with a
+// code from the authenticator app or a recovery code
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LoginTotpReq {
+	pub token: Token,
+	pub code: String,
+}
+
+impl Request for LoginTotpReq {
+	type Response = Login;
+	type Error = Error;
+
+	const PATH: &'static str = "/api/users/login/totp";
+	const METHOD: Method = Method::POST;
+}
+
+// Login with openid connect
+//
+// `OidcStartReq` returns the url of the provider the browser needs to be sent
+// to, the provider then redirects back to the ui with a `code` and a `state`
+// which need to be sent to `OidcLoginReq`
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OidcInfoReq {}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OidcInfo {
+	/// the name of the provider, `None` if oidc is not configured
+	pub name: Option<String>,
+}
+
+impl Request for OidcInfoReq {
+	type Response = OidcInfo;
+	type Error = Error;
+
+	const PATH: &'static str = "/api/users/oidc";
+	const METHOD: Method = Method::GET;
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OidcStartReq {
+	#[serde(default)]
+	pub device: Option<String>,
+	#[serde(default)]
+	pub remember: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OidcStart {
+	pub url: String,
+}
+
+impl Request for OidcStartReq {
+	type Response = OidcStart;
+	type Error = Error;
+
+	const PATH: &'static str = "/api/users/oidc/start";
+	const METHOD: Method = Method::POST;
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OidcLoginReq {
+	pub code: String,
+	pub state: String,
+}
+
+impl Request for OidcLoginReq {
+	type Response = Login;
+	type Error = Error;
+
+	const PATH: &'static str = "/api/users/oidc/login";
+	const METHOD: Method = Method::POST;
+}
+
+// Login with the username set by a trusted reverse proxy
+//
+// Returns the session of the request if it belongs to the same user, else a
+// new session get's created
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProxyLoginReq {
+	#[serde(default)]
+	pub device: Option<String>,
+}
+
+impl Request for ProxyLoginReq {
+	type Response = Login;
+	type Error = Error;
+
+	const PATH: &'static str = "/api/users/login/proxy";
+	const METHOD: Method = Method::POST;
+}
+
+// Invite info
+//
+// Allows the ui to show who the invite is for before accepting it
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InviteReq {
+	pub token: Token,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InviteInfo {
+	pub name: String,
+	pub expires_on: DateTime,
+}
+
+impl Request for InviteReq {
+	type Response = InviteInfo;
+	type Error = Error;
+
+	const PATH: &'static str = "/api/users/invite";
+	const METHOD: Method = Method::GET;
+}
+
+// Accept invite
+//
+// Creates the user with the rights of the invite and logs them in, the
+// invite can't be used again
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AcceptInviteReq {
+	pub token: Token,
+	pub username: String,
+	pub name: String,
+	pub password: String,
+	#[serde(default)]
+	pub device: Option<String>,
+	#[serde(default)]
+	pub remember: bool,
+}
+
+impl Request for AcceptInviteReq {
+	type Response = Login;
+	type Error = Error;
+
+	const PATH: &'static str = "/api/users/invite/accept";
+	const METHOD: Method = Method::POST;
+}
+
 // Login by token
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -59,6 +227,26 @@ impl Request for RenewReq {
 	const HEADERS: &'static [&'static str] = &["auth-token"];
 }
 
+// Reauth
+//
+// Entering the password again allows to use endpoints which require a fresh
+// login for some time, returns the session with the new `fresh_until`
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReauthReq {
+	pub password: String,
+}
+
+impl Request for ReauthReq {
+	type Response = Session;
+	type Error = Error;
+
+	const PATH: &'static str = "/api/users/reauth";
+	const METHOD: Method = Method::POST;
+	const HEADERS: &'static [&'static str] = &["auth-token"];
+}
+
 // Logout
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -74,13 +262,78 @@ impl Request for LogoutReq {
 	const HEADERS: &'static [&'static str] = &["auth-token"];
 }
 
+// Devices
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DevicesReq {}
+
+/// A session of the user
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Device {
+	pub id: UniqueId,
+	pub label: Option<String>,
+	pub user_agent: Option<String>,
+	pub ip: Option<String>,
+	pub last_seen: DateTime,
+	pub created_on: DateTime,
+	pub timeout: Timeout,
+	/// if this is the session which made the request
+	pub current: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Devices {
+	pub list: Vec<Device>,
+}
+
+impl Request for DevicesReq {
+	type Response = Devices;
+	type Error = Error;
+
+	const PATH: &'static str = "/api/users/devices";
+	const METHOD: Method = Method::GET;
+	const HEADERS: &'static [&'static str] = &["auth-token"];
+}
+
+// Revoke device
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RevokeDeviceReq;
+
+impl Request for RevokeDeviceReq {
+	type Response = ();
+	type Error = Error;
+
+	const PATH: &'static str = "/api/users/devices/{id}";
+	const METHOD: Method = Method::DELETE;
+	const HEADERS: &'static [&'static str] = &["auth-token"];
+}
+
+// Revoke all other devices
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RevokeOtherDevicesReq;
+
+impl Request for RevokeOtherDevicesReq {
+	type Response = ();
+	type Error = Error;
+
+	const PATH: &'static str = "/api/users/devices/revokeothers";
+	const METHOD: Method = Method::POST;
+	const HEADERS: &'static [&'static str] = &["auth-token"];
+}
+
 // Save admin
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct SaveReq {
 	pub name: String,
-	pub password: Option<String>,
 }
 
 impl Request for SaveReq {
@@ -91,3 +344,493 @@ impl Request for SaveReq {
 	const METHOD: Method = Method::POST;
 	const HEADERS: &'static [&'static str] = &["auth-token"];
 }
+
+// Change password
+//
+// All other sessions of the user get revoked
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChangePasswordReq {
+	pub current_password: String,
+	pub new_password: String,
+}
+
+impl Request for ChangePasswordReq {
+	type Response = ();
+	type Error = Error;
+
+	const PATH: &'static str = "/api/users/password";
+	const METHOD: Method = Method::POST;
+	const HEADERS: &'static [&'static str] = &["auth-token"];
+}
+
+// Totp status
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TotpReq {}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TotpStatus {
+	pub enabled: bool,
+	pub recovery_codes_left: usize,
+}
+
+impl Request for TotpReq {
+	type Response = TotpStatus;
+	type Error = Error;
+
+	const PATH: &'static str = "/api/users/totp";
+	const METHOD: Method = Method::GET;
+	const HEADERS: &'static [&'static str] = &["auth-token"];
+}
+
+// Totp enroll
+//
+// Creates a new secret, it only get's enabled after a code was verified
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TotpEnrollReq;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TotpEnroll {
+	pub secret: String,
+	/// an otpauth:// uri which can be shown as a qr code
+	pub uri: String,
+}
+
+impl Request for TotpEnrollReq {
+	type Response = TotpEnroll;
+	type Error = Error;
+
+	const PATH: &'static str = "/api/users/totp/enroll";
+	const METHOD: Method = Method::POST;
+	const HEADERS: &'static [&'static str] = &["auth-token"];
+}
+
+// Totp verify
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TotpVerifyReq {
+	pub code: String,
+}
+
+/// the recovery codes are only returned once
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TotpRecoveryCodes {
+	pub codes: Vec<String>,
+}
+
+impl Request for TotpVerifyReq {
+	type Response = TotpRecoveryCodes;
+	type Error = Error;
+
+	const PATH: &'static str = "/api/users/totp/verify";
+	const METHOD: Method = Method::POST;
+	const HEADERS: &'static [&'static str] = &["auth-token"];
+}
+
+// Totp disable
+
+/// requires that the password was entered recently, see [`ReauthReq`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TotpDisableReq {
+	/// a totp or a recovery code
+	pub code: String,
+}
+
+impl Request for TotpDisableReq {
+	type Response = ();
+	type Error = Error;
+
+	const PATH: &'static str = "/api/users/totp/disable";
+	const METHOD: Method = Method::POST;
+	const HEADERS: &'static [&'static str] = &["auth-token"];
+}
+
+// Api tokens
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiTokensReq {}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiTokens {
+	pub list: Vec<ApiToken>,
+}
+
+impl Request for ApiTokensReq {
+	type Response = ApiTokens;
+	type Error = Error;
+
+	const PATH: &'static str = "/api/users/apitokens";
+	const METHOD: Method = Method::GET;
+	const HEADERS: &'static [&'static str] = &["auth-token"];
+}
+
+// Create api token
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateApiTokenReq {
+	pub name: String,
+	pub scope: ApiTokenScope,
+	#[serde(default)]
+	pub expires_on: Option<DateTime>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewApiToken {
+	/// is only returned once
+	pub token: Token,
+	pub api_token: ApiToken,
+}
+
+impl Request for CreateApiTokenReq {
+	type Response = NewApiToken;
+	type Error = Error;
+
+	const PATH: &'static str = "/api/users/apitokens";
+	const METHOD: Method = Method::POST;
+	const HEADERS: &'static [&'static str] = &["auth-token"];
+}
+
+// Delete api token
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeleteApiTokenReq;
+
+impl Request for DeleteApiTokenReq {
+	type Response = ();
+	type Error = Error;
+
+	const PATH: &'static str = "/api/users/apitokens/{id}";
+	const METHOD: Method = Method::DELETE;
+	const HEADERS: &'static [&'static str] = &["auth-token"];
+}
+
+// Admin: list users
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UsersReq {}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UsersList {
+	pub list: Vec<User>,
+}
+
+impl Request for UsersReq {
+	type Response = UsersList;
+	type Error = Error;
+
+	const PATH: &'static str = "/api/users/admin/users";
+	const METHOD: Method = Method::GET;
+	const HEADERS: &'static [&'static str] = &["auth-token"];
+}
+
+// Admin: create user
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateUserReq {
+	pub username: String,
+	pub name: String,
+	pub password: String,
+	pub rights: Rights,
+}
+
+impl Request for CreateUserReq {
+	type Response = User;
+	type Error = Error;
+
+	const PATH: &'static str = "/api/users/admin/users";
+	const METHOD: Method = Method::POST;
+	const HEADERS: &'static [&'static str] = &["auth-token"];
+}
+
+// Admin: edit user
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EditUserReq {
+	pub username: String,
+	pub name: String,
+	pub rights: Rights,
+}
+
+impl Request for EditUserReq {
+	type Response = User;
+	type Error = Error;
+
+	const PATH: &'static str = "/api/users/admin/users/{id}";
+	const METHOD: Method = Method::POST;
+	const HEADERS: &'static [&'static str] = &["auth-token"];
+}
+
+// Admin: reset password
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResetPasswordReq {
+	pub password: String,
+}
+
+impl Request for ResetPasswordReq {
+	type Response = ();
+	type Error = Error;
+
+	const PATH: &'static str = "/api/users/admin/users/{id}/password";
+	const METHOD: Method = Method::POST;
+	const HEADERS: &'static [&'static str] = &["auth-token"];
+}
+
+// Admin: delete user
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeleteUserReq;
+
+impl Request for DeleteUserReq {
+	type Response = ();
+	type Error = Error;
+
+	const PATH: &'static str = "/api/users/admin/users/{id}";
+	const METHOD: Method = Method::DELETE;
+	const HEADERS: &'static [&'static str] = &["auth-token"];
+}
+
+// Admin: list lockouts
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LockoutsReq {}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Lockouts {
+	pub list: Vec<Lockout>,
+}
+
+impl Request for LockoutsReq {
+	type Response = Lockouts;
+	type Error = Error;
+
+	const PATH: &'static str = "/api/users/admin/lockouts";
+	const METHOD: Method = Method::GET;
+	const HEADERS: &'static [&'static str] = &["auth-token"];
+}
+
+// Admin: clear lockout
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClearLockoutReq {
+	pub kind: LimitKind,
+	/// the username or the ip
+	pub key: String,
+}
+
+impl Request for ClearLockoutReq {
+	type Response = ();
+	type Error = Error;
+
+	const PATH: &'static str = "/api/users/admin/lockouts/clear";
+	const METHOD: Method = Method::POST;
+	const HEADERS: &'static [&'static str] = &["auth-token"];
+}
+
+// Admin: list invites
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InvitesReq {}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Invites {
+	pub list: Vec<Invite>,
+}
+
+impl Request for InvitesReq {
+	type Response = Invites;
+	type Error = Error;
+
+	const PATH: &'static str = "/api/users/admin/invites";
+	const METHOD: Method = Method::GET;
+	const HEADERS: &'static [&'static str] = &["auth-token"];
+}
+
+// Admin: create invite
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateInviteReq {
+	/// who the invite is for
+	pub name: String,
+	pub rights: Rights,
+	/// defaults to in 7 days
+	#[serde(default)]
+	pub expires_on: Option<DateTime>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewInvite {
+	/// is only returned once
+	pub token: Token,
+	pub invite: Invite,
+}
+
+impl Request for CreateInviteReq {
+	type Response = NewInvite;
+	type Error = Error;
+
+	const PATH: &'static str = "/api/users/admin/invites";
+	const METHOD: Method = Method::POST;
+	const HEADERS: &'static [&'static str] = &["auth-token"];
+}
+
+// Admin: delete invite
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeleteInviteReq;
+
+impl Request for DeleteInviteReq {
+	type Response = ();
+	type Error = Error;
+
+	const PATH: &'static str = "/api/users/admin/invites/{id}";
+	const METHOD: Method = Method::DELETE;
+	const HEADERS: &'static [&'static str] = &["auth-token"];
+}
+
+// Admin: impersonate user
+//
+// Creates a time limited session in which root sees what the user sees,
+// requires a fresh session and does not work for other root users
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImpersonateReq;
+
+impl Request for ImpersonateReq {
+	type Response = Login;
+	type Error = Error;
+
+	const PATH: &'static str = "/api/users/admin/users/{id}/impersonate";
+	const METHOD: Method = Method::POST;
+	const HEADERS: &'static [&'static str] = &["auth-token"];
+}
+
+// Admin: list groups
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GroupsReq {}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Groups {
+	pub list: Vec<Group>,
+}
+
+impl Request for GroupsReq {
+	type Response = Groups;
+	type Error = Error;
+
+	const PATH: &'static str = "/api/users/admin/groups";
+	const METHOD: Method = Method::GET;
+	const HEADERS: &'static [&'static str] = &["auth-token"];
+}
+
+// Admin: create group
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateGroupReq {
+	pub name: String,
+}
+
+impl Request for CreateGroupReq {
+	type Response = Group;
+	type Error = Error;
+
+	const PATH: &'static str = "/api/users/admin/groups";
+	const METHOD: Method = Method::POST;
+	const HEADERS: &'static [&'static str] = &["auth-token"];
+}
+
+// Admin: edit group
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EditGroupReq {
+	pub name: String,
+}
+
+impl Request for EditGroupReq {
+	type Response = Group;
+	type Error = Error;
+
+	const PATH: &'static str = "/api/users/admin/groups/{id}";
+	const METHOD: Method = Method::POST;
+	const HEADERS: &'static [&'static str] = &["auth-token"];
+}
+
+// Admin: delete group
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeleteGroupReq;
+
+impl Request for DeleteGroupReq {
+	type Response = ();
+	type Error = Error;
+
+	const PATH: &'static str = "/api/users/admin/groups/{id}";
+	const METHOD: Method = Method::DELETE;
+	const HEADERS: &'static [&'static str] = &["auth-token"];
+}
+
+// Admin: add group member
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AddGroupMemberReq;
+
+impl Request for AddGroupMemberReq {
+	type Response = Group;
+	type Error = Error;
+
+	const PATH: &'static str = "/api/users/admin/groups/{id}/members/{user_id}";
+	const METHOD: Method = Method::POST;
+	const HEADERS: &'static [&'static str] = &["auth-token"];
+}
+
+// Admin: remove group member
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoveGroupMemberReq;
+
+impl Request for RemoveGroupMemberReq {
+	type Response = Group;
+	type Error = Error;
+
+	const PATH: &'static str = "/api/users/admin/groups/{id}/members/{user_id}";
+	const METHOD: Method = Method::DELETE;
+	const HEADERS: &'static [&'static str] = &["auth-token"];
+}
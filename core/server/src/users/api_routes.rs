@@ -1,22 +1,41 @@
 use super::api::{
-	Login, LoginByTokenReq, LoginReq, LogoutReq, RenewReq, SaveReq,
+	AcceptInviteReq, AddGroupMemberReq, ApiTokens, ApiTokensReq,
+	ChangePasswordReq, ClearLockoutReq, CreateApiTokenReq, CreateGroupReq,
+	CreateInviteReq, CreateUserReq, DeleteApiTokenReq, DeleteGroupReq,
+	DeleteInviteReq, DeleteUserReq, Device as ApiDevice, Devices, DevicesReq,
+	EditGroupReq, EditUserReq, Groups, GroupsReq, ImpersonateReq, InviteInfo,
+	InviteReq, Invites, InvitesReq, Lockouts, LockoutsReq, Login,
+	LoginByTokenReq, LoginReq, LoginTotpReq, LogoutReq, NewApiToken, NewInvite,
+	OidcInfo, OidcInfoReq, OidcLoginReq, OidcStart, OidcStartReq,
+	ProxyLoginReq, ReauthReq, RemoveGroupMemberReq, RenewReq, ResetPasswordReq,
+	RevokeDeviceReq, RevokeOtherDevicesReq, SaveReq, TotpDisableReq,
+	TotpEnroll, TotpEnrollReq, TotpRecoveryCodes, TotpReq, TotpStatus,
+	TotpVerifyReq, UsersList, UsersReq,
 };
 use super::db::Users;
-use super::{Session, Timeout, User};
+use super::limiter::LoginLimiter;
+use super::oidc::{Identity, Oidc};
+use super::proxy::{client_ip, Proxy};
+use super::totp::{self, Totp};
+use super::{Device, Group, Session, Token, User};
 use crate::api::{Error, Result};
+use crate::audit::{Audit, Event, EventKind};
 
+use std::cmp::Reverse;
 use std::time::Duration;
 
+use chuchi::extractor::PathParam;
 use chuchi::header::RequestHeader;
 use chuchi::Chuchi;
 
 use chuchi::api;
 
 use chuchi::api::response::ResponseSettings;
+use chuchi_postgres::time::DateTime;
+use chuchi_postgres::UniqueId;
 use core_lib::users::{get_token, get_token_from_cookie};
 
-// 1/2 year
-const TIMEOUT_DURATION: Duration = Duration::from_secs(365 * 24 * 60 * 60 * 60);
+use tracing::error;
 
 pub async fn sess_user_from_req(
 	header: &RequestHeader,
@@ -30,6 +49,35 @@ pub async fn sess_user_from_req(
 		.ok_or(Error::InvalidAuthToken)
 }
 
+/// like `sess_user_from_req` but refuses sessions in which root
+/// impersonates the user
+pub async fn own_sess_user_from_req(
+	header: &RequestHeader,
+	users: &Users,
+) -> Result<(Session, User)> {
+	let (session, user) = sess_user_from_req(header, users).await?;
+
+	if session.is_impersonated() {
+		return Err(Error::Impersonated);
+	}
+
+	Ok((session, user))
+}
+
+/// like `sess_user_from_req` but the user needs to be root
+pub async fn root_from_req(
+	header: &RequestHeader,
+	users: &Users,
+) -> Result<(Session, User)> {
+	let (session, user) = sess_user_from_req(header, users).await?;
+
+	if !user.rights.root {
+		return Err(Error::MissingRights);
+	}
+
+	Ok((session, user))
+}
+
 #[allow(dead_code)]
 pub async fn sess_user_from_cookie(
 	header: &RequestHeader,
@@ -43,6 +91,16 @@ pub async fn sess_user_from_cookie(
 		.ok_or(Error::InvalidDataToken)
 }
 
+fn new_device(header: &RequestHeader, label: Option<String>) -> Device {
+	Device {
+		id: UniqueId::new(),
+		label,
+		user_agent: header.value("user-agent").map(Into::into),
+		ip: Some(client_ip(header).to_string()),
+		last_seen: DateTime::now(),
+	}
+}
+
 fn set_cookie(headers: &mut ResponseSettings, sess: Option<&Session>) {
 	let setts = if crate::Args::enable_cors() {
 		"Path=/; HttpOnly; SameSite=None; Secure"
@@ -63,16 +121,400 @@ fn set_cookie(headers: &mut ResponseSettings, sess: Option<&Session>) {
 #[api(LoginReq)]
 async fn login(
 	req: LoginReq,
+	header: &RequestHeader,
 	users: &Users,
+	totp: &Totp,
+	limiter: &LoginLimiter,
+	audit: &Audit,
 	resp_header: &mut ResponseSettings,
 ) -> Result<Login> {
-	let user = users
-		.login(&req.username, &req.password)
+	let ip = client_ip(header);
+	// check before verifying the password since that is expensive
+	limiter
+		.check(&req.username, ip)
+		.map_err(Error::TooManyAttempts)?;
+
+	let Some(authenticated) = users.login(&req.username, &req.password).await?
+	else {
+		limiter.failed(&req.username, ip);
+		audit
+			.log(
+				Event::new(EventKind::LoginFailed)
+					.username(&req.username)
+					.header(header),
+			)
+			.await;
+		return Err(Error::LoginIncorrect);
+	};
+	let user = authenticated.user;
+
+	if authenticated.created {
+		audit
+			.log(
+				Event::new(EventKind::UserCreated)
+					.user(&user)
+					.header(header)
+					.details(authenticated.backend),
+			)
+			.await;
+	}
+
+	let totp_enabled = users.totp(&user.id).await?.is_some_and(|t| t.enabled);
+	if totp_enabled {
+		let token = totp.login_start(user.id, req.device, req.remember);
+		return Err(Error::TotpRequired(token));
+	}
+	limiter.succeeded(&req.username);
+
+	let session = users
+		.session_insert(user.id, req.remember, new_device(header, req.device))
+		.await?;
+
+	audit
+		.log(
+			Event::new(EventKind::Login)
+				.user(&user)
+				.header(header)
+				.details(authenticated.backend),
+		)
+		.await;
+
+	set_cookie(resp_header, Some(&session));
+
+	Ok(Login { user, session })
+}
+
+/// checks a totp code or a recovery code, a recovery code can only be used
+/// once
+async fn check_totp_code(
+	user_id: &UniqueId,
+	code: &str,
+	users: &Users,
+	totp: &Totp,
+) -> Result<bool> {
+	let Some(user_totp) = users.totp(user_id).await? else {
+		return Ok(false);
+	};
+	let Some(secret) = user_totp.secret.filter(|_| user_totp.enabled) else {
+		return Ok(false);
+	};
+
+	if totp.verify(user_id, &secret, code) {
+		return Ok(true);
+	}
+
+	let hash = totp::hash_recovery_code(code);
+	let mut recovery = user_totp.recovery;
+	let Some(pos) = recovery.iter().position(|h| *h == hash) else {
+		return Ok(false);
+	};
+	recovery.remove(pos);
+	users.totp_set_recovery(user_id, recovery).await?;
+
+	Ok(true)
+}
+
+#[api(LoginTotpReq)]
+async fn login_totp(
+	req: LoginTotpReq,
+	header: &RequestHeader,
+	users: &Users,
+	totp: &Totp,
+	limiter: &LoginLimiter,
+	audit: &Audit,
+	resp_header: &mut ResponseSettings,
+) -> Result<Login> {
+	// the login needs to be started again
+	let (user_id, device, remember) =
+		totp.login_get(&req.token).ok_or(Error::LoginIncorrect)?;
+	let user = users.by_id(&user_id).await?.ok_or(Error::LoginIncorrect)?;
+
+	let ip = client_ip(header);
+	limiter
+		.check(&user.username, ip)
+		.map_err(Error::TooManyAttempts)?;
+
+	if !check_totp_code(&user_id, &req.code, users, totp).await? {
+		totp.login_failed(&req.token);
+		limiter.failed(&user.username, ip);
+		audit
+			.log(
+				Event::new(EventKind::LoginFailed)
+					.user(&user)
+					.header(header)
+					.details("totp code incorrect"),
+			)
+			.await;
+		return Err(Error::TotpIncorrect);
+	}
+	totp.login_done(&req.token);
+	limiter.succeeded(&user.username);
+
+	let session = users
+		.session_insert(user.id, remember, new_device(header, device))
+		.await?;
+
+	audit
+		.log(
+			Event::new(EventKind::Login)
+				.user(&user)
+				.header(header)
+				.details("with totp"),
+		)
+		.await;
+
+	set_cookie(resp_header, Some(&session));
+
+	Ok(Login { user, session })
+}
+
+#[api(OidcInfoReq)]
+async fn oidc_info(oidc: &Oidc) -> Result<OidcInfo> {
+	Ok(OidcInfo {
+		name: oidc.conf().map(|c| c.name.clone()),
+	})
+}
+
+#[api(OidcStartReq)]
+async fn oidc_start(req: OidcStartReq, oidc: &Oidc) -> Result<OidcStart> {
+	let url = oidc.start(req.device, req.remember).await.map_err(|e| {
+		error!("oidc start failed {e}");
+		e
+	})?;
+
+	Ok(OidcStart { url })
+}
+
+/// returns the linked user, links a user with the same username or creates
+/// a new one depending on the config
+async fn oidc_user(
+	identity: &Identity,
+	header: &RequestHeader,
+	users: &Users,
+	oidc: &Oidc,
+	audit: &Audit,
+) -> Result<User> {
+	let cfg = oidc.conf().ok_or(Error::LoginIncorrect)?;
+
+	if let Some(user) = users
+		.by_oidc_subject(&cfg.issuer, &identity.subject)
 		.await?
-		.ok_or(Error::LoginIncorrect)?;
+	{
+		return Ok(user);
+	}
+
+	let user = match users.by_username(&identity.username).await? {
+		// the username is controlled by the provider, root and users which
+		// are linked to another subject are never taken over
+		Some(user)
+			if cfg.link_existing
+				&& !user.rights.root
+				&& !users.oidc_linked(&cfg.issuer, &user.id).await? =>
+		{
+			user
+		}
+		Some(_) => return Err(Error::LoginIncorrect),
+		None if cfg.auto_provision => {
+			// the user can only login via the provider until a root sets a
+			// password
+			let user = users
+				.insert(
+					identity.username.clone(),
+					identity.name.clone(),
+					Token::new().to_string(),
+					cfg.default_rights.clone(),
+				)
+				.await?;
+
+			audit
+				.log(
+					Event::new(EventKind::UserCreated)
+						.user(&user)
+						.header(header)
+						.details(format!("by oidc {}", cfg.issuer)),
+				)
+				.await;
+
+			user
+		}
+		None => return Err(Error::LoginIncorrect),
+	};
+
+	users
+		.oidc_link(cfg.issuer.clone(), identity.subject.clone(), user.id)
+		.await?;
+
+	Ok(user)
+}
+
+#[api(OidcLoginReq)]
+async fn oidc_login(
+	req: OidcLoginReq,
+	header: &RequestHeader,
+	users: &Users,
+	oidc: &Oidc,
+	audit: &Audit,
+	resp_header: &mut ResponseSettings,
+) -> Result<Login> {
+	let finished = oidc.finish(&req.code, &req.state).await.map_err(|e| {
+		error!("oidc login failed {e}");
+		e
+	})?;
+
+	let user = oidc_user(&finished.identity, header, users, oidc, audit).await;
+	let user = match user {
+		Ok(user) => user,
+		Err(e) => {
+			audit
+				.log(
+					Event::new(EventKind::LoginFailed)
+						.username(&finished.identity.username)
+						.header(header)
+						.details("oidc"),
+				)
+				.await;
+			return Err(e);
+		}
+	};
+
+	let session = users
+		.session_insert(
+			user.id,
+			finished.remember,
+			new_device(header, finished.device),
+		)
+		.await?;
 
-	// create Session
-	let session = users.session_insert(user.id, Timeout::new(TIMEOUT_DURATION));
+	audit
+		.log(
+			Event::new(EventKind::Login)
+				.user(&user)
+				.header(header)
+				.details("with oidc"),
+		)
+		.await;
+
+	set_cookie(resp_header, Some(&session));
+
+	Ok(Login { user, session })
+}
+
+#[api(ProxyLoginReq)]
+async fn proxy_login(
+	req: ProxyLoginReq,
+	header: &RequestHeader,
+	users: &Users,
+	proxy: &Proxy,
+	audit: &Audit,
+	resp_header: &mut ResponseSettings,
+) -> Result<Login> {
+	let cfg = proxy
+		.conf()
+		.ok_or_else(|| Error::Request("proxy auth is not configured".into()))?;
+	let proxy_user = proxy.user(header).ok_or(Error::LoginIncorrect)?;
+
+	// reuse the session if the ui still has one
+	if let Ok((session, user)) = sess_user_from_req(header, users).await {
+		if user.username == proxy_user.username {
+			set_cookie(resp_header, Some(&session));
+			return Ok(Login { user, session });
+		}
+	}
+
+	let user = match users.by_username(&proxy_user.username).await? {
+		Some(user) => user,
+		None if cfg.auto_provision => {
+			// the user can only login via the proxy until a root sets a
+			// password
+			let name = proxy_user
+				.name
+				.unwrap_or_else(|| proxy_user.username.clone());
+			let user = users
+				.insert(
+					proxy_user.username,
+					name,
+					Token::new().to_string(),
+					cfg.default_rights.clone(),
+				)
+				.await?;
+
+			audit
+				.log(
+					Event::new(EventKind::UserCreated)
+						.user(&user)
+						.header(header)
+						.details("proxy"),
+				)
+				.await;
+
+			user
+		}
+		None => return Err(Error::LoginIncorrect),
+	};
+
+	let session = users
+		.session_insert(user.id, false, new_device(header, req.device))
+		.await?;
+
+	audit
+		.log(
+			Event::new(EventKind::Login)
+				.user(&user)
+				.header(header)
+				.details("proxy"),
+		)
+		.await;
+
+	set_cookie(resp_header, Some(&session));
+
+	Ok(Login { user, session })
+}
+
+#[api(InviteReq)]
+async fn invite_info(req: InviteReq, users: &Users) -> Result<InviteInfo> {
+	let invite = users
+		.invite_by_token(&req.token)
+		.await?
+		.ok_or(Error::NotFound)?;
+
+	Ok(InviteInfo {
+		name: invite.name,
+		expires_on: invite.expires_on,
+	})
+}
+
+#[api(AcceptInviteReq)]
+async fn accept_invite(
+	req: AcceptInviteReq,
+	header: &RequestHeader,
+	users: &Users,
+	audit: &Audit,
+	resp_header: &mut ResponseSettings,
+) -> Result<Login> {
+	// check everything before the invite get's used up
+	let username = check_username(&req.username, None, users).await?;
+	users.check_policy(&username, &req.password)?;
+	if req.name.trim().is_empty() {
+		return Err(Error::Request("name cannot be empty".into()));
+	}
+
+	let (invite, user) = users
+		.invite_accept(&req.token, username, req.name, req.password)
+		.await?
+		.ok_or(Error::NotFound)?;
+
+	audit
+		.log(
+			Event::new(EventKind::InviteAccepted)
+				.user(&user)
+				.header(header)
+				.details(format!("invite {} for {}", invite.id, invite.name)),
+		)
+		.await;
+
+	let session = users
+		.session_insert(user.id, req.remember, new_device(header, req.device))
+		.await?;
 
 	set_cookie(resp_header, Some(&session));
 
@@ -96,29 +538,105 @@ async fn login_by_token(
 async fn renew(
 	header: &RequestHeader,
 	users: &Users,
+	audit: &Audit,
 	resp_header: &mut ResponseSettings,
 ) -> Result<Login> {
-	let (session, user) = sess_user_from_req(header, users).await?;
+	let (session, user) = own_sess_user_from_req(header, users).await?;
 
-	users.session_remove(&session.token);
+	let label = users
+		.session_device(&session.token)
+		.and_then(|device| device.label);
+
+	let session = users
+		.session_renew(&session.token, new_device(header, label))
+		.await?
+		.ok_or(Error::InvalidAuthToken)?;
 
-	// create Session
-	let session = users.session_insert(user.id, Timeout::new(TIMEOUT_DURATION));
+	audit
+		.log(
+			Event::new(EventKind::SessionRenewed)
+				.user(&user)
+				.header(header),
+		)
+		.await;
 
 	set_cookie(resp_header, Some(&session));
 
 	Ok(Login { user, session })
 }
 
+#[api(ReauthReq)]
+async fn reauth(
+	req: ReauthReq,
+	header: &RequestHeader,
+	users: &Users,
+	limiter: &LoginLimiter,
+	audit: &Audit,
+) -> Result<Session> {
+	let (session, user) = own_sess_user_from_req(header, users).await?;
+
+	let ip = client_ip(header);
+	limiter
+		.check(&user.username, ip)
+		.map_err(Error::TooManyAttempts)?;
+
+	// a backend could resolve the username to another user, for example an
+	// ldap user with the same name
+	let authenticated = users.login(&user.username, &req.password).await?;
+	if !authenticated.is_some_and(|a| a.user.id == user.id) {
+		limiter.failed(&user.username, ip);
+		audit
+			.log(
+				Event::new(EventKind::LoginFailed)
+					.user(&user)
+					.header(header)
+					.details("reauth"),
+			)
+			.await;
+		return Err(Error::LoginIncorrect);
+	}
+	limiter.succeeded(&user.username);
+
+	let session = users
+		.session_verified(&session.token)
+		.await?
+		.ok_or(Error::InvalidAuthToken)?;
+
+	audit
+		.log(
+			Event::new(EventKind::Reauthenticated)
+				.user(&user)
+				.header(header),
+		)
+		.await;
+
+	Ok(session)
+}
+
 #[api(LogoutReq)]
 async fn logout(
 	header: &RequestHeader,
 	users: &Users,
+	audit: &Audit,
 	resp_header: &mut ResponseSettings,
 ) -> Result<()> {
-	let (session, _) = sess_user_from_req(header, users).await?;
+	let (session, user) = sess_user_from_req(header, users).await?;
 
-	users.session_remove(&session.token);
+	users.session_remove(&session.token).await?;
+
+	let event = match &session.impersonated_by {
+		Some(root_id) => {
+			let root = users.by_id(root_id).await?;
+			let event = Event::new(EventKind::ImpersonationEnded)
+				.details(format!("as {}", user.username));
+			match root {
+				Some(root) => event.user(&root),
+				None => event,
+			}
+		}
+		None => Event::new(EventKind::Logout).user(&user),
+	};
+	audit.log(event.header(header)).await;
 
 	// set cookies
 	set_cookie(resp_header, None);
@@ -126,26 +644,804 @@ async fn logout(
 	Ok(())
 }
 
+#[api(DevicesReq)]
+async fn devices(header: &RequestHeader, users: &Users) -> Result<Devices> {
+	let (session, user) = sess_user_from_req(header, users).await?;
+
+	let mut list: Vec<_> = users
+		.sessions_by_user(&user.id)
+		.into_iter()
+		.map(|(sess, device)| ApiDevice {
+			id: device.id,
+			label: device.label,
+			user_agent: device.user_agent,
+			ip: device.ip,
+			last_seen: device.last_seen,
+			created_on: sess.created_on,
+			current: sess.token == session.token,
+			timeout: sess.timeout,
+		})
+		.collect();
+	list.sort_by_key(|d| Reverse(d.last_seen));
+
+	Ok(Devices { list })
+}
+
+#[api(RevokeDeviceReq)]
+async fn revoke_device(
+	id: PathParam<UniqueId>,
+	header: &RequestHeader,
+	users: &Users,
+) -> Result<()> {
+	let (_, user) = own_sess_user_from_req(header, users).await?;
+
+	let removed = users.session_remove_by_device(&user.id, &id).await?;
+	if !removed {
+		return Err(Error::NotFound);
+	}
+
+	Ok(())
+}
+
+#[api(RevokeOtherDevicesReq)]
+async fn revoke_other_devices(
+	header: &RequestHeader,
+	users: &Users,
+) -> Result<()> {
+	let (session, user) = own_sess_user_from_req(header, users).await?;
+
+	users
+		.sessions_remove_others(&user.id, &session.token)
+		.await
+		.map_err(Into::into)
+}
+
 #[api(SaveReq)]
 async fn save(
 	req: SaveReq,
 	header: &RequestHeader,
 	users: &Users,
 ) -> Result<User> {
-	let (_, mut user) = sess_user_from_req(header, users).await?;
+	let (_, mut user) = own_sess_user_from_req(header, users).await?;
+
+	user.name = req.name;
+	users.update(&user.id, &user.name).await?;
+
+	Ok(user)
+}
+
+#[api(ChangePasswordReq)]
+async fn change_password(
+	req: ChangePasswordReq,
+	header: &RequestHeader,
+	users: &Users,
+	limiter: &LoginLimiter,
+	audit: &Audit,
+) -> Result<()> {
+	let (session, user) = own_sess_user_from_req(header, users).await?;
+
+	let ip = client_ip(header);
+	limiter
+		.check(&user.username, ip)
+		.map_err(Error::TooManyAttempts)?;
+
+	// only the local password can be changed here
+	let correct = users
+		.check_password(&user.username, &req.current_password)
+		.await?
+		.is_some();
+	if !correct {
+		limiter.failed(&user.username, ip);
+		audit
+			.log(
+				Event::new(EventKind::LoginFailed)
+					.user(&user)
+					.header(header)
+					.details("change password"),
+			)
+			.await;
+		return Err(Error::LoginIncorrect);
+	}
+	limiter.succeeded(&user.username);
+
+	users.check_policy(&user.username, &req.new_password)?;
+	users.set_password(&user.id, &req.new_password).await?;
+
+	// the old password might be known by someone else
+	users
+		.sessions_remove_others(&user.id, &session.token)
+		.await?;
+
+	audit
+		.log(
+			Event::new(EventKind::PasswordChanged)
+				.user(&user)
+				.header(header),
+		)
+		.await;
+
+	Ok(())
+}
+
+#[api(TotpReq)]
+async fn totp_status(
+	header: &RequestHeader,
+	users: &Users,
+) -> Result<TotpStatus> {
+	let (_, user) = sess_user_from_req(header, users).await?;
+
+	let user_totp = users.totp(&user.id).await?.ok_or(Error::NotFound)?;
+
+	Ok(TotpStatus {
+		enabled: user_totp.enabled,
+		recovery_codes_left: user_totp.recovery.len(),
+	})
+}
+
+#[api(TotpEnrollReq)]
+async fn totp_enroll(
+	header: &RequestHeader,
+	users: &Users,
+) -> Result<TotpEnroll> {
+	let (_, user) = own_sess_user_from_req(header, users).await?;
+
+	let user_totp = users.totp(&user.id).await?.ok_or(Error::NotFound)?;
+	if user_totp.enabled {
+		return Err(Error::Request("totp is already enabled".into()));
+	}
+
+	let secret = totp::new_secret();
+	users.totp_set_secret(&user.id, Some(&secret)).await?;
+
+	Ok(TotpEnroll {
+		uri: totp::provisioning_uri(&secret, &user.username),
+		secret,
+	})
+}
+
+#[api(TotpVerifyReq)]
+async fn totp_verify(
+	req: TotpVerifyReq,
+	header: &RequestHeader,
+	users: &Users,
+	totp: &Totp,
+	audit: &Audit,
+) -> Result<TotpRecoveryCodes> {
+	let (_, user) = own_sess_user_from_req(header, users).await?;
+
+	let user_totp = users.totp(&user.id).await?.ok_or(Error::NotFound)?;
+	if user_totp.enabled {
+		return Err(Error::Request("totp is already enabled".into()));
+	}
+	let secret = user_totp
+		.secret
+		.ok_or_else(|| Error::Request("totp was not enrolled".into()))?;
+
+	if !totp.verify(&user.id, &secret, &req.code) {
+		return Err(Error::TotpIncorrect);
+	}
+
+	let (codes, hashes) = totp::new_recovery_codes();
+	users.totp_enable(&user.id, hashes).await?;
+
+	audit
+		.log(
+			Event::new(EventKind::TotpEnabled)
+				.user(&user)
+				.header(header),
+		)
+		.await;
+
+	Ok(TotpRecoveryCodes { codes })
+}
+
+#[api(TotpDisableReq)]
+async fn totp_disable(
+	req: TotpDisableReq,
+	header: &RequestHeader,
+	users: &Users,
+	totp: &Totp,
+	audit: &Audit,
+) -> Result<()> {
+	let (session, user) = own_sess_user_from_req(header, users).await?;
+
+	// a stolen session and one observed code should not be enough
+	if !session.is_fresh() {
+		return Err(Error::ReauthRequired);
+	}
+
+	if !check_totp_code(&user.id, &req.code, users, totp).await? {
+		return Err(Error::TotpIncorrect);
+	}
+
+	users.totp_set_secret(&user.id, None).await?;
+
+	audit
+		.log(
+			Event::new(EventKind::TotpDisabled)
+				.user(&user)
+				.header(header),
+		)
+		.await;
+
+	Ok(())
+}
+
+#[api(ApiTokensReq)]
+async fn api_tokens(
+	header: &RequestHeader,
+	users: &Users,
+) -> Result<ApiTokens> {
+	let (_, user) = sess_user_from_req(header, users).await?;
+
+	let mut list = users.api_tokens_by_user(&user.id).await?;
+	list.sort_by_key(|t| Reverse(t.created_on));
+
+	Ok(ApiTokens { list })
+}
+
+#[api(CreateApiTokenReq)]
+async fn create_api_token(
+	req: CreateApiTokenReq,
+	header: &RequestHeader,
+	users: &Users,
+	audit: &Audit,
+) -> Result<NewApiToken> {
+	let (_, user) = own_sess_user_from_req(header, users).await?;
+
+	if req.name.trim().is_empty() {
+		return Err(Error::Request("name cannot be empty".into()));
+	}
+
+	let (token, api_token) = users
+		.api_token_insert(user.id, req.name, req.scope, req.expires_on)
+		.await?;
+
+	audit
+		.log(
+			Event::new(EventKind::ApiTokenCreated)
+				.user(&user)
+				.header(header)
+				.details(format!("{} ({})", api_token.name, api_token.id)),
+		)
+		.await;
+
+	Ok(NewApiToken { token, api_token })
+}
+
+#[api(DeleteApiTokenReq)]
+async fn delete_api_token(
+	id: PathParam<UniqueId>,
+	header: &RequestHeader,
+	users: &Users,
+	audit: &Audit,
+) -> Result<()> {
+	let (_, user) = own_sess_user_from_req(header, users).await?;
+
+	if !users.api_token_remove(&user.id, &id).await? {
+		return Err(Error::NotFound);
+	}
+
+	audit
+		.log(
+			Event::new(EventKind::ApiTokenDeleted)
+				.user(&user)
+				.header(header)
+				.details(id.to_string()),
+		)
+		.await;
+
+	Ok(())
+}
+
+#[api(UsersReq)]
+async fn admin_users(
+	header: &RequestHeader,
+	users: &Users,
+) -> Result<UsersList> {
+	root_from_req(header, users).await?;
+
+	let mut list = users.all().await?;
+	list.sort_by(|a, b| a.username.cmp(&b.username));
+
+	Ok(UsersList { list })
+}
+
+/// returns the normalized username or an error if it is empty or already taken
+/// by another user
+async fn check_username(
+	username: &str,
+	id: Option<&UniqueId>,
+	users: &Users,
+) -> Result<String> {
+	// the ui always lowercases the username on login
+	let username = username.trim().to_lowercase();
+	if username.is_empty() {
+		return Err(Error::Request("username cannot be empty".into()));
+	}
+
+	match users.by_username(&username).await? {
+		Some(user) if Some(&user.id) != id => Err(Error::UsernameTaken),
+		_ => Ok(username),
+	}
+}
+
+#[api(CreateUserReq)]
+async fn admin_create_user(
+	req: CreateUserReq,
+	header: &RequestHeader,
+	users: &Users,
+	audit: &Audit,
+) -> Result<User> {
+	let (_, root) = root_from_req(header, users).await?;
+
+	let username = check_username(&req.username, None, users).await?;
+	users.check_policy(&username, &req.password)?;
+
+	let user = users
+		.insert(username, req.name, req.password, req.rights)
+		.await?;
+
+	audit
+		.log(
+			Event::new(EventKind::UserCreated)
+				.user(&user)
+				.header(header)
+				.details(format!("by {}", root.username)),
+		)
+		.await;
+
+	Ok(user)
+}
+
+#[api(EditUserReq)]
+async fn admin_edit_user(
+	id: PathParam<UniqueId>,
+	req: EditUserReq,
+	header: &RequestHeader,
+	users: &Users,
+	audit: &Audit,
+) -> Result<User> {
+	let (_, root) = root_from_req(header, users).await?;
+
+	let mut user = users.by_id(&id).await?.ok_or(Error::NotFound)?;
+
+	// make sure there is always at least one root
+	if user.id == root.id && !req.rights.root {
+		return Err(Error::Request(
+			"cannot remove your own root rights".into(),
+		));
+	}
 
+	user.username =
+		check_username(&req.username, Some(&user.id), users).await?;
 	user.name = req.name;
+	user.rights = req.rights;
+
 	users
-		.update(&user.id, &user.name, req.password.as_deref())
+		.edit(&user.id, &user.username, &user.name, user.rights.clone())
 		.await?;
 
+	audit
+		.log(
+			Event::new(EventKind::UserEdited)
+				.user(&user)
+				.header(header)
+				.details(format!("by {}", root.username)),
+		)
+		.await;
+
 	Ok(user)
 }
 
+#[api(ResetPasswordReq)]
+async fn admin_reset_password(
+	id: PathParam<UniqueId>,
+	req: ResetPasswordReq,
+	header: &RequestHeader,
+	users: &Users,
+	audit: &Audit,
+) -> Result<()> {
+	let (session, root) = root_from_req(header, users).await?;
+
+	let user = users.by_id(&id).await?.ok_or(Error::NotFound)?;
+	users.check_policy(&user.username, &req.password)?;
+
+	users.set_password(&user.id, &req.password).await?;
+
+	// the old password might be known by someone else
+	if session.user_id == user.id {
+		users
+			.sessions_remove_others(&user.id, &session.token)
+			.await?;
+	} else {
+		users.sessions_remove_by_user(&user.id).await?;
+	}
+
+	audit
+		.log(
+			Event::new(EventKind::PasswordReset)
+				.user(&user)
+				.header(header)
+				.details(format!("by {}", root.username)),
+		)
+		.await;
+
+	Ok(())
+}
+
+#[api(DeleteUserReq)]
+async fn admin_delete_user(
+	id: PathParam<UniqueId>,
+	header: &RequestHeader,
+	users: &Users,
+	audit: &Audit,
+) -> Result<()> {
+	let (_, root) = root_from_req(header, users).await?;
+
+	let user = users.by_id(&id).await?.ok_or(Error::NotFound)?;
+	if user.id == root.id {
+		return Err(Error::Request("cannot delete yourself".into()));
+	}
+
+	users.delete(&user.id).await?;
+
+	audit
+		.log(
+			Event::new(EventKind::UserDeleted)
+				.user(&user)
+				.header(header)
+				.details(format!("by {}", root.username)),
+		)
+		.await;
+
+	Ok(())
+}
+
+#[api(InvitesReq)]
+async fn admin_invites(
+	header: &RequestHeader,
+	users: &Users,
+) -> Result<Invites> {
+	root_from_req(header, users).await?;
+
+	let mut list = users.invites().await?;
+	list.sort_by_key(|i| Reverse(i.created_on));
+
+	Ok(Invites { list })
+}
+
+#[api(CreateInviteReq)]
+async fn admin_create_invite(
+	req: CreateInviteReq,
+	header: &RequestHeader,
+	users: &Users,
+	audit: &Audit,
+) -> Result<NewInvite> {
+	let (_, root) = root_from_req(header, users).await?;
+
+	let now = DateTime::now();
+	let expires_on = req
+		.expires_on
+		.unwrap_or_else(|| now + Duration::from_secs(7 * 24 * 60 * 60));
+	if expires_on <= now {
+		return Err(Error::Request(
+			"expires on needs to be in the future".into(),
+		));
+	}
+
+	let (token, invite) = users
+		.invite_insert(req.name, req.rights, root.id, expires_on)
+		.await?;
+
+	audit
+		.log(
+			Event::new(EventKind::InviteCreated)
+				.user(&root)
+				.header(header)
+				.details(format!("invite {} for {}", invite.id, invite.name)),
+		)
+		.await;
+
+	Ok(NewInvite { token, invite })
+}
+
+#[api(DeleteInviteReq)]
+async fn admin_delete_invite(
+	id: PathParam<UniqueId>,
+	header: &RequestHeader,
+	users: &Users,
+	audit: &Audit,
+) -> Result<()> {
+	let (_, root) = root_from_req(header, users).await?;
+
+	if !users.invite_remove(&id).await? {
+		return Err(Error::NotFound);
+	}
+
+	audit
+		.log(
+			Event::new(EventKind::InviteDeleted)
+				.user(&root)
+				.header(header)
+				.details(format!("invite {}", *id)),
+		)
+		.await;
+
+	Ok(())
+}
+
+#[api(LockoutsReq)]
+async fn admin_lockouts(
+	header: &RequestHeader,
+	users: &Users,
+	limiter: &LoginLimiter,
+) -> Result<Lockouts> {
+	root_from_req(header, users).await?;
+
+	let mut list = limiter.all();
+	list.sort_by_key(|l| Reverse(l.last_failure));
+
+	Ok(Lockouts { list })
+}
+
+#[api(ClearLockoutReq)]
+async fn admin_clear_lockout(
+	req: ClearLockoutReq,
+	header: &RequestHeader,
+	users: &Users,
+	limiter: &LoginLimiter,
+	audit: &Audit,
+) -> Result<()> {
+	let (_, root) = root_from_req(header, users).await?;
+
+	if !limiter.clear(req.kind, &req.key) {
+		return Err(Error::NotFound);
+	}
+
+	audit
+		.log(
+			Event::new(EventKind::LockoutCleared)
+				.user(&root)
+				.header(header)
+				.details(format!("{:?} {}", req.kind, req.key)),
+		)
+		.await;
+
+	Ok(())
+}
+
+#[api(ImpersonateReq)]
+async fn admin_impersonate(
+	id: PathParam<UniqueId>,
+	header: &RequestHeader,
+	users: &Users,
+	audit: &Audit,
+) -> Result<Login> {
+	let (session, root) = root_from_req(header, users).await?;
+
+	if !session.is_fresh() {
+		return Err(Error::ReauthRequired);
+	}
+
+	let user = users.by_id(&id).await?.ok_or(Error::NotFound)?;
+	// another root user could do more than root is allowed to while
+	// impersonating
+	if user.rights.root {
+		return Err(Error::MissingRights);
+	}
+
+	let label = format!("impersonated by {}", root.username);
+	let session = users
+		.session_insert_impersonation(
+			user.id,
+			root.id,
+			new_device(header, Some(label)),
+		)
+		.await?;
+
+	audit
+		.log(
+			Event::new(EventKind::ImpersonationStarted)
+				.user(&root)
+				.header(header)
+				.details(format!("as {}", user.username)),
+		)
+		.await;
+
+	Ok(Login { user, session })
+}
+
+#[api(GroupsReq)]
+async fn admin_groups(header: &RequestHeader, users: &Users) -> Result<Groups> {
+	root_from_req(header, users).await?;
+
+	let mut list = users.groups().await?;
+	list.sort_by(|a, b| a.name.cmp(&b.name));
+
+	Ok(Groups { list })
+}
+
+fn check_group_name(name: &str) -> Result<String> {
+	let name = name.trim();
+	if name.is_empty() {
+		return Err(Error::Request("name cannot be empty".into()));
+	}
+
+	Ok(name.into())
+}
+
+#[api(CreateGroupReq)]
+async fn admin_create_group(
+	req: CreateGroupReq,
+	header: &RequestHeader,
+	users: &Users,
+	audit: &Audit,
+) -> Result<Group> {
+	let (_, root) = root_from_req(header, users).await?;
+
+	let group = users.group_insert(check_group_name(&req.name)?).await?;
+
+	audit
+		.log(
+			Event::new(EventKind::GroupCreated)
+				.user(&root)
+				.header(header)
+				.details(format!("group {} {}", group.id, group.name)),
+		)
+		.await;
+
+	Ok(group)
+}
+
+#[api(EditGroupReq)]
+async fn admin_edit_group(
+	id: PathParam<UniqueId>,
+	req: EditGroupReq,
+	header: &RequestHeader,
+	users: &Users,
+	audit: &Audit,
+) -> Result<Group> {
+	let (_, root) = root_from_req(header, users).await?;
+
+	let mut group = users.group_by_id(&id).await?.ok_or(Error::NotFound)?;
+	group.name = check_group_name(&req.name)?;
+
+	users.group_rename(&group.id, &group.name).await?;
+
+	audit
+		.log(
+			Event::new(EventKind::GroupEdited)
+				.user(&root)
+				.header(header)
+				.details(format!("group {} {}", group.id, group.name)),
+		)
+		.await;
+
+	Ok(group)
+}
+
+#[api(DeleteGroupReq)]
+async fn admin_delete_group(
+	id: PathParam<UniqueId>,
+	header: &RequestHeader,
+	users: &Users,
+	audit: &Audit,
+) -> Result<()> {
+	let (_, root) = root_from_req(header, users).await?;
+
+	if !users.group_remove(&id).await? {
+		return Err(Error::NotFound);
+	}
+
+	audit
+		.log(
+			Event::new(EventKind::GroupDeleted)
+				.user(&root)
+				.header(header)
+				.details(format!("group {}", *id)),
+		)
+		.await;
+
+	Ok(())
+}
+
+#[api(AddGroupMemberReq)]
+async fn admin_add_group_member(
+	id: PathParam<UniqueId>,
+	user_id: PathParam<UniqueId>,
+	header: &RequestHeader,
+	users: &Users,
+	audit: &Audit,
+) -> Result<Group> {
+	let (_, root) = root_from_req(header, users).await?;
+
+	let group = users.group_by_id(&id).await?.ok_or(Error::NotFound)?;
+	let user = users.by_id(&user_id).await?.ok_or(Error::NotFound)?;
+
+	if users.group_add_member(&group.id, &user.id).await? {
+		audit
+			.log(
+				Event::new(EventKind::GroupMemberAdded)
+					.user(&user)
+					.header(header)
+					.details(format!("to {} by {}", group.name, root.username)),
+			)
+			.await;
+	}
+
+	users.group_by_id(&group.id).await?.ok_or(Error::NotFound)
+}
+
+#[api(RemoveGroupMemberReq)]
+async fn admin_remove_group_member(
+	id: PathParam<UniqueId>,
+	user_id: PathParam<UniqueId>,
+	header: &RequestHeader,
+	users: &Users,
+	audit: &Audit,
+) -> Result<Group> {
+	let (_, root) = root_from_req(header, users).await?;
+
+	let group = users.group_by_id(&id).await?.ok_or(Error::NotFound)?;
+	let user = users.by_id(&user_id).await?.ok_or(Error::NotFound)?;
+
+	if !users.group_remove_member(&group.id, &user.id).await? {
+		return Err(Error::NotFound);
+	}
+
+	audit
+		.log(
+			Event::new(EventKind::GroupMemberRemoved)
+				.user(&user)
+				.header(header)
+				.details(format!("from {} by {}", group.name, root.username)),
+		)
+		.await;
+
+	users.group_by_id(&group.id).await?.ok_or(Error::NotFound)
+}
+
 pub fn add_routes(server: &mut Chuchi) {
 	server.add_route(login);
+	server.add_route(login_totp);
+	server.add_route(oidc_info);
+	server.add_route(oidc_start);
+	server.add_route(oidc_login);
+	server.add_route(proxy_login);
+	server.add_route(invite_info);
+	server.add_route(accept_invite);
 	server.add_route(login_by_token);
 	server.add_route(renew);
+	server.add_route(reauth);
 	server.add_route(logout);
+	server.add_route(devices);
+	server.add_route(revoke_device);
+	server.add_route(revoke_other_devices);
 	server.add_route(save);
+	server.add_route(change_password);
+	server.add_route(api_tokens);
+	server.add_route(create_api_token);
+	server.add_route(delete_api_token);
+	server.add_route(totp_status);
+	server.add_route(totp_enroll);
+	server.add_route(totp_verify);
+	server.add_route(totp_disable);
+	server.add_route(admin_users);
+	server.add_route(admin_create_user);
+	server.add_route(admin_edit_user);
+	server.add_route(admin_reset_password);
+	server.add_route(admin_delete_user);
+	server.add_route(admin_invites);
+	server.add_route(admin_create_invite);
+	server.add_route(admin_delete_invite);
+	server.add_route(admin_lockouts);
+	server.add_route(admin_clear_lockout);
+	server.add_route(admin_impersonate);
+	server.add_route(admin_groups);
+	server.add_route(admin_create_group);
+	server.add_route(admin_edit_group);
+	server.add_route(admin_delete_group);
+	server.add_route(admin_add_group_member);
+	server.add_route(admin_remove_group_member);
 }
@@ -0,0 +1,117 @@
+//! Backends which check a username and password
+//!
+//! The configured backends get asked in order until one of them accepts
+//! the login.
+
+use super::db::Users;
+use super::ldap::{Ldap, LdapError};
+use super::{User, UsersConf};
+
+use std::fmt;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendKind {
+	/// the password stored in the database
+	Local,
+	/// requires the `[users.ldap]` section
+	Ldap,
+}
+
+#[derive(Debug)]
+pub enum AuthError {
+	Database(chuchi_postgres::Error),
+	/// the backend could not be reached or failed otherwise, the next
+	/// backend still get's asked
+	Backend(String),
+}
+
+impl fmt::Display for AuthError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		fmt::Debug::fmt(self, f)
+	}
+}
+
+impl std::error::Error for AuthError {}
+
+impl From<chuchi_postgres::Error> for AuthError {
+	fn from(e: chuchi_postgres::Error) -> Self {
+		Self::Database(e)
+	}
+}
+
+impl From<LdapError> for AuthError {
+	fn from(e: LdapError) -> Self {
+		Self::Backend(e.to_string())
+	}
+}
+
+/// A user which entered the correct password
+#[derive(Debug, Clone)]
+pub struct Authenticated {
+	pub user: User,
+	/// the backend which accepted the password
+	pub backend: &'static str,
+	/// if the user did not exist before this login
+	pub created: bool,
+}
+
+#[async_trait]
+pub trait AuthBackend: fmt::Debug + Send + Sync {
+	fn name(&self) -> &'static str;
+
+	/// returns `None` if the user is not known or the password is incorrect
+	async fn authenticate(
+		&self,
+		username: &str,
+		password: &str,
+		users: &Users,
+	) -> Result<Option<Authenticated>, AuthError>;
+}
+
+/// Checks the password hash stored in the users table
+#[derive(Debug, Clone)]
+pub struct Local;
+
+#[async_trait]
+impl AuthBackend for Local {
+	fn name(&self) -> &'static str {
+		"local"
+	}
+
+	async fn authenticate(
+		&self,
+		username: &str,
+		password: &str,
+		users: &Users,
+	) -> Result<Option<Authenticated>, AuthError> {
+		let user = users.check_password(username, password).await?;
+
+		Ok(user.map(|user| Authenticated {
+			user,
+			backend: self.name(),
+			created: false,
+		}))
+	}
+}
+
+/// ## Panics
+/// if a backend is missing it's configuration
+pub fn backends(cfg: &UsersConf) -> Vec<Box<dyn AuthBackend>> {
+	cfg.backends
+		.iter()
+		.map(|kind| -> Box<dyn AuthBackend> {
+			match kind {
+				BackendKind::Local => Box::new(Local),
+				BackendKind::Ldap => Box::new(Ldap::new(
+					cfg.ldap
+						.clone()
+						.expect("the ldap backend requires [users.ldap]"),
+				)),
+			}
+		})
+		.collect()
+}
@@ -1,33 +1,249 @@
-use super::{Rights, Session, Timeout, Token, User};
+use super::auth::{self, AuthBackend, Authenticated};
+use super::password::{Hasher, Policy, PolicyError};
+use super::{
+	ApiToken, ApiTokenScope, Device, Group, Invite, Rights, Session,
+	SessionsConf, Timeout, Token, User, UsersConf,
+};
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::{Arc, RwLock};
+use std::time::Duration;
 
-use bcrypt::{hash, verify};
-use core_lib::ffi;
-use core_lib::users::db::UnsafeUser;
+use core_lib::users::db::{
+	hash_api_token, DbGroup, DbGroupMember, UnsafeApiToken, UnsafeUser,
+};
+use core_lib::{ffi, migration_files};
 
 use chuchi::Resource;
 use chuchi_postgres::json::Json;
 use chuchi_postgres::table::TableOwned;
+use chuchi_postgres::time::DateTime;
 use chuchi_postgres::{filter, row, try2, whr, Error};
-use chuchi_postgres::{Database, Result, UniqueId};
+use chuchi_postgres::{Database, FromRow, Result, TableTempl, ToRow, UniqueId};
+
+use tracing::error;
+
+const MIGRATIONS: &[(&str, &str)] = migration_files!(
+	"core-sessions-create",
+	"core-sessions-devices",
+	"core-users-totp",
+	"core-api-tokens-create",
+	"core-sessions-lifetime",
+	"core-sessions-verified",
+	"core-oidc-identities",
+	"core-invites-create",
+	"core-sessions-impersonation",
+	"core-groups-create",
+	"core-ldap-identities",
+);
+
+/// the channel on which the cli announces that it changed sessions in the
+/// database, see [`Users::sessions_notify`]
+pub const SESSIONS_CHANNEL: &str = "core_sessions";
+
+/// A session like it is stored in the database
+#[derive(Debug, TableTempl, FromRow, ToRow)]
+struct DbSession {
+	#[index(primary)]
+	token: Token,
+	#[index(unique)]
+	data_token: Token,
+	// secs since UNIX_EPOCH
+	timeout: i64,
+	// secs since UNIX_EPOCH, the timeout never get's extended past this
+	max_timeout: i64,
+	// secs
+	idle_secs: Option<i64>,
+	remember: bool,
+	/// when the password was entered the last time
+	verified_on: DateTime,
+	created_on: DateTime,
+	#[index(index)]
+	user_id: UniqueId,
+	#[index(unique)]
+	id: UniqueId,
+	label: Option<String>,
+	user_agent: Option<String>,
+	ip: Option<String>,
+	last_seen: DateTime,
+	impersonated_by: Option<UniqueId>,
+}
+
+impl DbSession {
+	fn from_entry(entry: &Entry) -> Self {
+		let Entry {
+			session: sess,
+			device,
+			..
+		} = entry;
+
+		Self {
+			token: sess.token.clone(),
+			data_token: sess.data_token.clone(),
+			timeout: sess.timeout.as_secs() as i64,
+			max_timeout: entry.max_timeout.as_secs() as i64,
+			idle_secs: entry.idle.map(|d| d.as_secs() as i64),
+			remember: entry.remember,
+			verified_on: entry.verified_on,
+			created_on: sess.created_on,
+			user_id: sess.user_id,
+			id: device.id,
+			label: device.label.clone(),
+			user_agent: device.user_agent.clone(),
+			ip: device.ip.clone(),
+			last_seen: device.last_seen,
+			impersonated_by: sess.impersonated_by,
+		}
+	}
+
+	fn into_entry(self, fresh_window: Duration) -> Option<Entry> {
+		Some(Entry {
+			session: Session {
+				token: self.token,
+				data_token: self.data_token,
+				timeout: Timeout::from_secs(self.timeout.try_into().ok()?)?,
+				created_on: self.created_on,
+				user_id: self.user_id,
+				fresh_until: self.verified_on + fresh_window,
+				impersonated_by: self.impersonated_by,
+			},
+			device: Device {
+				id: self.id,
+				label: self.label,
+				user_agent: self.user_agent,
+				ip: self.ip,
+				last_seen: self.last_seen,
+			},
+			max_timeout: Timeout::from_secs(self.max_timeout.try_into().ok()?)?,
+			idle: self
+				.idle_secs
+				.and_then(|s| s.try_into().ok())
+				.map(Duration::from_secs),
+			remember: self.remember,
+			verified_on: self.verified_on,
+		})
+	}
+}
+
+/// Links a user of an openid connect provider to a user
+#[derive(Debug, TableTempl, FromRow, ToRow)]
+struct OidcIdentity {
+	issuer: String,
+	subject: String,
+	#[index(index)]
+	user_id: UniqueId,
+	created_on: DateTime,
+}
+
+/// Links an entry of the ldap directory to a user
+#[derive(Debug, TableTempl, FromRow, ToRow)]
+struct LdapIdentity {
+	/// see [`LdapConf::id_attribute`](super::ldap::LdapConf::id_attribute)
+	#[index(primary)]
+	id: String,
+	#[index(unique)]
+	user_id: UniqueId,
+	created_on: DateTime,
+}
+
+/// An invite like it is stored in the database
+#[derive(Debug, TableTempl, FromRow, ToRow)]
+struct DbInvite {
+	#[index(primary)]
+	id: UniqueId,
+	/// see [`hash_api_token`]
+	#[index(unique)]
+	token_hash: String,
+	name: String,
+	rights: Json<Rights>,
+	created_by: UniqueId,
+	created_on: DateTime,
+	expires_on: DateTime,
+}
+
+impl From<DbInvite> for Invite {
+	fn from(i: DbInvite) -> Self {
+		Self {
+			id: i.id,
+			name: i.name,
+			rights: i.rights.0,
+			created_by: i.created_by,
+			created_on: i.created_on,
+			expires_on: i.expires_on,
+		}
+	}
+}
+
+/// The totp settings of a user
+#[derive(Debug, Clone)]
+pub struct UserTotp {
+	pub secret: Option<String>,
+	pub enabled: bool,
+	/// hashes of the unused recovery codes
+	pub recovery: Vec<String>,
+}
 
 #[derive(Debug, Clone, Resource)]
 pub struct Users {
 	table: TableOwned<UnsafeUser>,
+	sessions_table: TableOwned<DbSession>,
+	api_tokens: TableOwned<UnsafeApiToken>,
+	oidc_identities: TableOwned<OidcIdentity>,
+	ldap_identities: TableOwned<LdapIdentity>,
+	invites: TableOwned<DbInvite>,
+	groups: TableOwned<DbGroup>,
+	group_members: TableOwned<DbGroupMember>,
+	/// makes sure an invite can only be used once
+	invites_lock: Arc<tokio::sync::Mutex<()>>,
+	/// a cache of all sessions stored in the database, this allows apps
+	/// to lookup sessions without hitting the database
 	sessions: Sessions,
+	sessions_conf: SessionsConf,
+	/// asked in order on every login
+	backends: Arc<Vec<Box<dyn AuthBackend>>>,
+	hasher: Hasher,
+	policy: Policy,
 }
 
 impl Users {
-	pub async fn new(db: &Database) -> Self {
-		Self {
-			table: db.table_owned("users").create().await,
-			sessions: Sessions::new(),
+	/// ## Panics
+	/// if the tables could not be created, the sessions could not be
+	/// loaded or an authentication backend is not configured
+	pub async fn new(db: &Database, cfg: &UsersConf) -> Self {
+		let table = db.table_owned("users").create().await;
+
+		let migrations = db.migrations();
+		let mut conn = db.get().await.unwrap();
+
+		for (name, sql) in MIGRATIONS {
+			migrations
+				.add(&mut conn, name, sql)
+				.await
+				.expect("failed to run migration");
 		}
+
+		let this = Self {
+			table,
+			sessions_table: db.table_owned("sessions"),
+			api_tokens: db.table_owned("api_tokens"),
+			oidc_identities: db.table_owned("oidc_identities"),
+			ldap_identities: db.table_owned("ldap_identities"),
+			invites: db.table_owned("invites"),
+			groups: db.table_owned("groups"),
+			group_members: db.table_owned("group_members"),
+			invites_lock: Arc::new(tokio::sync::Mutex::new(())),
+			sessions: Sessions::new(),
+			sessions_conf: cfg.sessions.clone(),
+			backends: Arc::new(auth::backends(cfg)),
+			hasher: Hasher::new(&cfg.hashing),
+			policy: Policy::new(&cfg.password_policy),
+		};
+
+		this.sessions_load().await.expect("failed to load sessions");
+
+		this
 	}
 
-	#[allow(dead_code)]
 	pub async fn all(&self) -> Result<Vec<User>> {
 		let users = self.table.find_all().await?;
 
@@ -41,17 +257,66 @@ impl Users {
 			.map(|opt| opt.map(Into::into))
 	}
 
+	pub async fn by_username(&self, username: &str) -> Result<Option<User>> {
+		self.table
+			.find_one(filter!(&username))
+			.await
+			.map(|opt| opt.map(Into::into))
+	}
+
+	/// asks every authentication backend in order until one accepts the
+	/// password
+	///
+	/// a backend which fails only get's logged so the next one can still
+	/// accept the login
 	pub async fn login(
 		&self,
 		username: &str,
 		password: &str,
+	) -> Result<Option<Authenticated>> {
+		for backend in self.backends.iter() {
+			match backend.authenticate(username, password, self).await {
+				Ok(Some(authenticated)) => return Ok(Some(authenticated)),
+				Ok(None) => {}
+				Err(auth::AuthError::Database(e)) => return Err(e),
+				Err(auth::AuthError::Backend(e)) => {
+					error!("auth backend {} failed {e}", backend.name())
+				}
+			}
+		}
+
+		Ok(None)
+	}
+
+	/// checks the password stored in the database
+	pub async fn check_password(
+		&self,
+		username: &str,
+		password: &str,
 	) -> Result<Option<User>> {
 		let username = &username;
 		let user = try2!(self.table.find_one(filter!(username)).await?);
 
-		let passwd_corr = verify(password, &user.password).unwrap_or(false);
+		let verified = self.hasher.verify(password, &user.password);
+		if !verified.correct {
+			return Ok(None);
+		}
+
+		// bcrypt or outdated parameters get replaced while the password is
+		// known
+		if verified.needs_rehash {
+			if let Err(e) = self.set_password(&user.id, password).await {
+				error!("failed to rehash password of {} {e}", user.username);
+			}
+		}
+
+		Ok(Some(user.into()))
+	}
 
-		Ok(passwd_corr.then(|| user.into()))
+	fn hash(&self, password: &str) -> Result<String> {
+		self.hasher
+			.hash(password)
+			.map_err(|e| Error::Unknown(e.to_string().into()))
 	}
 
 	pub async fn insert(
@@ -63,11 +328,13 @@ impl Users {
 	) -> Result<User> {
 		let user = UnsafeUser {
 			id: UniqueId::new(),
-			password: hash(&password, 12)
-				.map_err(|e| Error::Unknown(e.into()))?,
+			password: self.hash(&password)?,
 			username,
 			name,
 			rights: Json(rights),
+			totp_secret: None,
+			totp_enabled: false,
+			totp_recovery: Json(vec![]),
 		};
 
 		self.table.insert_one(&user).await?;
@@ -75,24 +342,166 @@ impl Users {
 		Ok(user.into())
 	}
 
-	// the password will get hashed
-	pub async fn update(
+	pub async fn update(&self, id: &UniqueId, name: &str) -> Result<()> {
+		self.table.update(row! { &name }, whr!(id)).await
+	}
+
+	/// checks if a new password follows the configured policy
+	pub fn check_policy(
+		&self,
+		username: &str,
+		password: &str,
+	) -> std::result::Result<(), PolicyError> {
+		self.policy.check(username, password)
+	}
+
+	pub async fn edit(
 		&self,
 		id: &UniqueId,
+		username: &str,
 		name: &str,
-		password: Option<&str>,
+		rights: Rights,
 	) -> Result<()> {
-		if let Some(password) = password {
-			let password =
-				hash(password, 12).map_err(|e| Error::Unknown(e.into()))?;
-			self.table.update(row! { &name, &password }, whr!(id)).await
-		} else {
-			self.table.update(row! { &name }, whr!(id)).await
+		let rights = Json(rights);
+		self.table
+			.update(row! { &username, &name, &rights }, whr!(id))
+			.await
+	}
+
+	// the password will get hashed
+	pub async fn set_password(
+		&self,
+		id: &UniqueId,
+		password: &str,
+	) -> Result<()> {
+		let password = self.hash(password)?;
+		self.table.update(row! { &password }, whr!(id)).await
+	}
+
+	pub async fn totp(&self, id: &UniqueId) -> Result<Option<UserTotp>> {
+		self.table.find_one(filter!(id)).await.map(|opt| {
+			opt.map(|u| UserTotp {
+				secret: u.totp_secret,
+				enabled: u.totp_enabled,
+				recovery: u.totp_recovery.0,
+			})
+		})
+	}
+
+	/// sets a new secret which still needs to be verified before it gets
+	/// enabled, `None` disables totp
+	pub async fn totp_set_secret(
+		&self,
+		id: &UniqueId,
+		secret: Option<&str>,
+	) -> Result<()> {
+		let totp_secret = secret;
+		let totp_enabled = false;
+		let totp_recovery = Json(Vec::<String>::new());
+		self.table
+			.update(
+				row! { &totp_secret, &totp_enabled, &totp_recovery },
+				whr!(id),
+			)
+			.await
+	}
+
+	pub async fn totp_enable(
+		&self,
+		id: &UniqueId,
+		recovery: Vec<String>,
+	) -> Result<()> {
+		let totp_enabled = true;
+		let totp_recovery = Json(recovery);
+		self.table
+			.update(row! { &totp_enabled, &totp_recovery }, whr!(id))
+			.await
+	}
+
+	pub async fn totp_set_recovery(
+		&self,
+		id: &UniqueId,
+		recovery: Vec<String>,
+	) -> Result<()> {
+		let totp_recovery = Json(recovery);
+		self.table.update(row! { &totp_recovery }, whr!(id)).await
+	}
+
+	/// deletes the user and all of their sessions
+	pub async fn delete(&self, id: &UniqueId) -> Result<()> {
+		// the sessions get deleted by the foreign key, including the ones
+		// in which the user impersonated someone else
+		self.table.delete(whr!(id)).await?;
+		self.sessions.remove_by_user(id, None);
+		self.sessions.remove_impersonated_by(id);
+
+		Ok(())
+	}
+
+	/// fills the cache with all sessions which have not timed out
+	async fn sessions_load(&self) -> Result<()> {
+		let sessions = self.sessions_table.find_all().await?;
+		let fresh_window = self.sessions_conf.fresh_window();
+
+		for entry in sessions
+			.into_iter()
+			.filter_map(|s| s.into_entry(fresh_window))
+		{
+			if !entry.session.did_timeout() {
+				self.sessions.insert(entry);
+			}
 		}
+
+		Ok(())
 	}
 
-	pub fn sessions_cleanup(&self) {
+	/// tells a running server to drop the sessions which no longer exist
+	/// in the database
+	pub async fn sessions_notify(&self) -> Result<()> {
+		let conn = self.sessions_table.get_connection().await?;
+		conn.connection()
+			.execute("SELECT pg_notify($1, '')", &[&SESSIONS_CHANNEL])
+			.await?;
+
+		Ok(())
+	}
+
+	/// removes all sessions from the cache which where removed from the
+	/// database by someone else
+	pub async fn sessions_sync(&self) -> Result<()> {
+		self.sessions.sync_start();
+		let stored = self
+			.sessions_table
+			.find_all()
+			.await
+			.inspect_err(|_| self.sessions.sync_finish(None))?;
+
+		let stored = stored.into_iter().map(|s| s.token).collect();
+		self.sessions.sync_finish(Some(stored));
+
+		Ok(())
+	}
+
+	/// writes the last seen time and the extended timeout of all sessions
+	/// which where used since the last call to the database
+	pub async fn sessions_store_last_seen(&self) -> Result<()> {
+		for (token, last_seen, timeout) in self.sessions.take_seen() {
+			let timeout = timeout.as_secs() as i64;
+			self.sessions_table
+				.update(row! { last_seen, timeout }, whr!(&token))
+				.await?;
+		}
+
+		Ok(())
+	}
+
+	pub async fn sessions_cleanup(&self) -> Result<()> {
+		let now = Timeout::new(Duration::ZERO).as_secs() as i64;
+		self.sessions_table.delete(whr!("timeout" < &now)).await?;
+
 		self.sessions.cleanup();
+
+		Ok(())
 	}
 
 	pub async fn by_sess_token(
@@ -123,21 +532,519 @@ impl Users {
 		self.sessions.find_by_data(token)
 	}
 
-	pub fn session_insert(
+	/// creates a session for a user which just entered their password
+	///
+	/// if remember is true the session lasts longer and has no idle timeout
+	pub async fn session_insert(
 		&self,
 		user_id: UniqueId,
-		timeout: Timeout,
-	) -> Session {
-		self.sessions.insert(user_id, timeout)
+		remember: bool,
+		device: Device,
+	) -> Result<Session> {
+		self.session_insert_verified(user_id, remember, DateTime::now(), device)
+			.await
 	}
 
-	pub fn session_remove(&self, token: &Token) {
+	async fn session_insert_verified(
+		&self,
+		user_id: UniqueId,
+		remember: bool,
+		verified_on: DateTime,
+		device: Device,
+	) -> Result<Session> {
+		let lifetime = self.sessions_conf.lifetime(remember);
+		let fresh_until = verified_on + self.sessions_conf.fresh_window();
+		let max_timeout = Timeout::new(lifetime.max);
+		let timeout = match lifetime.idle {
+			Some(idle) => Timeout::new(idle).min(max_timeout.clone()),
+			None => max_timeout.clone(),
+		};
+
+		self.session_store(Entry {
+			session: Session::new(timeout, user_id, fresh_until),
+			device,
+			max_timeout,
+			idle: lifetime.idle,
+			remember: lifetime.remember,
+			verified_on,
+		})
+		.await
+	}
+
+	/// creates a session in which root sees what the user sees
+	///
+	/// the session can't be extended and never counts as fresh
+	pub async fn session_insert_impersonation(
+		&self,
+		user_id: UniqueId,
+		root_id: UniqueId,
+		device: Device,
+	) -> Result<Session> {
+		let fresh_window = self.sessions_conf.fresh_window();
+		let verified_on = DateTime::now() - fresh_window;
+		let timeout = Timeout::new(self.sessions_conf.impersonation_lifetime());
+
+		let mut session =
+			Session::new(timeout.clone(), user_id, verified_on + fresh_window);
+		session.impersonated_by = Some(root_id);
+
+		self.session_store(Entry {
+			session,
+			device,
+			max_timeout: timeout,
+			idle: None,
+			remember: false,
+			verified_on,
+		})
+		.await
+	}
+
+	async fn session_store(&self, entry: Entry) -> Result<Session> {
+		self.sessions_table
+			.insert_one(&DbSession::from_entry(&entry))
+			.await?;
+		let session = entry.session.clone();
+		self.sessions.insert(entry);
+
+		Ok(session)
+	}
+
+	pub async fn session_remove(&self, token: &Token) -> Result<()> {
+		self.sessions_table.delete(whr!(token)).await?;
 		self.sessions.remove(token);
+
+		Ok(())
+	}
+
+	pub fn session_device(&self, token: &Token) -> Option<Device> {
+		self.sessions.device(token)
+	}
+
+	/// replaces the session with a new one of the same kind
+	///
+	/// returns `None` if the session does not exist
+	pub async fn session_renew(
+		&self,
+		token: &Token,
+		device: Device,
+	) -> Result<Option<Session>> {
+		let Some(entry) = self.sessions.entry(token) else {
+			return Ok(None);
+		};
+
+		self.session_remove(token).await?;
+
+		self.session_store(entry.renewed(device)).await.map(Some)
+	}
+
+	/// marks that the user of the session entered their password again
+	///
+	/// returns `None` if the session does not exist
+	pub async fn session_verified(
+		&self,
+		token: &Token,
+	) -> Result<Option<Session>> {
+		let verified_on = DateTime::now();
+		let fresh_until = verified_on + self.sessions_conf.fresh_window();
+
+		self.sessions_table
+			.update(row! { verified_on }, whr!(token))
+			.await?;
+
+		Ok(self.sessions.verified(token, verified_on, fresh_until))
+	}
+
+	/// returns all sessions of a user with their device
+	pub fn sessions_by_user(
+		&self,
+		user_id: &UniqueId,
+	) -> Vec<(Session, Device)> {
+		self.sessions.by_user(user_id)
+	}
+
+	/// removes the session with the given device id
+	///
+	/// Returns false if the user does not have such a session
+	pub async fn session_remove_by_device(
+		&self,
+		user_id: &UniqueId,
+		id: &UniqueId,
+	) -> Result<bool> {
+		let Some(token) = self.sessions.token_by_device(user_id, id) else {
+			return Ok(false);
+		};
+
+		self.session_remove(&token).await?;
+
+		Ok(true)
+	}
+
+	/// removes all sessions of the user except the one with the given token
+	pub async fn sessions_remove_others(
+		&self,
+		user_id: &UniqueId,
+		token: &Token,
+	) -> Result<()> {
+		self.sessions_table
+			.delete(whr!(user_id AND "token" != token))
+			.await?;
+		self.sessions.remove_by_user(user_id, Some(token));
+
+		Ok(())
+	}
+
+	/// removes all sessions of the user
+	pub async fn sessions_remove_by_user(
+		&self,
+		user_id: &UniqueId,
+	) -> Result<()> {
+		self.sessions_table.delete(whr!(user_id)).await?;
+		self.sessions.remove_by_user(user_id, None);
+
+		Ok(())
+	}
+
+	pub async fn api_tokens_by_user(
+		&self,
+		user_id: &UniqueId,
+	) -> Result<Vec<ApiToken>> {
+		let tokens = self.api_tokens.find_many(filter!(user_id)).await?;
+
+		Ok(tokens.into_iter().map(Into::into).collect())
+	}
+
+	/// returns the token which should only be shown once, only it's hash
+	/// is stored
+	pub async fn api_token_insert(
+		&self,
+		user_id: UniqueId,
+		name: String,
+		scope: ApiTokenScope,
+		expires_on: Option<DateTime>,
+	) -> Result<(Token, ApiToken)> {
+		let token = Token::new();
+		let api_token = UnsafeApiToken {
+			id: UniqueId::new(),
+			user_id,
+			name,
+			token_hash: hash_api_token(&token),
+			scope: Json(scope),
+			expires_on,
+			created_on: DateTime::now(),
+		};
+
+		self.api_tokens.insert_one(&api_token).await?;
+
+		Ok((token, api_token.into()))
+	}
+
+	/// returns false if the user does not have such a token
+	pub async fn api_token_remove(
+		&self,
+		user_id: &UniqueId,
+		id: &UniqueId,
+	) -> Result<bool> {
+		let exists =
+			self.api_tokens.count("id", filter!(id AND user_id)).await?;
+		if exists == 0 {
+			return Ok(false);
+		}
+
+		self.api_tokens.delete(whr!(id AND user_id)).await?;
+
+		Ok(true)
+	}
+
+	pub async fn invites(&self) -> Result<Vec<Invite>> {
+		let invites = self.invites.find_all().await?;
+
+		Ok(invites.into_iter().map(Into::into).collect())
+	}
+
+	/// returns the token which should only be shown once, only it's hash
+	/// is stored
+	pub async fn invite_insert(
+		&self,
+		name: String,
+		rights: Rights,
+		created_by: UniqueId,
+		expires_on: DateTime,
+	) -> Result<(Token, Invite)> {
+		let token = Token::new();
+		let invite = DbInvite {
+			id: UniqueId::new(),
+			token_hash: hash_api_token(&token),
+			name,
+			rights: Json(rights),
+			created_by,
+			created_on: DateTime::now(),
+			expires_on,
+		};
+
+		self.invites.insert_one(&invite).await?;
+
+		Ok((token, invite.into()))
+	}
+
+	/// returns the invite if it exists and has not expired
+	pub async fn invite_by_token(
+		&self,
+		token: &Token,
+	) -> Result<Option<Invite>> {
+		let token_hash = hash_api_token(token);
+		let invite = try2!(self.invites.find_one(filter!(&token_hash)).await?);
+
+		Ok(Some(invite.into())
+			.filter(|i: &Invite| i.expires_on > DateTime::now()))
+	}
+
+	/// removes the invite and creates the user, returns `None` if the invite
+	/// does not exist or has expired
+	pub async fn invite_accept(
+		&self,
+		token: &Token,
+		username: String,
+		name: String,
+		password: String,
+	) -> Result<Option<(Invite, User)>> {
+		let _lock = self.invites_lock.lock().await;
+
+		let invite = try2!(self.invite_by_token(token).await?);
+		let id = &invite.id;
+		self.invites.delete(whr!(id)).await?;
+
+		let user = self
+			.insert(username, name, password, invite.rights.clone())
+			.await?;
+
+		Ok(Some((invite, user)))
+	}
+
+	/// returns false if the invite does not exist
+	pub async fn invite_remove(&self, id: &UniqueId) -> Result<bool> {
+		let exists = self.invites.count("id", filter!(id)).await?;
+		if exists == 0 {
+			return Ok(false);
+		}
+
+		self.invites.delete(whr!(id)).await?;
+
+		Ok(true)
+	}
+
+	pub async fn invites_cleanup(&self) -> Result<()> {
+		let now = DateTime::now();
+		self.invites.delete(whr!("expires_on" < &now)).await
+	}
+
+	pub async fn groups(&self) -> Result<Vec<Group>> {
+		let groups = self.groups.find_all().await?;
+
+		let mut members: HashMap<_, Vec<_>> = HashMap::new();
+		for member in self.group_members.find_all().await? {
+			members.entry(member.group_id).or_default().push(member);
+		}
+
+		Ok(groups
+			.into_iter()
+			.map(|group| {
+				let own = members.remove(&group.id).unwrap_or_default();
+				group.into_group(own)
+			})
+			.collect())
+	}
+
+	pub async fn group_by_id(&self, id: &UniqueId) -> Result<Option<Group>> {
+		let group = try2!(self.groups.find_one(filter!(id)).await?);
+		let group_id = id;
+		let members = self.group_members.find_many(filter!(group_id)).await?;
+
+		Ok(Some(group.into_group(members)))
+	}
+
+	pub async fn group_insert(&self, name: String) -> Result<Group> {
+		let group = DbGroup {
+			id: UniqueId::new(),
+			name,
+			created_on: DateTime::now(),
+		};
+
+		self.groups.insert_one(&group).await?;
+
+		Ok(group.into_group(vec![]))
+	}
+
+	pub async fn group_rename(&self, id: &UniqueId, name: &str) -> Result<()> {
+		self.groups.update(row! { &name }, whr!(id)).await
+	}
+
+	/// returns false if the group does not exist
+	pub async fn group_remove(&self, id: &UniqueId) -> Result<bool> {
+		let exists = self.groups.count("id", filter!(id)).await?;
+		if exists == 0 {
+			return Ok(false);
+		}
+
+		// the members get deleted by the foreign key
+		self.groups.delete(whr!(id)).await?;
+
+		Ok(true)
+	}
+
+	/// returns false if the user already is a member
+	pub async fn group_add_member(
+		&self,
+		group_id: &UniqueId,
+		user_id: &UniqueId,
+	) -> Result<bool> {
+		let exists = self
+			.group_members
+			.count("user_id", filter!(group_id AND user_id))
+			.await?;
+		if exists > 0 {
+			return Ok(false);
+		}
+
+		self.group_members
+			.insert_one(&DbGroupMember {
+				group_id: *group_id,
+				user_id: *user_id,
+				added_on: DateTime::now(),
+			})
+			.await?;
+
+		Ok(true)
+	}
+
+	/// returns false if the user was not a member
+	pub async fn group_remove_member(
+		&self,
+		group_id: &UniqueId,
+		user_id: &UniqueId,
+	) -> Result<bool> {
+		let exists = self
+			.group_members
+			.count("user_id", filter!(group_id AND user_id))
+			.await?;
+		if exists == 0 {
+			return Ok(false);
+		}
+
+		self.group_members
+			.delete(whr!(group_id AND user_id))
+			.await?;
+
+		Ok(true)
+	}
+
+	/// returns the user linked to the subject of the provider
+	pub async fn by_oidc_subject(
+		&self,
+		issuer: &str,
+		subject: &str,
+	) -> Result<Option<User>> {
+		let identity = try2!(
+			self.oidc_identities
+				.find_one(filter!(&issuer AND &subject))
+				.await?
+		);
+
+		self.by_id(&identity.user_id).await
+	}
+
+	/// returns true if the user is linked to a subject of the issuer
+	pub async fn oidc_linked(
+		&self,
+		issuer: &str,
+		user_id: &UniqueId,
+	) -> Result<bool> {
+		self.oidc_identities
+			.find_one(filter!(&issuer AND user_id))
+			.await
+			.map(|i| i.is_some())
+	}
+
+	pub async fn oidc_link(
+		&self,
+		issuer: String,
+		subject: String,
+		user_id: UniqueId,
+	) -> Result<()> {
+		self.oidc_identities
+			.insert_one(&OidcIdentity {
+				issuer,
+				subject,
+				user_id,
+				created_on: DateTime::now(),
+			})
+			.await
+	}
+
+	pub async fn by_ldap_id(&self, id: &str) -> Result<Option<User>> {
+		let identity =
+			try2!(self.ldap_identities.find_one(filter!(&id)).await?);
+
+		self.by_id(&identity.user_id).await
+	}
+
+	pub async fn ldap_link(&self, id: String, user_id: UniqueId) -> Result<()> {
+		self.ldap_identities
+			.insert_one(&LdapIdentity {
+				id,
+				user_id,
+				created_on: DateTime::now(),
+			})
+			.await
 	}
 
 	pub fn to_sessions_c(&self) -> ffi::c_sessions {
 		self.sessions.to_c()
 	}
+
+	pub fn to_sessions_v2_c(&self) -> ffi::c_sessions_v2 {
+		Sessions::to_c_v2()
+	}
+
+	pub fn to_sessions_v3_c(&self) -> ffi::c_sessions_v3 {
+		Sessions::to_c_v3()
+	}
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+	session: Session,
+	device: Device,
+	max_timeout: Timeout,
+	idle: Option<Duration>,
+	remember: bool,
+	verified_on: DateTime,
+}
+
+impl Entry {
+	/// a new session which replaces this one, it does not last longer than
+	/// the original one
+	fn renewed(&self, device: Device) -> Self {
+		let timeout = match self.idle {
+			Some(idle) => Timeout::new(idle).min(self.max_timeout.clone()),
+			None => self.max_timeout.clone(),
+		};
+
+		let mut session = Session::new(
+			timeout,
+			self.session.user_id,
+			self.session.fresh_until,
+		);
+		session.impersonated_by = self.session.impersonated_by;
+
+		Self {
+			session,
+			device,
+			max_timeout: self.max_timeout.clone(),
+			idle: self.idle,
+			remember: self.remember,
+			verified_on: self.verified_on,
+		}
+	}
 }
 
 #[derive(Debug, Clone)]
@@ -152,34 +1059,163 @@ impl Sessions {
 		}
 	}
 
+	/// marks the session as seen
 	pub fn find(&self, token: &Token) -> Option<Session> {
-		let reader = self.inner.read().unwrap();
-		reader.get(token).map(Clone::clone)
+		let entry = self.inner.read().unwrap().get(token).cloned()?;
+
+		Some(self.seen(entry))
 	}
 
+	/// marks the session as seen
 	pub fn find_by_data(&self, token: &Token) -> Option<Session> {
+		let entry = self.inner.read().unwrap().get_by_data(token).cloned()?;
+
+		Some(self.seen(entry))
+	}
+
+	pub fn device(&self, token: &Token) -> Option<Device> {
 		let reader = self.inner.read().unwrap();
-		reader.get_by_data(token).map(Clone::clone)
+		reader.get(token).map(|e| e.device.clone())
+	}
+
+	fn entry(&self, token: &Token) -> Option<Entry> {
+		self.inner.read().unwrap().get(token).cloned()
 	}
 
-	pub fn insert(&self, user_id: UniqueId, timeout: Timeout) -> Session {
+	pub fn verified(
+		&self,
+		token: &Token,
+		verified_on: DateTime,
+		fresh_until: DateTime,
+	) -> Option<Session> {
 		let mut writer = self.inner.write().unwrap();
+		let entry = writer.inner.get_mut(token)?;
+		entry.verified_on = verified_on;
+		entry.session.fresh_until = fresh_until;
 
-		let sess = Session::new(timeout, user_id);
+		Some(entry.session.clone())
+	}
+
+	pub fn by_user(&self, user_id: &UniqueId) -> Vec<(Session, Device)> {
+		let reader = self.inner.read().unwrap();
+		reader
+			.inner
+			.values()
+			.filter(|e| e.session.user_id == *user_id)
+			.filter(|e| !e.session.did_timeout())
+			.map(|e| (e.session.clone(), e.device.clone()))
+			.collect()
+	}
 
-		writer.insert(sess.token.clone(), sess.clone());
+	pub fn token_by_device(
+		&self,
+		user_id: &UniqueId,
+		id: &UniqueId,
+	) -> Option<Token> {
+		let reader = self.inner.read().unwrap();
+		reader
+			.inner
+			.values()
+			.find(|e| e.session.user_id == *user_id && e.device.id == *id)
+			.map(|e| e.session.token.clone())
+	}
+
+	pub fn insert(&self, entry: Entry) {
+		let mut writer = self.inner.write().unwrap();
 
-		sess
+		writer.insert(entry.session.token.clone(), entry);
 	}
 
 	pub fn remove(&self, token: &Token) {
 		self.inner.write().unwrap().remove(token);
 	}
 
+	/// removes all sessions of the user except the one with the given token
+	/// if any
+	pub fn remove_by_user(&self, user_id: &UniqueId, except: Option<&Token>) {
+		let mut writer = self.inner.write().unwrap();
+
+		let tokens: Vec<_> = writer
+			.inner
+			.values()
+			.map(|e| &e.session)
+			.filter(|s| s.user_id == *user_id)
+			.filter(|s| Some(&s.token) != except)
+			.map(|s| s.token.clone())
+			.collect();
+
+		for token in tokens {
+			writer.remove(&token);
+		}
+	}
+
+	/// removes all sessions in which the user impersonates someone else
+	pub fn remove_impersonated_by(&self, user_id: &UniqueId) {
+		let mut writer = self.inner.write().unwrap();
+
+		let tokens: Vec<_> = writer
+			.inner
+			.values()
+			.map(|e| &e.session)
+			.filter(|s| s.impersonated_by.as_ref() == Some(user_id))
+			.map(|s| s.token.clone())
+			.collect();
+
+		for token in tokens {
+			writer.remove(&token);
+		}
+	}
+
+	/// returns the session with the extended timeout
+	fn seen(&self, entry: Entry) -> Session {
+		let now = DateTime::now();
+		if now < entry.device.last_seen + LAST_SEEN_PRECISION {
+			return entry.session;
+		}
+
+		self.inner
+			.write()
+			.unwrap()
+			.seen(&entry.session.token, now)
+			.unwrap_or(entry.session)
+	}
+
+	/// returns all sessions which where seen since the last call
+	pub fn take_seen(&self) -> Vec<(Token, DateTime, Timeout)> {
+		self.inner.write().unwrap().take_seen()
+	}
+
 	pub fn cleanup(&self) {
 		self.inner.write().unwrap().cleanup();
 	}
 
+	/// sessions inserted from now on are kept by [`Self::sync_finish`]
+	fn sync_start(&self) {
+		self.inner.write().unwrap().syncing = Some(HashSet::new());
+	}
+
+	/// removes all sessions which are not stored, `None` aborts the sync
+	fn sync_finish(&self, stored: Option<HashSet<Token>>) {
+		let mut writer = self.inner.write().unwrap();
+		let Some(inserted) = writer.syncing.take() else {
+			return;
+		};
+		let Some(stored) = stored else {
+			return;
+		};
+
+		let removed: Vec<_> = writer
+			.inner
+			.keys()
+			.filter(|t| !stored.contains(*t) && !inserted.contains(*t))
+			.cloned()
+			.collect();
+
+		for token in removed {
+			writer.remove(&token);
+		}
+	}
+
 	fn into_ptr(self) -> *const u8 {
 		Arc::into_raw(self.inner) as *const _
 	}
@@ -202,33 +1238,17 @@ impl Sessions {
 		extern "C" fn by_token(
 			ctx: *const u8,
 			token: ffi::c_token,
-			session: *mut ffi::c_session,
+			session: *mut ffi::c_session_v1,
 		) -> bool {
-			let sessions = unsafe { Sessions::clone_from_ptr(ctx) };
-
-			match sessions.find(&token.into_token()) {
-				Some(sess) => {
-					unsafe { session.write(sess.into_c()) };
-					true
-				}
-				None => false,
-			}
+			c_write(ctx, token, false, session, ffi::c_session_v1::from_session)
 		}
 
 		extern "C" fn by_data_token(
 			ctx: *const u8,
 			token: ffi::c_token,
-			session: *mut ffi::c_session,
+			session: *mut ffi::c_session_v1,
 		) -> bool {
-			let sessions = unsafe { Sessions::clone_from_ptr(ctx) };
-
-			match sessions.find_by_data(&token.into_token()) {
-				Some(sess) => {
-					unsafe { session.write(sess.into_c()) };
-					true
-				}
-				None => false,
-			}
+			c_write(ctx, token, true, session, ffi::c_session_v1::from_session)
 		}
 
 		extern "C" fn free(ctx: *const u8) {
@@ -242,12 +1262,93 @@ impl Sessions {
 			free,
 		}
 	}
+
+	/// the lookups get called with the ctx of [`Self::to_c`]
+	fn to_c_v2() -> ffi::c_sessions_v2 {
+		extern "C" fn by_token(
+			ctx: *const u8,
+			token: ffi::c_token,
+			session: *mut ffi::c_session_v2,
+		) -> bool {
+			c_write(ctx, token, false, session, ffi::c_session_v2::from_session)
+		}
+
+		extern "C" fn by_data_token(
+			ctx: *const u8,
+			token: ffi::c_token,
+			session: *mut ffi::c_session_v2,
+		) -> bool {
+			c_write(ctx, token, true, session, ffi::c_session_v2::from_session)
+		}
+
+		ffi::c_sessions_v2 {
+			by_token,
+			by_data_token,
+		}
+	}
+
+	/// the lookups get called with the ctx of [`Self::to_c`]
+	fn to_c_v3() -> ffi::c_sessions_v3 {
+		extern "C" fn by_token(
+			ctx: *const u8,
+			token: ffi::c_token,
+			session: *mut ffi::c_session,
+		) -> bool {
+			c_write(ctx, token, false, session, |s| s)
+		}
+
+		extern "C" fn by_data_token(
+			ctx: *const u8,
+			token: ffi::c_token,
+			session: *mut ffi::c_session,
+		) -> bool {
+			c_write(ctx, token, true, session, |s| s)
+		}
+
+		ffi::c_sessions_v3 {
+			by_token,
+			by_data_token,
+		}
+	}
 }
 
+/// writes the session in the layout the app expects
+fn c_write<S>(
+	ctx: *const u8,
+	token: ffi::c_token,
+	data: bool,
+	session: *mut S,
+	conv: fn(ffi::c_session) -> S,
+) -> bool {
+	let sessions = unsafe { Sessions::clone_from_ptr(ctx) };
+	let token = token.into_token();
+	let sess = if data {
+		sessions.find_by_data(&token)
+	} else {
+		sessions.find(&token)
+	};
+
+	match sess {
+		Some(sess) => {
+			unsafe { session.write(conv(sess.into_c())) };
+			true
+		}
+		None => false,
+	}
+}
+
+/// how often the last seen time and the idle timeout of a session gets
+/// updated
+const LAST_SEEN_PRECISION: Duration = Duration::from_secs(60);
+
 #[derive(Debug)]
 struct Inner {
-	inner: HashMap<Token, Session>,
+	inner: HashMap<Token, Entry>,
 	data: HashMap<Token, Token>,
+	/// sessions which where seen but not yet stored
+	seen: HashSet<Token>,
+	/// sessions which where inserted while a sync is running
+	syncing: Option<HashSet<Token>>,
 }
 
 impl Inner {
@@ -255,36 +1356,68 @@ impl Inner {
 		Self {
 			inner: HashMap::new(),
 			data: HashMap::new(),
+			seen: HashSet::new(),
+			syncing: None,
 		}
 	}
 
-	fn get(&self, token: &Token) -> Option<&Session> {
-		self.inner.get(token).filter(|s| !s.did_timeout())
+	fn get(&self, token: &Token) -> Option<&Entry> {
+		self.inner.get(token).filter(|e| !e.session.did_timeout())
 	}
 
-	fn get_by_data(&self, token: &Token) -> Option<&Session> {
+	fn get_by_data(&self, token: &Token) -> Option<&Entry> {
 		self.data
 			.get(token)
 			.and_then(|t| self.inner.get(t))
-			.filter(|s| !s.did_timeout())
+			.filter(|e| !e.session.did_timeout())
 	}
 
-	fn insert(&mut self, token: Token, session: Session) {
-		self.data.insert(session.data_token.clone(), token.clone());
-		self.inner.insert(token, session);
+	fn insert(&mut self, token: Token, entry: Entry) {
+		if let Some(syncing) = &mut self.syncing {
+			syncing.insert(token.clone());
+		}
+		self.data
+			.insert(entry.session.data_token.clone(), token.clone());
+		self.inner.insert(token, entry);
 	}
 
 	fn remove(&mut self, token: &Token) {
-		let sess = self.inner.remove(token);
-		if let Some(sess) = sess {
-			self.data.remove(&sess.data_token);
+		let entry = self.inner.remove(token);
+		if let Some(entry) = entry {
+			self.data.remove(&entry.session.data_token);
+		}
+		self.seen.remove(token);
+	}
+
+	fn seen(&mut self, token: &Token, now: DateTime) -> Option<Session> {
+		let entry = self.inner.get_mut(token)?;
+		entry.device.last_seen = now;
+		if let Some(idle) = entry.idle {
+			entry.session.timeout =
+				Timeout::new(idle).min(entry.max_timeout.clone());
 		}
+		self.seen.insert(token.clone());
+
+		Some(entry.session.clone())
+	}
+
+	fn take_seen(&mut self) -> Vec<(Token, DateTime, Timeout)> {
+		self.seen
+			.drain()
+			.filter_map(|t| {
+				let entry = self.inner.get(&t)?;
+				let last_seen = entry.device.last_seen;
+				let timeout = entry.session.timeout.clone();
+				Some((t, last_seen, timeout))
+			})
+			.collect()
 	}
 
 	fn cleanup(&mut self) {
-		self.inner.retain(|_, s| {
-			if s.did_timeout() {
-				self.data.remove(&s.data_token);
+		self.inner.retain(|t, e| {
+			if e.session.did_timeout() {
+				self.data.remove(&e.session.data_token);
+				self.seen.remove(t);
 				false
 			} else {
 				true
@@ -292,3 +1425,82 @@ impl Inner {
 		});
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn device() -> Device {
+		Device {
+			id: UniqueId::new(),
+			label: None,
+			user_agent: None,
+			ip: None,
+			last_seen: DateTime::now(),
+		}
+	}
+
+	#[test]
+	fn renew_keeps_max_timeout() {
+		let max_timeout = Timeout::new(Duration::from_secs(60));
+		let mut session =
+			Session::new(max_timeout.clone(), UniqueId::new(), DateTime::now());
+		session.impersonated_by = Some(UniqueId::new());
+
+		let entry = Entry {
+			session,
+			device: device(),
+			max_timeout: max_timeout.clone(),
+			idle: Some(Duration::from_secs(60 * 60)),
+			remember: false,
+			verified_on: DateTime::now(),
+		};
+
+		let renewed = entry.renewed(device());
+		assert_ne!(renewed.session.token, entry.session.token);
+		assert_eq!(renewed.max_timeout, max_timeout);
+		assert_eq!(renewed.session.timeout, max_timeout);
+		assert_eq!(
+			renewed.session.impersonated_by,
+			entry.session.impersonated_by
+		);
+
+		// renewing again does not extend it either
+		let renewed = renewed.renewed(device());
+		assert_eq!(renewed.session.timeout, max_timeout);
+	}
+
+	#[test]
+	fn remove_impersonated_by() {
+		let timeout = Timeout::new(Duration::from_secs(60));
+		let root_id = UniqueId::new();
+		let entry = |impersonated_by| {
+			let mut session =
+				Session::new(timeout.clone(), UniqueId::new(), DateTime::now());
+			session.impersonated_by = impersonated_by;
+
+			Entry {
+				session,
+				device: device(),
+				max_timeout: timeout.clone(),
+				idle: None,
+				remember: false,
+				verified_on: DateTime::now(),
+			}
+		};
+
+		let sessions = Sessions::new();
+		let impersonated = entry(Some(root_id));
+		let other = entry(None);
+		let (imp_token, other_token) = (
+			impersonated.session.token.clone(),
+			other.session.token.clone(),
+		);
+		sessions.insert(impersonated);
+		sessions.insert(other);
+
+		sessions.remove_impersonated_by(&root_id);
+		assert!(sessions.find(&imp_token).is_none());
+		assert!(sessions.find(&other_token).is_some());
+	}
+}
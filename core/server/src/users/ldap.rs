@@ -0,0 +1,618 @@
+//! Login with the password of an LDAP directory
+//!
+//! The user get's searched with a service account (or anonymously) and then
+//! the password is checked by binding as the found entry. Users are linked
+//! to their entry by [`LdapConf::id_attribute`], an existing user which was
+//! not created through ldap is never used.
+
+use super::auth::{AuthBackend, AuthError, Authenticated};
+use super::db::Users;
+use super::{Rights, Token};
+
+use std::fmt;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use ldap3::{ldap_escape, LdapConnAsync, LdapConnSettings, Scope, SearchEntry};
+use serde::{Deserialize, Serialize};
+use tokio::time::timeout;
+use url::Url;
+
+/// how long a whole login may take
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+const INVALID_CREDENTIALS: u32 = 49;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct LdapConf {
+	/// `ldap://host:389` or `ldaps://host:636`
+	pub url: String,
+	/// upgrades an `ldap://` connection with StartTLS before any password
+	/// get's sent
+	///
+	/// only disable this if the directory is reached over a trusted network
+	#[serde(default = "default_true")]
+	pub start_tls: bool,
+	/// the account used to search for users, if not set the search is done
+	/// anonymously
+	#[serde(default)]
+	pub bind_dn: Option<String>,
+	#[serde(default)]
+	pub bind_password: Option<String>,
+	/// where users get searched
+	pub base_dn: String,
+	#[serde(default = "default_username_attribute")]
+	pub username_attribute: String,
+	#[serde(default = "default_name_attribute")]
+	pub name_attribute: String,
+	/// identifies an entry even if it get's renamed, if an entry does not
+	/// have it the dn is used
+	#[serde(default = "default_id_attribute")]
+	pub id_attribute: String,
+	/// if set only members of this group (a dn) can login
+	#[serde(default)]
+	pub member_of: Option<String>,
+	/// the rights of users which login the first time
+	#[serde(default = "default_rights")]
+	pub default_rights: Rights,
+}
+
+fn default_true() -> bool {
+	true
+}
+
+fn default_username_attribute() -> String {
+	"uid".into()
+}
+
+fn default_name_attribute() -> String {
+	"cn".into()
+}
+
+fn default_id_attribute() -> String {
+	"entryUUID".into()
+}
+
+fn default_rights() -> Rights {
+	Rights {
+		root: false,
+		apps: Some(vec![]),
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LdapError {
+	/// the directory could not be reached or returned something unexpected
+	Connection(String),
+	/// an operation failed with the given result code
+	Operation { code: u32, message: String },
+}
+
+impl fmt::Display for LdapError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		fmt::Debug::fmt(self, f)
+	}
+}
+
+impl std::error::Error for LdapError {}
+
+impl From<ldap3::LdapError> for LdapError {
+	fn from(e: ldap3::LdapError) -> Self {
+		match e {
+			ldap3::LdapError::LdapResult { result } => Self::Operation {
+				code: result.rc,
+				message: result.text,
+			},
+			e => Self::Connection(e.to_string()),
+		}
+	}
+}
+
+/// The user like the directory knows them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LdapUser {
+	/// the value of the id attribute or the dn
+	pub id: String,
+	pub dn: String,
+	pub username: String,
+	pub name: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Ldap {
+	cfg: LdapConf,
+}
+
+impl Ldap {
+	/// ## Panics
+	/// if the url is not valid
+	pub fn new(cfg: LdapConf) -> Self {
+		let url = Url::parse(&cfg.url).expect("invalid ldap url");
+		assert!(
+			matches!(url.scheme(), "ldap" | "ldaps"),
+			"ldap url needs to start with ldap:// or ldaps://"
+		);
+
+		Self { cfg }
+	}
+
+	/// returns the user if the password is correct
+	pub async fn lookup(
+		&self,
+		username: &str,
+		password: &str,
+	) -> Result<Option<LdapUser>, LdapError> {
+		// binding without a password is an anonymous bind which most
+		// directories accept
+		if password.is_empty() {
+			return Ok(None);
+		}
+
+		timeout(TIMEOUT, self.lookup_inner(username, password))
+			.await
+			.map_err(|_| LdapError::Connection("timed out".into()))?
+	}
+
+	async fn lookup_inner(
+		&self,
+		username: &str,
+		password: &str,
+	) -> Result<Option<LdapUser>, LdapError> {
+		let cfg = &self.cfg;
+
+		// ldaps:// ignores the starttls setting
+		let settings = LdapConnSettings::new()
+			.set_conn_timeout(TIMEOUT)
+			.set_starttls(cfg.start_tls);
+		let (conn, mut ldap) =
+			LdapConnAsync::with_settings(settings, &cfg.url).await?;
+		ldap3::drive!(conn);
+
+		if let Some(dn) = &cfg.bind_dn {
+			let bind_password = cfg.bind_password.as_deref().unwrap_or("");
+			ldap.simple_bind(dn, bind_password).await?.success()?;
+		}
+
+		let mut filter =
+			format!("({}={})", cfg.username_attribute, ldap_escape(username));
+		if let Some(group) = &cfg.member_of {
+			filter = format!("(&{filter}(memberOf={}))", ldap_escape(group));
+		}
+
+		let (mut entries, _) = ldap
+			.search(
+				&cfg.base_dn,
+				Scope::Subtree,
+				&filter,
+				[
+					&cfg.username_attribute,
+					&cfg.name_attribute,
+					&cfg.id_attribute,
+				],
+			)
+			.await?
+			.success()?;
+
+		// the username is unknown or ambiguous
+		if entries.len() != 1 {
+			let _ = ldap.unbind().await;
+			return Ok(None);
+		}
+		let entry = SearchEntry::construct(entries.pop().unwrap());
+
+		let res = ldap.simple_bind(&entry.dn, password).await?;
+		let _ = ldap.unbind().await;
+		if res.rc == INVALID_CREDENTIALS {
+			return Ok(None);
+		}
+		res.success()?;
+
+		let first = |attribute: &str| {
+			entry
+				.attrs
+				.iter()
+				.find(|(k, _)| k.eq_ignore_ascii_case(attribute))
+				.and_then(|(_, v)| v.first())
+				.map(String::as_str)
+		};
+
+		let username = first(&cfg.username_attribute)
+			.unwrap_or(username)
+			.to_lowercase();
+		let name = first(&cfg.name_attribute).unwrap_or(&username).to_string();
+		let id = first(&cfg.id_attribute).unwrap_or(&entry.dn).to_string();
+
+		Ok(Some(LdapUser {
+			id,
+			dn: entry.dn,
+			username,
+			name,
+		}))
+	}
+}
+
+#[async_trait]
+impl AuthBackend for Ldap {
+	fn name(&self) -> &'static str {
+		"ldap"
+	}
+
+	/// creates the user on the first login and keeps the name in sync with
+	/// the directory
+	async fn authenticate(
+		&self,
+		username: &str,
+		password: &str,
+		users: &Users,
+	) -> Result<Option<Authenticated>, AuthError> {
+		let Some(ldap_user) = self.lookup(username, password).await? else {
+			return Ok(None);
+		};
+
+		if let Some(mut user) = users.by_ldap_id(&ldap_user.id).await? {
+			if user.name != ldap_user.name {
+				users.update(&user.id, &ldap_user.name).await?;
+				user.name = ldap_user.name;
+			}
+
+			return Ok(Some(Authenticated {
+				user,
+				backend: self.name(),
+				created: false,
+			}));
+		}
+
+		// anyone with an entry in the directory could otherwise take over
+		// a local user, like root
+		if users.by_username(&ldap_user.username).await?.is_some() {
+			return Ok(None);
+		}
+
+		// the password is never used, the directory checks it
+		let user = users
+			.insert(
+				ldap_user.username,
+				ldap_user.name,
+				Token::new().to_string(),
+				self.cfg.default_rights.clone(),
+			)
+			.await?;
+		users.ldap_link(ldap_user.id, user.id).await?;
+
+		Ok(Some(Authenticated {
+			user,
+			backend: self.name(),
+			created: true,
+		}))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use bytes::BytesMut;
+	use lber::common::TagClass;
+	use lber::structure::{StructureTag, PL};
+	use lber::structures::{ASNTag, Enumerated, Integer};
+	use tokio::io::{AsyncReadExt, AsyncWriteExt};
+	use tokio::net::{TcpListener, TcpStream};
+
+	const ADMIN_DN: &str = "cn=admin,dc=example,dc=org";
+	const GROUP_DN: &str = "cn=alpenwind,ou=groups,dc=example,dc=org";
+
+	// protocol operations
+	const BIND_REQUEST: u64 = 0;
+	const BIND_RESPONSE: u64 = 1;
+	const SEARCH_REQUEST: u64 = 3;
+	const SEARCH_RESULT_ENTRY: u64 = 4;
+	const SEARCH_RESULT_DONE: u64 = 5;
+	const EXTENDED_REQUEST: u64 = 23;
+	const EXTENDED_RESPONSE: u64 = 24;
+
+	const PROTOCOL_ERROR: i64 = 2;
+
+	const FILTER_AND: u64 = 0;
+	const FILTER_EQUALITY: u64 = 3;
+
+	struct MockEntry {
+		dn: &'static str,
+		uid: &'static str,
+		cn: &'static str,
+		uuid: Option<&'static str>,
+		password: &'static str,
+		groups: &'static [&'static str],
+	}
+
+	const ENTRIES: &[MockEntry] = &[
+		MockEntry {
+			dn: "uid=alice,ou=people,dc=example,dc=org",
+			uid: "Alice",
+			cn: "Alice Muster",
+			uuid: Some("0e7d1b2c-alice"),
+			password: "alice-pw",
+			groups: &[GROUP_DN],
+		},
+		MockEntry {
+			dn: "uid=bob,ou=people,dc=example,dc=org",
+			uid: "bob",
+			cn: "Bob",
+			uuid: None,
+			password: "bob-pw",
+			groups: &[],
+		},
+	];
+
+	fn primitive(class: TagClass, id: u64, value: &[u8]) -> StructureTag {
+		StructureTag {
+			class,
+			id,
+			payload: PL::P(value.to_vec()),
+		}
+	}
+
+	fn constructed(
+		class: TagClass,
+		id: u64,
+		inner: Vec<StructureTag>,
+	) -> StructureTag {
+		StructureTag {
+			class,
+			id,
+			payload: PL::C(inner),
+		}
+	}
+
+	fn octets(value: &str) -> StructureTag {
+		primitive(TagClass::Universal, 4, value.as_bytes())
+	}
+
+	fn string(tag: StructureTag) -> String {
+		String::from_utf8(tag.expect_primitive().unwrap()).unwrap()
+	}
+
+	fn message(id: i64, op: StructureTag) -> BytesMut {
+		let id = Integer {
+			inner: id,
+			..Default::default()
+		}
+		.into_structure();
+
+		let mut buf = BytesMut::new();
+		lber::write::encode_into(
+			&mut buf,
+			constructed(TagClass::Universal, 16, vec![id, op]),
+		)
+		.unwrap();
+		buf
+	}
+
+	fn result(op: u64, code: i64) -> StructureTag {
+		let code = Enumerated {
+			inner: code,
+			..Default::default()
+		}
+		.into_structure();
+
+		constructed(
+			TagClass::Application,
+			op,
+			vec![code, octets(""), octets("")],
+		)
+	}
+
+	/// returns all attribute value pairs of equality filters
+	fn equalities(filter: StructureTag) -> Vec<(String, String)> {
+		match filter.id {
+			FILTER_AND => filter
+				.expect_constructed()
+				.unwrap()
+				.into_iter()
+				.flat_map(equalities)
+				.collect(),
+			FILTER_EQUALITY => {
+				let mut inner = filter.expect_constructed().unwrap();
+				let value = string(inner.pop().unwrap());
+				let attr = string(inner.pop().unwrap());
+				vec![(attr, value)]
+			}
+			_ => panic!("unexpected filter"),
+		}
+	}
+
+	fn matches(entry: &MockEntry, (attr, value): &(String, String)) -> bool {
+		match attr.as_str() {
+			"uid" => entry.uid.eq_ignore_ascii_case(value),
+			"memberOf" => entry.groups.contains(&value.as_str()),
+			_ => false,
+		}
+	}
+
+	/// returns the message id and the operation of the next message
+	async fn read_message(
+		stream: &mut TcpStream,
+		buf: &mut Vec<u8>,
+	) -> Option<(i64, StructureTag)> {
+		loop {
+			if let Ok((rest, tag)) = lber::parse::parse_tag(buf) {
+				let used = buf.len() - rest.len();
+				buf.drain(..used);
+
+				let mut inner = tag.expect_constructed()?.into_iter();
+				let id = inner.next()?.expect_primitive()?;
+				let id = id.iter().fold(0, |id, b| (id << 8) | *b as i64);
+				return Some((id, inner.next()?));
+			}
+
+			let mut chunk = [0; 4096];
+			match stream.read(&mut chunk).await {
+				Ok(0) | Err(_) => return None,
+				Ok(len) => buf.extend_from_slice(&chunk[..len]),
+			}
+		}
+	}
+
+	fn search(id: i64, op: StructureTag) -> BytesMut {
+		let mut inner = op.expect_constructed().unwrap().into_iter();
+		let filter = equalities(inner.nth(6).unwrap());
+
+		let mut resp = BytesMut::new();
+		for e in ENTRIES
+			.iter()
+			.filter(|e| filter.iter().all(|f| matches(e, f)))
+		{
+			let attr = |kind: &str, value: &str| {
+				constructed(
+					TagClass::Universal,
+					16,
+					vec![
+						octets(kind),
+						constructed(
+							TagClass::Universal,
+							17,
+							vec![octets(value)],
+						),
+					],
+				)
+			};
+
+			let mut attrs = vec![attr("uid", e.uid), attr("cn", e.cn)];
+			if let Some(uuid) = e.uuid {
+				attrs.push(attr("entryUUID", uuid));
+			}
+
+			let entry = constructed(
+				TagClass::Application,
+				SEARCH_RESULT_ENTRY,
+				vec![octets(e.dn), constructed(TagClass::Universal, 16, attrs)],
+			);
+			resp.extend(message(id, entry));
+		}
+		resp.extend(message(id, result(SEARCH_RESULT_DONE, 0)));
+
+		resp
+	}
+
+	/// a directory which knows the entries above and the admin account
+	///
+	/// it does not support StartTLS
+	async fn mock_directory() -> String {
+		let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let addr = listener.local_addr().unwrap();
+
+		tokio::spawn(async move {
+			loop {
+				let (mut stream, _) = listener.accept().await.unwrap();
+				tokio::spawn(async move {
+					let mut buf = vec![];
+					while let Some((id, op)) =
+						read_message(&mut stream, &mut buf).await
+					{
+						let resp = match op.id {
+							BIND_REQUEST => {
+								let mut inner =
+									op.expect_constructed().unwrap();
+								let pw = string(inner.pop().unwrap());
+								let dn = string(inner.pop().unwrap());
+
+								let correct = (dn == ADMIN_DN
+									&& pw == "admin-pw") || ENTRIES
+									.iter()
+									.any(|e| e.dn == dn && e.password == pw);
+								let code = if correct {
+									0
+								} else {
+									INVALID_CREDENTIALS as i64
+								};
+								message(id, result(BIND_RESPONSE, code))
+							}
+							SEARCH_REQUEST => search(id, op),
+							// StartTLS is the only extended operation
+							EXTENDED_REQUEST => message(
+								id,
+								result(EXTENDED_RESPONSE, PROTOCOL_ERROR),
+							),
+							_ => break,
+						};
+
+						stream.write_all(&resp).await.unwrap();
+					}
+				});
+			}
+		});
+
+		format!("ldap://{addr}")
+	}
+
+	fn conf(url: String) -> LdapConf {
+		LdapConf {
+			url,
+			start_tls: false,
+			bind_dn: Some(ADMIN_DN.into()),
+			bind_password: Some("admin-pw".into()),
+			base_dn: "ou=people,dc=example,dc=org".into(),
+			username_attribute: default_username_attribute(),
+			name_attribute: default_name_attribute(),
+			id_attribute: default_id_attribute(),
+			member_of: None,
+			default_rights: default_rights(),
+		}
+	}
+
+	#[tokio::test]
+	async fn lookup() {
+		let ldap = Ldap::new(conf(mock_directory().await));
+
+		let alice = ldap.lookup("alice", "alice-pw").await.unwrap().unwrap();
+		assert_eq!(
+			alice,
+			LdapUser {
+				id: "0e7d1b2c-alice".into(),
+				dn: "uid=alice,ou=people,dc=example,dc=org".into(),
+				username: "alice".into(),
+				name: "Alice Muster".into(),
+			}
+		);
+
+		assert_eq!(ldap.lookup("alice", "bob-pw").await.unwrap(), None);
+		assert_eq!(ldap.lookup("alice", "").await.unwrap(), None);
+		assert_eq!(ldap.lookup("carol", "alice-pw").await.unwrap(), None);
+
+		// without an id attribute the dn is used
+		let bob = ldap.lookup("bob", "bob-pw").await.unwrap().unwrap();
+		assert_eq!(bob.id, bob.dn);
+	}
+
+	#[tokio::test]
+	async fn member_of_and_service_account() {
+		let url = mock_directory().await;
+
+		let mut cfg = conf(url.clone());
+		cfg.member_of = Some(GROUP_DN.into());
+		let ldap = Ldap::new(cfg);
+		assert!(ldap.lookup("alice", "alice-pw").await.unwrap().is_some());
+		assert_eq!(ldap.lookup("bob", "bob-pw").await.unwrap(), None);
+
+		let mut cfg = conf(url);
+		cfg.bind_password = Some("wrong".into());
+		let ldap = Ldap::new(cfg);
+		assert!(matches!(
+			ldap.lookup("alice", "alice-pw").await,
+			Err(LdapError::Operation {
+				code: INVALID_CREDENTIALS,
+				..
+			})
+		));
+	}
+
+	#[tokio::test]
+	async fn requires_start_tls() {
+		let mut cfg = conf(mock_directory().await);
+		cfg.start_tls = true;
+		let ldap = Ldap::new(cfg);
+
+		// no password get's sent if the upgrade fails
+		assert!(ldap.lookup("alice", "alice-pw").await.is_err());
+	}
+}
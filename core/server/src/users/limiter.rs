@@ -0,0 +1,261 @@
+//! Throttles failed logins per username and per client ip
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chuchi::Resource;
+use chuchi_postgres::time::DateTime;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct LoginLimitsConf {
+	/// failed attempts for a username until it get's locked
+	pub max_attempts: u32,
+	/// failed attempts from an ip until it get's locked
+	///
+	/// this should be higher than `max-attempts` since a whole household
+	/// might share the same ip
+	pub ip_max_attempts: u32,
+	/// secs to wait after the first failed attempt, doubles with every
+	/// further failed attempt
+	pub delay: u64,
+	/// secs
+	pub max_delay: u64,
+	/// secs a lockout lasts, failed attempts are also forgotten after this
+	/// time
+	pub lockout: u64,
+}
+
+impl Default for LoginLimitsConf {
+	fn default() -> Self {
+		Self {
+			max_attempts: 5,
+			ip_max_attempts: 20,
+			delay: 1,
+			max_delay: 60,
+			lockout: 15 * 60,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum LimitKind {
+	Username,
+	Ip,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Key {
+	kind: LimitKind,
+	key: String,
+}
+
+impl Key {
+	fn username(username: &str) -> Self {
+		Self {
+			kind: LimitKind::Username,
+			key: username.trim().to_lowercase(),
+		}
+	}
+
+	fn ip(ip: IpAddr) -> Self {
+		Self {
+			kind: LimitKind::Ip,
+			key: ip.to_string(),
+		}
+	}
+}
+
+#[derive(Debug, Clone)]
+struct Attempts {
+	failures: u32,
+	last_failure: DateTime,
+}
+
+impl Attempts {
+	/// returns when the next attempt is allowed
+	fn retry_at(&self, max_attempts: u32, cfg: &LoginLimitsConf) -> DateTime {
+		if self.failures >= max_attempts {
+			return self.last_failure + Duration::from_secs(cfg.lockout);
+		}
+
+		let delay = cfg
+			.delay
+			.saturating_mul(1 << (self.failures.saturating_sub(1)).min(32))
+			.min(cfg.max_delay);
+		self.last_failure + Duration::from_secs(delay)
+	}
+
+	fn is_locked(&self, max_attempts: u32) -> bool {
+		self.failures >= max_attempts
+	}
+
+	fn expired(&self, now: DateTime, cfg: &LoginLimitsConf) -> bool {
+		now > self.last_failure + Duration::from_secs(cfg.lockout)
+	}
+}
+
+/// A username or ip which had failed login attempts
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Lockout {
+	pub kind: LimitKind,
+	pub key: String,
+	pub failures: u32,
+	pub last_failure: DateTime,
+	/// the next login attempt is not allowed before this
+	pub retry_at: DateTime,
+	/// if the maximum attempts were reached
+	pub locked: bool,
+}
+
+#[derive(Clone, Resource)]
+pub struct LoginLimiter {
+	cfg: LoginLimitsConf,
+	inner: Arc<Mutex<HashMap<Key, Attempts>>>,
+}
+
+impl LoginLimiter {
+	pub fn new(cfg: LoginLimitsConf) -> Self {
+		Self {
+			cfg,
+			inner: Arc::new(Mutex::new(HashMap::new())),
+		}
+	}
+
+	fn max_attempts(&self, kind: LimitKind) -> u32 {
+		match kind {
+			LimitKind::Username => self.cfg.max_attempts,
+			LimitKind::Ip => self.cfg.ip_max_attempts,
+		}
+	}
+
+	/// returns `Err(retry_at)` if a login is not allowed right now
+	pub fn check(&self, username: &str, ip: IpAddr) -> Result<(), DateTime> {
+		self.check_at(username, ip, DateTime::now())
+	}
+
+	fn check_at(
+		&self,
+		username: &str,
+		ip: IpAddr,
+		now: DateTime,
+	) -> Result<(), DateTime> {
+		let inner = self.inner.lock().unwrap();
+
+		let retry_at = [Key::username(username), Key::ip(ip)]
+			.into_iter()
+			.filter_map(|k| {
+				let attempts = inner.get(&k)?;
+				Some(attempts.retry_at(self.max_attempts(k.kind), &self.cfg))
+			})
+			.max();
+
+		match retry_at {
+			Some(retry_at) if retry_at > now => Err(retry_at),
+			_ => Ok(()),
+		}
+	}
+
+	pub fn failed(&self, username: &str, ip: IpAddr) {
+		self.failed_at(username, ip, DateTime::now())
+	}
+
+	fn failed_at(&self, username: &str, ip: IpAddr, now: DateTime) {
+		let mut inner = self.inner.lock().unwrap();
+		inner.retain(|_, a| !a.expired(now, &self.cfg));
+
+		for key in [Key::username(username), Key::ip(ip)] {
+			let attempts = inner.entry(key).or_insert(Attempts {
+				failures: 0,
+				last_failure: now,
+			});
+			attempts.failures += 1;
+			attempts.last_failure = now;
+		}
+	}
+
+	/// a successfull login resets the attempts of the username
+	///
+	/// the ip is not reset, else an attacker with a valid account could
+	/// keep guessing other accounts
+	pub fn succeeded(&self, username: &str) {
+		self.inner.lock().unwrap().remove(&Key::username(username));
+	}
+
+	/// returns all usernames and ips with failed attempts
+	pub fn all(&self) -> Vec<Lockout> {
+		let now = DateTime::now();
+		let inner = self.inner.lock().unwrap();
+
+		inner
+			.iter()
+			.filter(|(_, a)| !a.expired(now, &self.cfg))
+			.map(|(k, a)| {
+				let max = self.max_attempts(k.kind);
+				Lockout {
+					kind: k.kind,
+					key: k.key.clone(),
+					failures: a.failures,
+					last_failure: a.last_failure,
+					retry_at: a.retry_at(max, &self.cfg),
+					locked: a.is_locked(max),
+				}
+			})
+			.collect()
+	}
+
+	/// returns false if there were no failed attempts
+	pub fn clear(&self, kind: LimitKind, key: &str) -> bool {
+		let key = match kind {
+			LimitKind::Username => Key::username(key),
+			LimitKind::Ip => Key {
+				kind,
+				key: key.to_string(),
+			},
+		};
+
+		self.inner.lock().unwrap().remove(&key).is_some()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn delay_and_lockout() {
+		let limiter = LoginLimiter::new(LoginLimitsConf::default());
+		let ip: IpAddr = "127.0.0.1".parse().unwrap();
+		let start = DateTime::from_secs(1_000_000);
+		let secs = |s| start + Duration::from_secs(s);
+
+		assert!(limiter.check_at("alice", ip, start).is_ok());
+
+		limiter.failed_at("Alice", ip, start);
+		assert_eq!(limiter.check_at("alice", ip, start), Err(secs(1)));
+		assert!(limiter.check_at("alice", ip, secs(1)).is_ok());
+
+		limiter.failed_at("alice", ip, secs(1));
+		assert_eq!(limiter.check_at("alice", ip, secs(1)), Err(secs(3)));
+
+		for i in 2..5 {
+			limiter.failed_at("alice", ip, secs(i * 100));
+		}
+		// locked
+		assert_eq!(
+			limiter.check_at("alice", ip, secs(401)),
+			Err(secs(400 + 15 * 60))
+		);
+		// the ip is only delayed
+		assert_eq!(limiter.check_at("bob", ip, secs(400)), Err(secs(416)));
+
+		limiter.succeeded("alice");
+		assert!(limiter.check_at("alice", ip, secs(416)).is_ok());
+	}
+}
@@ -0,0 +1,10 @@
+CREATE TABLE api_tokens (
+    id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    token_hash TEXT NOT NULL UNIQUE,
+    scope JSON NOT NULL,
+    expires_on TIMESTAMP,
+    created_on TIMESTAMP NOT NULL
+);
+CREATE INDEX idx_api_tokens_user_id ON api_tokens (user_id);
@@ -0,0 +1,9 @@
+CREATE TABLE invites (
+    id TEXT PRIMARY KEY,
+    token_hash TEXT NOT NULL UNIQUE,
+    name TEXT NOT NULL,
+    rights JSON NOT NULL,
+    created_by TEXT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    created_on TIMESTAMP NOT NULL,
+    expires_on TIMESTAMP NOT NULL
+);
@@ -0,0 +1,5 @@
+CREATE TABLE ldap_identities (
+    id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL UNIQUE REFERENCES users(id) ON DELETE CASCADE,
+    created_on TIMESTAMP NOT NULL
+);
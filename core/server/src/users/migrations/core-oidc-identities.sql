@@ -0,0 +1,8 @@
+CREATE TABLE oidc_identities (
+    issuer TEXT NOT NULL,
+    subject TEXT NOT NULL,
+    user_id TEXT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    created_on TIMESTAMP NOT NULL,
+    PRIMARY KEY (issuer, subject)
+);
+CREATE INDEX idx_oidc_identities_user_id ON oidc_identities (user_id);
@@ -0,0 +1,10 @@
+CREATE TABLE sessions (
+    token TEXT PRIMARY KEY,
+    data_token TEXT NOT NULL UNIQUE,
+    timeout BIGINT NOT NULL,
+    created_on TIMESTAMP NOT NULL,
+    user_id TEXT NOT NULL REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_sessions_user_id ON sessions (user_id);
+CREATE INDEX idx_sessions_timeout ON sessions (timeout);
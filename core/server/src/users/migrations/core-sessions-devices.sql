@@ -0,0 +1,19 @@
+ALTER TABLE sessions
+    ADD COLUMN id TEXT,
+    ADD COLUMN label TEXT,
+    ADD COLUMN user_agent TEXT,
+    ADD COLUMN ip TEXT,
+    ADD COLUMN last_seen TIMESTAMP;
+
+-- existing sessions get a random id in the same format as UniqueId
+UPDATE sessions SET
+    id = rtrim(translate(encode(
+        substr(decode(md5(random()::text || token), 'hex'), 1, 10),
+        'base64'
+    ), '+/', '-_'), '='),
+    last_seen = created_on;
+
+ALTER TABLE sessions
+    ALTER COLUMN id SET NOT NULL,
+    ALTER COLUMN last_seen SET NOT NULL,
+    ADD UNIQUE (id);
@@ -0,0 +1,2 @@
+ALTER TABLE sessions
+    ADD COLUMN impersonated_by TEXT REFERENCES users(id) ON DELETE CASCADE;
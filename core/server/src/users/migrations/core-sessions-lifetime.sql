@@ -0,0 +1,17 @@
+ALTER TABLE sessions
+    ADD COLUMN max_timeout BIGINT,
+    ADD COLUMN idle_secs BIGINT,
+    ADD COLUMN remember BOOLEAN NOT NULL DEFAULT TRUE;
+
+-- sessions used to be created with a timeout of 60 years instead of half a
+-- year, existing sessions are kept as remembered sessions of half a year
+UPDATE sessions SET
+    max_timeout = LEAST(
+        timeout,
+        EXTRACT(EPOCH FROM created_on)::BIGINT + 183 * 24 * 60 * 60
+    );
+UPDATE sessions SET timeout = max_timeout;
+
+ALTER TABLE sessions
+    ALTER COLUMN max_timeout SET NOT NULL,
+    ALTER COLUMN remember DROP DEFAULT;
@@ -0,0 +1,5 @@
+ALTER TABLE sessions ADD COLUMN verified_on TIMESTAMP;
+
+UPDATE sessions SET verified_on = created_on;
+
+ALTER TABLE sessions ALTER COLUMN verified_on SET NOT NULL;
@@ -0,0 +1,5 @@
+-- new installs already get these columns from UnsafeUser
+ALTER TABLE users
+    ADD COLUMN IF NOT EXISTS totp_secret TEXT,
+    ADD COLUMN IF NOT EXISTS totp_enabled BOOLEAN NOT NULL DEFAULT FALSE,
+    ADD COLUMN IF NOT EXISTS totp_recovery JSON NOT NULL DEFAULT '[]';
@@ -1,12 +1,158 @@
 pub mod api;
 pub mod api_routes;
+pub mod auth;
 pub mod db;
+pub mod ldap;
+pub mod limiter;
+pub mod oidc;
+pub mod password;
+pub mod proxy;
+pub mod totp;
 
-pub use core_lib::users::{Rights, Session, Timeout, Token, User};
+pub use core_lib::users::{
+	ApiToken, ApiTokenScope, Group, Rights, Session, Timeout, Token, User,
+};
+
+use core_lib::config::DbConf;
 
 use chuchi::resources::Resources;
+use chuchi_postgres::time::DateTime;
+use chuchi_postgres::UniqueId;
+use futures_util::{stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
 use tokio::task::JoinHandle;
 use tokio::time::{self, Duration};
+use tokio_postgres::{AsyncMessage, NoTls};
+
+use tracing::error;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct UsersConf {
+	pub login_limits: limiter::LoginLimitsConf,
+	pub sessions: SessionsConf,
+	/// the parameters of new password hashes
+	pub hashing: password::HashingConf,
+	/// the rules new passwords need to follow
+	pub password_policy: password::PolicyConf,
+	/// the backends which check the password of a login, in the order they
+	/// get asked
+	pub backends: Vec<auth::BackendKind>,
+	pub ldap: Option<ldap::LdapConf>,
+	/// allows to login with an openid connect provider
+	pub oidc: Option<oidc::OidcConf>,
+	/// trusts the username set by an authenticating reverse proxy
+	pub proxy: Option<proxy::ProxyConf>,
+}
+
+impl Default for UsersConf {
+	fn default() -> Self {
+		Self {
+			login_limits: Default::default(),
+			sessions: Default::default(),
+			hashing: Default::default(),
+			password_policy: Default::default(),
+			backends: vec![auth::BackendKind::Local],
+			ldap: None,
+			oidc: None,
+			proxy: None,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct SessionsConf {
+	/// secs a session lasts at most
+	pub lifetime: u64,
+	/// secs a session lasts if the user chose to be remembered at login
+	pub remember_lifetime: u64,
+	/// secs after which an unused session expires, every use extends it
+	///
+	/// does not apply to remembered sessions, 0 disables it
+	pub idle_timeout: u64,
+	/// secs after entering the password in which apps allow sensitive
+	/// actions, after that the password needs to be entered again
+	pub fresh_window: u64,
+	/// secs root can impersonate another user
+	pub impersonation_lifetime: u64,
+}
+
+impl Default for SessionsConf {
+	fn default() -> Self {
+		Self {
+			lifetime: 7 * 24 * 60 * 60,
+			remember_lifetime: 180 * 24 * 60 * 60,
+			idle_timeout: 24 * 60 * 60,
+			fresh_window: 15 * 60,
+			impersonation_lifetime: 60 * 60,
+		}
+	}
+}
+
+impl SessionsConf {
+	pub fn fresh_window(&self) -> Duration {
+		Duration::from_secs(self.fresh_window)
+	}
+
+	pub fn impersonation_lifetime(&self) -> Duration {
+		Duration::from_secs(self.impersonation_lifetime)
+	}
+
+	pub fn lifetime(&self, remember: bool) -> Lifetime {
+		if remember {
+			Lifetime {
+				max: Duration::from_secs(self.remember_lifetime),
+				idle: None,
+				remember,
+			}
+		} else {
+			Lifetime {
+				max: Duration::from_secs(self.lifetime),
+				idle: Some(Duration::from_secs(self.idle_timeout))
+					.filter(|d| !d.is_zero()),
+				remember,
+			}
+		}
+	}
+}
+
+/// How long a new session lasts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Lifetime {
+	pub max: Duration,
+	/// the session expires if it is not used for this long
+	pub idle: Option<Duration>,
+	pub remember: bool,
+}
+
+/// The device which created a session
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Device {
+	/// identifies the session without exposing it's token
+	pub id: UniqueId,
+	/// a name the client chose for itself
+	pub label: Option<String>,
+	pub user_agent: Option<String>,
+	pub ip: Option<String>,
+	pub last_seen: DateTime,
+}
+
+/// Allows someone to create their own user once
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Invite {
+	pub id: UniqueId,
+	/// who the invite is for
+	pub name: String,
+	/// the rights the created user get's
+	pub rights: Rights,
+	pub created_by: UniqueId,
+	pub created_on: DateTime,
+	pub expires_on: DateTime,
+}
 
 pub(crate) fn bg_task(data: Resources) -> JoinHandle<()> {
 	tokio::spawn(async move {
@@ -16,7 +162,80 @@ pub(crate) fn bg_task(data: Resources) -> JoinHandle<()> {
 		loop {
 			intv.tick().await;
 
-			users.sessions_cleanup();
+			if let Err(e) = users.sessions_store_last_seen().await {
+				error!("storing sessions last seen failed {e}");
+			}
+
+			if let Err(e) = users.sessions_cleanup().await {
+				error!("sessions cleanup failed {e}");
+			}
+
+			if let Err(e) = users.invites_cleanup().await {
+				error!("invites cleanup failed {e}");
+			}
+		}
+	})
+}
+
+/// keeps the sessions cache in sync with changes the cli makes to the
+/// database
+pub(crate) fn listen_task(cfg: &DbConf, data: Resources) -> JoinHandle<()> {
+	let cfg = cfg.clone();
+
+	tokio::spawn(async move {
+		let users = data.get::<db::Users>().unwrap();
+
+		loop {
+			if let Err(e) = listen(&cfg, users).await {
+				error!("listening for session changes failed {e}");
+			}
+
+			time::sleep(Duration::from_secs(10)).await;
 		}
 	})
 }
+
+async fn listen(
+	cfg: &DbConf,
+	users: &db::Users,
+) -> Result<(), tokio_postgres::Error> {
+	let (client, mut conn) = tokio_postgres::Config::new()
+		.host(&cfg.host)
+		.dbname(&cfg.name)
+		.user(&cfg.user)
+		.password(&cfg.password)
+		.connect(NoTls)
+		.await?;
+
+	// the connection needs to be polled to receive notifications
+	let (tx, mut rx) = mpsc::unbounded_channel();
+	let conn = tokio::spawn(async move {
+		let mut messages = stream::poll_fn(|cx| conn.poll_message(cx));
+		while let Some(msg) = messages.next().await {
+			if let AsyncMessage::Notification(_) = msg? {
+				let _ = tx.send(());
+			}
+		}
+
+		Ok(())
+	});
+
+	client
+		.batch_execute(&format!("LISTEN {}", db::SESSIONS_CHANNEL))
+		.await?;
+
+	// notifications might have been missed while not listening
+	loop {
+		if let Err(e) = users.sessions_sync().await {
+			error!("sessions sync failed {e}");
+		}
+
+		if rx.recv().await.is_none() {
+			break;
+		}
+		// one sync covers all queued notifications
+		while rx.try_recv().is_ok() {}
+	}
+
+	conn.await.expect("listen connection panicked")
+}
@@ -0,0 +1,703 @@
+//! Login with an external OpenID Connect provider
+//!
+//! Uses the authorization code flow with PKCE, the id token get's validated
+//! with `jsonwebtoken` against the keys published by the provider.
+
+use super::Rights;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use chuchi::Resource;
+
+use data_encoding::BASE64URL_NOPAD;
+use jsonwebtoken::jwk::{JwkSet, PublicKeyUse};
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use url::Url;
+
+/// how long a user has to finish the login at the provider
+const LOGIN_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+/// logins can be started without authentication, the oldest get dropped
+/// once there are more
+const MAX_PENDING: usize = 1024;
+/// allowed clock difference to the provider
+const LEEWAY: u64 = 60;
+/// id tokens need to be signed with the key of the provider, hmac would use
+/// the client secret
+const ALGORITHMS: &[Algorithm] = &[
+	Algorithm::RS256,
+	Algorithm::RS384,
+	Algorithm::RS512,
+	Algorithm::PS256,
+	Algorithm::PS384,
+	Algorithm::PS512,
+	Algorithm::ES256,
+	Algorithm::ES384,
+	Algorithm::EdDSA,
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct OidcConf {
+	/// the name shown on the login button
+	#[serde(default = "default_name")]
+	pub name: String,
+	/// the configuration get's loaded from
+	/// `{issuer}/.well-known/openid-configuration`
+	pub issuer: String,
+	pub client_id: String,
+	#[serde(default)]
+	pub client_secret: Option<String>,
+	/// where the provider redirects to after the login, this needs to be an
+	/// url of the ui
+	pub redirect_url: String,
+	#[serde(default = "default_scopes")]
+	pub scopes: Vec<String>,
+	/// the claim which contains the username
+	#[serde(default = "default_username_claim")]
+	pub username_claim: String,
+	/// the claim which contains the display name
+	#[serde(default = "default_name_claim")]
+	pub name_claim: String,
+	/// on the first login a user with the same username get's linked,
+	/// root users and users which are already linked are never linked
+	///
+	/// only enable this if users can't choose their username at the provider
+	#[serde(default)]
+	pub link_existing: bool,
+	/// creates a user if there is none yet
+	#[serde(default)]
+	pub auto_provision: bool,
+	/// the rights of created users
+	#[serde(default = "default_rights")]
+	pub default_rights: Rights,
+}
+
+fn default_name() -> String {
+	"SSO".into()
+}
+
+fn default_scopes() -> Vec<String> {
+	["openid", "profile", "email"].map(Into::into).to_vec()
+}
+
+fn default_username_claim() -> String {
+	"preferred_username".into()
+}
+
+fn default_name_claim() -> String {
+	"name".into()
+}
+
+fn default_rights() -> Rights {
+	Rights {
+		root: false,
+		apps: Some(vec![]),
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OidcError {
+	NotConfigured,
+	/// the login was not started or took too long
+	InvalidState,
+	InvalidToken(String),
+	/// the provider could not be reached or returned something unexpected
+	Provider(String),
+}
+
+impl fmt::Display for OidcError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		fmt::Debug::fmt(self, f)
+	}
+}
+
+impl std::error::Error for OidcError {}
+
+impl From<reqwest::Error> for OidcError {
+	fn from(e: reqwest::Error) -> Self {
+		Self::Provider(e.to_string())
+	}
+}
+
+/// The user like the provider knows them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Identity {
+	/// identifies the user at the provider, never changes
+	pub subject: String,
+	pub username: String,
+	pub name: String,
+}
+
+impl Identity {
+	fn from_claims(
+		claims: &Map<String, Value>,
+		cfg: &OidcConf,
+	) -> Result<Self, OidcError> {
+		let claim = |name: &str| {
+			claims
+				.get(name)
+				.and_then(Value::as_str)
+				.map(str::trim)
+				.filter(|s| !s.is_empty())
+		};
+
+		let subject = claim("sub")
+			.ok_or_else(|| OidcError::InvalidToken("sub missing".into()))?;
+		let username = claim(&cfg.username_claim).ok_or_else(|| {
+			OidcError::InvalidToken(format!("{} missing", cfg.username_claim))
+		})?;
+		// the ui always lowercases the username on login
+		let username = username.to_lowercase();
+		let name = claim(&cfg.name_claim).unwrap_or(&username).to_string();
+
+		Ok(Self {
+			subject: subject.into(),
+			username,
+			name,
+		})
+	}
+}
+
+/// A finished login
+#[derive(Debug, Clone)]
+pub struct OidcLogin {
+	pub identity: Identity,
+	pub device: Option<String>,
+	pub remember: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Metadata {
+	issuer: String,
+	authorization_endpoint: String,
+	token_endpoint: String,
+	jwks_uri: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+	id_token: String,
+}
+
+fn random_string() -> String {
+	let mut bytes = [0u8; 32];
+	OsRng.fill_bytes(&mut bytes);
+
+	BASE64URL_NOPAD.encode(&bytes)
+}
+
+fn pkce_challenge(verifier: &str) -> String {
+	BASE64URL_NOPAD.encode(&Sha256::digest(verifier.as_bytes()))
+}
+
+/// returns the kid the token was signed with if it is not in the jwks
+///
+/// this allows to reload the keys after the provider rotated them
+fn missing_kid(token: &str, jwks: &JwkSet) -> Option<String> {
+	let kid = jsonwebtoken::decode_header(token).ok()?.kid?;
+
+	jwks.find(&kid).is_none().then_some(kid)
+}
+
+/// verifies the signature and the claims of an id token and returns the
+/// claims
+fn validate_id_token(
+	token: &str,
+	jwks: &JwkSet,
+	issuer: &str,
+	client_id: &str,
+	nonce: &str,
+) -> Result<Map<String, Value>, OidcError> {
+	let invalid =
+		|e: jsonwebtoken::errors::Error| OidcError::InvalidToken(e.to_string());
+
+	let header = jsonwebtoken::decode_header(token).map_err(invalid)?;
+	if !ALGORITHMS.contains(&header.alg) {
+		return Err(OidcError::InvalidToken("alg".into()));
+	}
+
+	let signing = |k: &&jsonwebtoken::jwk::Jwk| {
+		!matches!(k.common.public_key_use, Some(PublicKeyUse::Encryption))
+	};
+	let jwk = match &header.kid {
+		Some(kid) => jwks.find(kid).filter(signing),
+		// without a kid the provider can only have one key
+		None => match jwks.keys.as_slice() {
+			[key] => Some(key).filter(signing),
+			_ => None,
+		},
+	}
+	.ok_or_else(|| OidcError::InvalidToken("unknown key".into()))?;
+
+	let key = DecodingKey::from_jwk(jwk).map_err(invalid)?;
+
+	let mut validation = Validation::new(header.alg);
+	validation.leeway = LEEWAY;
+	validation.validate_nbf = true;
+	validation.set_issuer(&[issuer]);
+	validation.set_audience(&[client_id]);
+	validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+
+	let claims =
+		jsonwebtoken::decode::<Map<String, Value>>(token, &key, &validation)
+			.map_err(invalid)?
+			.claims;
+
+	if claims.get("nonce").and_then(Value::as_str) != Some(nonce) {
+		return Err(OidcError::InvalidToken("nonce".into()));
+	}
+
+	Ok(claims)
+}
+
+struct PendingLogin {
+	verifier: String,
+	nonce: String,
+	device: Option<String>,
+	remember: bool,
+	created: Instant,
+}
+
+struct Inner {
+	cfg: OidcConf,
+	client: reqwest::Client,
+	metadata: RwLock<Option<Metadata>>,
+	jwks: RwLock<JwkSet>,
+	/// logins which were started but not yet finished by their state
+	pending: Mutex<HashMap<String, PendingLogin>>,
+}
+
+#[derive(Clone, Resource)]
+pub struct Oidc {
+	inner: Option<Arc<Inner>>,
+}
+
+impl Oidc {
+	/// if no config is given every login fails with
+	/// [`OidcError::NotConfigured`]
+	pub fn new(cfg: Option<OidcConf>) -> Self {
+		Self {
+			inner: cfg.map(|cfg| {
+				Arc::new(Inner {
+					cfg,
+					client: reqwest::Client::new(),
+					metadata: RwLock::new(None),
+					jwks: RwLock::new(JwkSet { keys: vec![] }),
+					pending: Mutex::new(HashMap::new()),
+				})
+			}),
+		}
+	}
+
+	pub fn conf(&self) -> Option<&OidcConf> {
+		self.inner.as_ref().map(|i| &i.cfg)
+	}
+
+	fn inner(&self) -> Result<&Inner, OidcError> {
+		self.inner.as_deref().ok_or(OidcError::NotConfigured)
+	}
+
+	async fn metadata(&self) -> Result<Metadata, OidcError> {
+		let inner = self.inner()?;
+		if let Some(metadata) = inner.metadata.read().unwrap().clone() {
+			return Ok(metadata);
+		}
+
+		let url = format!(
+			"{}/.well-known/openid-configuration",
+			inner.cfg.issuer.trim_end_matches('/')
+		);
+		let metadata: Metadata = inner
+			.client
+			.get(url)
+			.send()
+			.await?
+			.error_for_status()?
+			.json()
+			.await?;
+
+		if metadata.issuer.trim_end_matches('/')
+			!= inner.cfg.issuer.trim_end_matches('/')
+		{
+			return Err(OidcError::Provider(format!(
+				"issuer mismatch {}",
+				metadata.issuer
+			)));
+		}
+
+		*inner.metadata.write().unwrap() = Some(metadata.clone());
+
+		Ok(metadata)
+	}
+
+	async fn load_jwks(&self, metadata: &Metadata) -> Result<(), OidcError> {
+		let inner = self.inner()?;
+		let jwks: JwkSet = inner
+			.client
+			.get(&metadata.jwks_uri)
+			.send()
+			.await?
+			.error_for_status()?
+			.json()
+			.await?;
+
+		*inner.jwks.write().unwrap() = jwks;
+
+		Ok(())
+	}
+
+	/// returns the url of the provider the user needs to be sent to
+	pub async fn start(
+		&self,
+		device: Option<String>,
+		remember: bool,
+	) -> Result<String, OidcError> {
+		let inner = self.inner()?;
+		let metadata = self.metadata().await?;
+		let cfg = &inner.cfg;
+
+		let state = random_string();
+		let nonce = random_string();
+		let verifier = random_string();
+
+		let mut url = Url::parse(&metadata.authorization_endpoint)
+			.map_err(|e| OidcError::Provider(e.to_string()))?;
+		url.query_pairs_mut()
+			.append_pair("response_type", "code")
+			.append_pair("client_id", &cfg.client_id)
+			.append_pair("redirect_uri", &cfg.redirect_url)
+			.append_pair("scope", &cfg.scopes.join(" "))
+			.append_pair("state", &state)
+			.append_pair("nonce", &nonce)
+			.append_pair("code_challenge", &pkce_challenge(&verifier))
+			.append_pair("code_challenge_method", "S256");
+
+		let mut pending = inner.pending.lock().unwrap();
+		pending.retain(|_, p| p.created.elapsed() < LOGIN_TIMEOUT);
+		while pending.len() >= MAX_PENDING {
+			let oldest = pending
+				.iter()
+				.min_by_key(|(_, p)| p.created)
+				.map(|(state, _)| state.clone())
+				.unwrap();
+			pending.remove(&oldest);
+		}
+		pending.insert(
+			state,
+			PendingLogin {
+				verifier,
+				nonce,
+				device,
+				remember,
+				created: Instant::now(),
+			},
+		);
+
+		Ok(url.into())
+	}
+
+	/// exchanges the code the provider returned and validates the id token
+	pub async fn finish(
+		&self,
+		code: &str,
+		state: &str,
+	) -> Result<OidcLogin, OidcError> {
+		let inner = self.inner()?;
+		let cfg = &inner.cfg;
+
+		// a state can only be used once
+		let pending = inner
+			.pending
+			.lock()
+			.unwrap()
+			.remove(state)
+			.filter(|p| p.created.elapsed() < LOGIN_TIMEOUT)
+			.ok_or(OidcError::InvalidState)?;
+
+		let metadata = self.metadata().await?;
+
+		let mut form = vec![
+			("grant_type", "authorization_code"),
+			("code", code),
+			("redirect_uri", &cfg.redirect_url),
+			("client_id", &cfg.client_id),
+			("code_verifier", &pending.verifier),
+		];
+		if let Some(secret) = &cfg.client_secret {
+			form.push(("client_secret", secret));
+		}
+
+		let token: TokenResponse = inner
+			.client
+			.post(&metadata.token_endpoint)
+			.form(&form)
+			.send()
+			.await?
+			.error_for_status()?
+			.json()
+			.await?;
+
+		let needs_keys = {
+			let jwks = inner.jwks.read().unwrap();
+			jwks.keys.is_empty()
+				|| missing_kid(&token.id_token, &jwks).is_some()
+		};
+		if needs_keys {
+			self.load_jwks(&metadata).await?;
+		}
+
+		let claims = validate_id_token(
+			&token.id_token,
+			&inner.jwks.read().unwrap(),
+			&metadata.issuer,
+			&cfg.client_id,
+			&pending.nonce,
+		)?;
+
+		Ok(OidcLogin {
+			identity: Identity::from_claims(&claims, cfg)?,
+			device: pending.device,
+			remember: pending.remember,
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use jsonwebtoken::{EncodingKey, Header};
+	use serde_json::json;
+	use std::time::{SystemTime, UNIX_EPOCH};
+	use tokio::io::{AsyncReadExt, AsyncWriteExt};
+	use tokio::net::TcpListener;
+
+	// a 2048 bit rsa key only used in these tests (pkcs1)
+	const TEST_KEY: &str = "\
+		MIIEogIBAAKCAQEArnGk3J72bO/Zn95kRjIzDALSlQxxPlrNnsTsqnl1l4X3fBOOzX2kiCbe\
+		ZnCN/Bx/KNohCYS+EPW5x5s5iUBhApqPNL+F292nir2esw/uI1XMGTb5pzGRRxapj3wnUkeB\
+		bsfVXA8EmvwRD+wCF4M24HQgwfvnx3rfLKXLE/jYw1GVaeZ0XVdMRc0xyX/f99hyd7E6A/2G\
+		J5Xhmw4tCJ2WRkv24iVCiv1Y4gnW9pkB+K6vvq0m8eyvkxBlAoGyW0W7hxKUhru45iiw/53D\
+		WmN9gsorL32WwAuDbyPaYjJ3WT2CEstlYiGMvE5ASZ57e0kF3ogbkk72A4xAQlvicj0HmwID\
+		AQABAoIBABgoQrq5Wh+Mjj2hEDqBryda/gsyqugGA8hDNbxcj5fz2b7up8L17aKxMfFLuviO\
+		gF7e6N5RlKSdSCSdRA1+wzBsyPzMB7SCmzYPu6TJGvhSZFjwOl3DSSloDlksH0yPUcb5Q3xj\
+		1AZxIR0XA38lZk8U+zMB4yobw5Ys63+Q4YEYAPyiLKrO97ta/GwwejasO2wFhH1rnWO4AD9t\
+		NLoc/mEvhHiwGUXmlyUq5lD8mf8BqqozG+5kC+7+ibaf/0UQrmp4Kzhc3E0hHycYXBwIgyx4\
+		9JjueVw42zr7Q8NJ6+uIe5vvN4GyKX3AiBpbYSvc+EupsEg5f4Owgga4+7isfoECgYEA16WW\
+		SK4iM7MTtR7NshUorgKoc1H4qfcy6azZQDYALkRLnofdDG12dJ8m/ZOCvpCry4gOwqZ7Hayu\
+		IyL/M3ejz+USOFrU0I57Xjd1qb7vhYrPr9yCiuyPuGYvNLiL59iwIqQwxSaqfeicv+ZDwOeJ\
+		BswJ9sDAY6SuEv4fVbTDMkECgYEAzxZCnP5bo97V9rvNRmvuQ0BOoZXzJFMPp4vkOqWkOlqc\
+		3h/qHOq169sZz8C1lB5QrXnmRQlMU7+rjFEjC46lMp3utgZiTh/EhUa1VANx9cdAW891Ga84\
+		wBrCYSjnEvt6SCY1z1wXaT49tvQcKz/BC4M6dQ+2k7qIE87DJHeqitsCgYBUirFRSxtTg2g9\
+		gJcJsuvg1F/v3foLwELwg3gkiUcm3FV9ECyj+6IN9HsXvKXsSdFgGLYYr6Xiq1kVo1nDdqr9\
+		DQRInauDG+jXtgxmANnsU9A6VMvE5RsvIrhtBdCrkzLyUcZg9Fiwxen1/bMgRGVfCcz6NPT3\
+		2473GTN1qUlBQQKBgD6g0MyB8F0Cy1qvOUmO4qPcEuuzWbEmnZliKUfDSIONW9uhc0ADi7kk\
+		rg2yjjeZY3TTZNyED11UniD1eM/J+BPyx3qtO5W87cYQuE4MLK7T75bUiumVoGgYr6iKojeP\
+		bk/bnnJzu3DMrgVilhJOWaeNrGQyuFi9FOJIqaOhvSX5AoGAWR1oo/ZVP+etCJ4/a8LWcsf9\
+		OkbRc8/JnZfUHSnSMFMq132iFUNLFtY9GdcSrDpkYR2f+tnfPa1eAHhUbAzAstN95SpjEOaY\
+		dxg/xg9JVX8EpOQcIg0qvw08HI+1EbIa3UKA3WMfMGv6ofV4cAleeHebVzisnWor1wF5g+0a\
+		8OA=";
+	const TEST_N: &str = "\
+		rnGk3J72bO_Zn95kRjIzDALSlQxxPlrNnsTsqnl1l4X3fBOOzX2kiCbeZnCN_Bx_\
+		KNohCYS-EPW5x5s5iUBhApqPNL-F292nir2esw_uI1XMGTb5pzGRRxapj3wnUkeB\
+		bsfVXA8EmvwRD-wCF4M24HQgwfvnx3rfLKXLE_jYw1GVaeZ0XVdMRc0xyX_f99hy\
+		d7E6A_2GJ5Xhmw4tCJ2WRkv24iVCiv1Y4gnW9pkB-K6vvq0m8eyvkxBlAoGyW0W7\
+		hxKUhru45iiw_53DWmN9gsorL32WwAuDbyPaYjJ3WT2CEstlYiGMvE5ASZ57e0kF\
+		3ogbkk72A4xAQlvicj0Hmw";
+	const CLIENT_ID: &str = "alpenwind";
+
+	fn now_secs() -> u64 {
+		SystemTime::now()
+			.duration_since(UNIX_EPOCH)
+			.unwrap()
+			.as_secs()
+	}
+
+	fn sign(claims: &Value) -> String {
+		let der = data_encoding::BASE64.decode(TEST_KEY.as_bytes()).unwrap();
+		let mut header = Header::new(Algorithm::RS256);
+		header.kid = Some("test".into());
+
+		jsonwebtoken::encode(&header, claims, &EncodingKey::from_rsa_der(&der))
+			.unwrap()
+	}
+
+	fn jwks() -> Value {
+		json!({ "keys": [{
+			"kty": "RSA",
+			"kid": "test",
+			"use": "sig",
+			"alg": "RS256",
+			"n": TEST_N,
+			"e": "AQAB"
+		}] })
+	}
+
+	fn claims(issuer: &str, nonce: &str) -> Value {
+		let now = now_secs();
+		json!({
+			"iss": issuer,
+			"sub": "1234",
+			"aud": CLIENT_ID,
+			"exp": now + 60,
+			"iat": now,
+			"nonce": nonce,
+			"preferred_username": "Alice",
+			"name": "Alice Example"
+		})
+	}
+
+	/// serves the discovery document, the keys and a token endpoint which
+	/// returns an id token for the nonce of the last started login
+	async fn mock_provider(nonce: Arc<Mutex<String>>) -> String {
+		let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let issuer = format!("http://{}", listener.local_addr().unwrap());
+		let issuer_c = issuer.clone();
+
+		tokio::spawn(async move {
+			loop {
+				let (mut stream, _) = listener.accept().await.unwrap();
+				let mut buf = vec![0; 8192];
+				let len = stream.read(&mut buf).await.unwrap();
+				let req = String::from_utf8_lossy(&buf[..len]).to_string();
+				let path = req.split_whitespace().nth(1).unwrap_or("");
+
+				let body = match path {
+					"/.well-known/openid-configuration" => json!({
+						"issuer": issuer_c,
+						"authorization_endpoint": format!("{issuer_c}/auth"),
+						"token_endpoint": format!("{issuer_c}/token"),
+						"jwks_uri": format!("{issuer_c}/jwks"),
+					}),
+					"/jwks" => jwks(),
+					"/token" => {
+						assert!(req.contains("code=the-code"));
+						assert!(req.contains("code_verifier="));
+						let nonce = nonce.lock().unwrap().clone();
+						json!({
+							"access_token": "access",
+							"token_type": "Bearer",
+							"id_token": sign(&claims(&issuer_c, &nonce)),
+						})
+					}
+					_ => json!({}),
+				}
+				.to_string();
+
+				let resp = format!(
+					"HTTP/1.1 200 OK\r\ncontent-type: application/json\r\n\
+					content-length: {}\r\nconnection: close\r\n\r\n{body}",
+					body.len()
+				);
+				stream.write_all(resp.as_bytes()).await.unwrap();
+			}
+		});
+
+		issuer
+	}
+
+	fn conf(issuer: &str) -> OidcConf {
+		toml::from_str(&format!(
+			"issuer = \"{issuer}\"\n\
+			client-id = \"{CLIENT_ID}\"\n\
+			redirect-url = \"http://localhost/login\""
+		))
+		.unwrap()
+	}
+
+	#[tokio::test]
+	async fn login_flow() {
+		let nonce = Arc::new(Mutex::new(String::new()));
+		let issuer = mock_provider(nonce.clone()).await;
+		let oidc = Oidc::new(Some(conf(&issuer)));
+
+		let url = oidc.start(Some("phone".into()), true).await.unwrap();
+		let url = Url::parse(&url).unwrap();
+		assert!(url.as_str().starts_with(&format!("{issuer}/auth?")));
+		let query: HashMap<_, _> = url.query_pairs().collect();
+		assert_eq!(query["code_challenge_method"], "S256");
+		assert_eq!(query["client_id"], CLIENT_ID);
+		*nonce.lock().unwrap() = query["nonce"].to_string();
+
+		assert_eq!(
+			oidc.finish("the-code", "other-state").await.unwrap_err(),
+			OidcError::InvalidState
+		);
+
+		let login = oidc.finish("the-code", &query["state"]).await.unwrap();
+		assert_eq!(
+			login.identity,
+			Identity {
+				subject: "1234".into(),
+				username: "alice".into(),
+				name: "Alice Example".into(),
+			}
+		);
+		assert_eq!(login.device.as_deref(), Some("phone"));
+		assert!(login.remember);
+
+		// the state can only be used once
+		assert_eq!(
+			oidc.finish("the-code", &query["state"]).await.unwrap_err(),
+			OidcError::InvalidState
+		);
+	}
+
+	#[test]
+	fn id_token_validation() {
+		let jwks: JwkSet = serde_json::from_value(jwks()).unwrap();
+		let iss = "https://id.example.com";
+		let now = now_secs();
+		let validate =
+			|token: &str| validate_id_token(token, &jwks, iss, CLIENT_ID, "n");
+
+		assert!(validate(&sign(&claims(iss, "n"))).is_ok());
+		assert!(validate(&sign(&claims(iss, "other"))).is_err());
+		assert!(validate(&sign(&claims("https://evil.com", "n"))).is_err());
+
+		let mut c = claims(iss, "n");
+		c["aud"] = json!(["other", CLIENT_ID]);
+		assert!(validate(&sign(&c)).is_ok());
+		c["aud"] = json!("other");
+		assert!(validate(&sign(&c)).is_err());
+
+		let mut c = claims(iss, "n");
+		c["exp"] = json!(now - 2 * LEEWAY);
+		assert!(validate(&sign(&c)).is_err());
+
+		// a token signed with a shared secret is never accepted
+		let hmac = jsonwebtoken::encode(
+			&Header::new(Algorithm::HS256),
+			&claims(iss, "n"),
+			&EncodingKey::from_secret(b"secret"),
+		)
+		.unwrap();
+		assert!(validate(&hmac).is_err());
+
+		// tampered payload
+		let token = sign(&claims(iss, "n"));
+		let mut parts: Vec<_> = token.split('.').collect();
+		let mut c = claims(iss, "n");
+		c["sub"] = json!("root");
+		let payload = BASE64URL_NOPAD.encode(c.to_string().as_bytes());
+		parts[1] = &payload;
+		assert!(validate(&parts.join(".")).is_err());
+	}
+
+	#[test]
+	fn pkce() {
+		// base64url encoded sha256 without padding
+		assert_eq!(
+			pkce_challenge("alpenwind-pkce-verifier"),
+			"p29PCMSX2RYJJrjtaurXwmJUPPuy9Gexdk427233NB4"
+		);
+	}
+}
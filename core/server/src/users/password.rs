@@ -0,0 +1,246 @@
+//! Hashing of the passwords stored in the database and the policy new
+//! passwords need to follow
+//!
+//! New hashes use Argon2id, bcrypt hashes from older versions still get
+//! verified and should be replaced on the next successful login.
+
+use argon2::password_hash::{self, PasswordHash, SaltString};
+use argon2::{
+	Algorithm, Argon2, Params, PasswordHasher, PasswordVerifier, Version,
+};
+use std::collections::HashSet;
+
+use rand::rngs::OsRng;
+use serde::{Deserialize, Serialize};
+
+/// The Argon2id parameters, the defaults follow the OWASP recommendation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct HashingConf {
+	/// KiB
+	pub memory: u32,
+	pub iterations: u32,
+	pub parallelism: u32,
+}
+
+impl Default for HashingConf {
+	fn default() -> Self {
+		Self {
+			memory: 19 * 1024,
+			iterations: 2,
+			parallelism: 1,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Verified {
+	pub correct: bool,
+	/// the hash uses bcrypt or other parameters than configured
+	pub needs_rehash: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct Hasher {
+	params: Params,
+}
+
+impl Hasher {
+	/// ## Panics
+	/// if the parameters are not valid
+	pub fn new(cfg: &HashingConf) -> Self {
+		let params =
+			Params::new(cfg.memory, cfg.iterations, cfg.parallelism, None)
+				.expect("invalid password hashing parameters");
+
+		Self { params }
+	}
+
+	fn argon2(&self) -> Argon2<'static> {
+		Argon2::new(Algorithm::Argon2id, Version::V0x13, self.params.clone())
+	}
+
+	pub fn hash(&self, password: &str) -> Result<String, password_hash::Error> {
+		let salt = SaltString::generate(&mut OsRng);
+
+		self.argon2()
+			.hash_password(password.as_bytes(), &salt)
+			.map(|h| h.to_string())
+	}
+
+	pub fn verify(&self, password: &str, hash: &str) -> Verified {
+		if hash.starts_with("$2") {
+			let correct = bcrypt::verify(password, hash).unwrap_or(false);
+			return Verified {
+				correct,
+				needs_rehash: correct,
+			};
+		}
+
+		let Ok(parsed) = PasswordHash::new(hash) else {
+			return Verified {
+				correct: false,
+				needs_rehash: false,
+			};
+		};
+
+		// the parameters of the hash get used to verify
+		let correct = Argon2::default()
+			.verify_password(password.as_bytes(), &parsed)
+			.is_ok();
+
+		let outdated = || {
+			let params = Params::try_from(&parsed).ok();
+			parsed.algorithm != Algorithm::Argon2id.ident()
+				|| parsed.version != Some(Version::V0x13.into())
+				|| params.is_none_or(|p| {
+					p.m_cost() != self.params.m_cost()
+						|| p.t_cost() != self.params.t_cost()
+						|| p.p_cost() != self.params.p_cost()
+				})
+		};
+
+		Verified {
+			correct,
+			needs_rehash: correct && outdated(),
+		}
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct PolicyConf {
+	/// in characters
+	pub min_length: usize,
+	/// passwords which are not allowed, compared case insensitive
+	pub denylist: Vec<String>,
+}
+
+impl Default for PolicyConf {
+	fn default() -> Self {
+		Self {
+			min_length: 8,
+			denylist: [
+				"password",
+				"passwort",
+				"12345678",
+				"123456789",
+				"1234567890",
+				"qwertzuiop",
+				"qwertyuiop",
+				"alpenwind",
+			]
+			.map(Into::into)
+			.to_vec(),
+		}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PolicyError {
+	/// contains the minimum length
+	TooShort(usize),
+	/// the password is on the denylist or equal to the username
+	NotAllowed,
+}
+
+#[derive(Debug, Clone)]
+pub struct Policy {
+	min_length: usize,
+	denylist: HashSet<String>,
+}
+
+impl Policy {
+	pub fn new(cfg: &PolicyConf) -> Self {
+		Self {
+			min_length: cfg.min_length,
+			denylist: cfg.denylist.iter().map(|p| p.to_lowercase()).collect(),
+		}
+	}
+
+	pub fn check(
+		&self,
+		username: &str,
+		password: &str,
+	) -> Result<(), PolicyError> {
+		if password.chars().count() < self.min_length {
+			return Err(PolicyError::TooShort(self.min_length));
+		}
+
+		let password = password.to_lowercase();
+		if self.denylist.contains(&password)
+			|| password == username.to_lowercase()
+		{
+			return Err(PolicyError::NotAllowed);
+		}
+
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn test_hasher(memory: u32) -> Hasher {
+		Hasher::new(&HashingConf {
+			memory,
+			iterations: 1,
+			parallelism: 1,
+		})
+	}
+
+	#[test]
+	fn argon2() {
+		let hasher = test_hasher(64);
+		let hash = hasher.hash("secret").unwrap();
+		assert!(hash.starts_with("$argon2id$v=19$m=64,t=1,p=1$"));
+
+		let verified = hasher.verify("secret", &hash);
+		assert!(verified.correct);
+		assert!(!verified.needs_rehash);
+		assert!(!hasher.verify("Secret", &hash).correct);
+
+		// other parameters still verify but need a rehash
+		let verified = test_hasher(128).verify("secret", &hash);
+		assert!(verified.correct);
+		assert!(verified.needs_rehash);
+	}
+
+	#[test]
+	fn bcrypt() {
+		let hash = bcrypt::hash("secret", 4).unwrap();
+		let hasher = test_hasher(64);
+
+		assert_eq!(
+			hasher.verify("secret", &hash),
+			Verified {
+				correct: true,
+				needs_rehash: true
+			}
+		);
+		assert!(!hasher.verify("wrong", &hash).correct);
+		assert!(!hasher.verify("secret", "invalid").correct);
+	}
+
+	#[test]
+	fn policy() {
+		let policy = Policy::new(&PolicyConf::default());
+
+		assert_eq!(
+			policy.check("alice", "short"),
+			Err(PolicyError::TooShort(8))
+		);
+		// characters get counted not bytes
+		assert!(policy.check("alice", "ääääääää").is_ok());
+		assert_eq!(
+			policy.check("alice", "Passwort"),
+			Err(PolicyError::NotAllowed)
+		);
+		assert_eq!(
+			policy.check("alice1234", "ALICE1234"),
+			Err(PolicyError::NotAllowed)
+		);
+		assert!(policy.check("alice", "correct horse").is_ok());
+	}
+}
@@ -0,0 +1,259 @@
+//! Login with a username set by an authenticating reverse proxy
+//!
+//! The header is only trusted if the request comes directly from one of the
+//! configured proxy addresses, everyone else could set it as well. The same
+//! goes for `X-Forwarded-For` which contains the address of the client.
+
+use super::Rights;
+
+use std::fmt;
+use std::net::IpAddr;
+use std::sync::{Arc, OnceLock};
+
+use chuchi::header::RequestHeader;
+use chuchi::Resource;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ProxyConf {
+	/// the header which contains the username
+	#[serde(default = "default_header")]
+	pub header: String,
+	/// the header which contains the display name, only used when a user
+	/// get's created
+	#[serde(default)]
+	pub name_header: Option<String>,
+	/// addresses or networks like `10.0.0.0/8` of the proxies
+	pub trusted_proxies: Vec<IpNet>,
+	/// creates a user if there is none with the username yet
+	#[serde(default)]
+	pub auto_provision: bool,
+	/// the rights of created users
+	#[serde(default = "default_rights")]
+	pub default_rights: Rights,
+}
+
+fn default_header() -> String {
+	"X-Forwarded-User".into()
+}
+
+fn default_rights() -> Rights {
+	Rights {
+		root: false,
+		apps: Some(vec![]),
+	}
+}
+
+/// An ip address or a network like `10.0.0.0/8`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct IpNet {
+	addr: IpAddr,
+	prefix: u8,
+}
+
+impl IpNet {
+	pub fn contains(&self, ip: IpAddr) -> bool {
+		match (self.addr, ip.to_canonical()) {
+			(IpAddr::V4(net), IpAddr::V4(ip)) => {
+				let mask = u32::MAX.checked_shl(32 - self.prefix as u32);
+				let mask = mask.unwrap_or(0);
+				u32::from(net) & mask == u32::from(ip) & mask
+			}
+			(IpAddr::V6(net), IpAddr::V6(ip)) => {
+				let mask = u128::MAX.checked_shl(128 - self.prefix as u32);
+				let mask = mask.unwrap_or(0);
+				u128::from(net) & mask == u128::from(ip) & mask
+			}
+			_ => false,
+		}
+	}
+}
+
+impl TryFrom<String> for IpNet {
+	type Error = String;
+
+	fn try_from(s: String) -> Result<Self, String> {
+		let (addr, prefix) = match s.split_once('/') {
+			Some((addr, prefix)) => (addr, Some(prefix)),
+			None => (s.as_str(), None),
+		};
+
+		let addr: IpAddr = addr
+			.parse()
+			.map_err(|_| format!("invalid ip address {addr}"))?;
+		let max = if addr.is_ipv4() { 32 } else { 128 };
+		let prefix = match prefix {
+			Some(p) => p
+				.parse()
+				.ok()
+				.filter(|p| *p <= max)
+				.ok_or_else(|| format!("invalid prefix {p}"))?,
+			None => max,
+		};
+
+		Ok(Self {
+			addr: addr.to_canonical(),
+			prefix,
+		})
+	}
+}
+
+impl From<IpNet> for String {
+	fn from(net: IpNet) -> Self {
+		net.to_string()
+	}
+}
+
+impl fmt::Display for IpNet {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}/{}", self.addr, self.prefix)
+	}
+}
+
+static TRUSTED_PROXIES: OnceLock<Vec<IpNet>> = OnceLock::new();
+
+/// only the first call has an effect, should be called before the server
+/// starts
+pub fn set_trusted_proxies(nets: Vec<IpNet>) {
+	let _ = TRUSTED_PROXIES.set(nets);
+}
+
+fn is_trusted(ip: IpAddr) -> bool {
+	TRUSTED_PROXIES
+		.get()
+		.is_some_and(|nets| nets.iter().any(|net| net.contains(ip)))
+}
+
+/// returns the address of the client, if the request came through trusted
+/// proxies it is taken from `X-Forwarded-For`
+pub fn client_ip(header: &RequestHeader) -> IpAddr {
+	client_ip_from(
+		header.address().ip(),
+		header.value("x-forwarded-for"),
+		is_trusted,
+	)
+}
+
+/// every proxy appends the address it received the request from, so the
+/// first untrusted address from the right is the client
+fn client_ip_from(
+	peer: IpAddr,
+	forwarded_for: Option<&str>,
+	is_trusted: impl Fn(IpAddr) -> bool,
+) -> IpAddr {
+	let mut ip = peer.to_canonical();
+	let Some(forwarded_for) = forwarded_for else {
+		return ip;
+	};
+
+	for addr in forwarded_for.rsplit(',') {
+		if !is_trusted(ip) {
+			break;
+		}
+
+		match addr.trim().parse::<IpAddr>() {
+			Ok(addr) => ip = addr.to_canonical(),
+			// the trusted proxy did not set a valid address
+			Err(_) => break,
+		}
+	}
+
+	ip
+}
+
+/// The user like the proxy knows them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProxyUser {
+	pub username: String,
+	pub name: Option<String>,
+}
+
+#[derive(Debug, Clone, Resource)]
+pub struct Proxy {
+	cfg: Option<Arc<ProxyConf>>,
+}
+
+impl Proxy {
+	pub fn new(cfg: Option<ProxyConf>) -> Self {
+		Self {
+			cfg: cfg.map(Arc::new),
+		}
+	}
+
+	pub fn conf(&self) -> Option<&ProxyConf> {
+		self.cfg.as_deref()
+	}
+
+	/// returns the user set by the proxy, `None` if the request did not come
+	/// from a trusted proxy or the header is missing
+	pub fn user(&self, header: &RequestHeader) -> Option<ProxyUser> {
+		let cfg = self.cfg.as_ref()?;
+		let ip = header.address().ip();
+		if !cfg.trusted_proxies.iter().any(|net| net.contains(ip)) {
+			return None;
+		}
+
+		let value = |name: &str| {
+			header
+				.value(name.to_lowercase())
+				.map(str::trim)
+				.filter(|v| !v.is_empty())
+		};
+
+		Some(ProxyUser {
+			username: value(&cfg.header)?.to_lowercase(),
+			name: cfg.name_header.as_deref().and_then(value).map(Into::into),
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn net(s: &str) -> IpNet {
+		s.to_string().try_into().unwrap()
+	}
+
+	fn ip(s: &str) -> IpAddr {
+		s.parse().unwrap()
+	}
+
+	#[test]
+	fn ip_net() {
+		assert!(net("10.0.0.0/8").contains(ip("10.1.2.3")));
+		assert!(!net("10.0.0.0/8").contains(ip("11.0.0.1")));
+		assert!(net("127.0.0.1").contains(ip("127.0.0.1")));
+		assert!(!net("127.0.0.1").contains(ip("127.0.0.2")));
+		// mapped ipv4 addresses get used as ipv4
+		assert!(net("172.16.0.0/12").contains(ip("::ffff:172.20.0.5")));
+		assert!(net("0.0.0.0/0").contains(ip("8.8.8.8")));
+		assert!(net("fd00::/8").contains(ip("fd12::1")));
+		assert!(!net("fd00::/8").contains(ip("10.0.0.1")));
+
+		assert_eq!(net("10.0.0.0/8").to_string(), "10.0.0.0/8");
+		assert!(IpNet::try_from("10.0.0.0/33".to_string()).is_err());
+		assert!(IpNet::try_from("localhost".to_string()).is_err());
+	}
+
+	#[test]
+	fn client_ip() {
+		let trusted = |ip| net("10.0.0.0/8").contains(ip);
+		let client = |peer, fwd| client_ip_from(ip(peer), fwd, trusted);
+
+		// the header is ignored if the peer is not a proxy
+		assert_eq!(client("8.8.8.8", Some("1.1.1.1")), ip("8.8.8.8"));
+		assert_eq!(client("10.0.0.1", None), ip("10.0.0.1"));
+		assert_eq!(client("10.0.0.1", Some("1.1.1.1")), ip("1.1.1.1"));
+		// a client could set the header itself
+		assert_eq!(
+			client("10.0.0.1", Some("1.1.1.1, 2.2.2.2, 10.0.0.2")),
+			ip("2.2.2.2")
+		);
+		assert_eq!(client("10.0.0.1", Some("10.0.0.3")), ip("10.0.0.3"));
+		assert_eq!(client("10.0.0.1", Some("nope")), ip("10.0.0.1"));
+	}
+}
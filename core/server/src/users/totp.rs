@@ -0,0 +1,257 @@
+//! Time-based one-time passwords (RFC 6238)
+
+use super::Token;
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use chuchi::Resource;
+use chuchi_postgres::UniqueId;
+
+use data_encoding::BASE32_NOPAD;
+use hmac::{Hmac, Mac};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+
+const ISSUER: &str = "Alpenwind";
+const STEP: u64 = 30;
+const DIGITS: u32 = 6;
+/// how many steps a code might be off, to allow for clock drift
+const SKEW: u64 = 1;
+const SECRET_LEN: usize = 20;
+
+const RECOVERY_CODES: usize = 10;
+const RECOVERY_CODE_LEN: usize = 10;
+
+/// how long a user has to enter the code after the password was accepted
+const LOGIN_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+const LOGIN_MAX_ATTEMPTS: u8 = 5;
+
+/// returns a new random base32 encoded secret
+pub fn new_secret() -> String {
+	let mut secret = [0u8; SECRET_LEN];
+	OsRng.fill_bytes(&mut secret);
+
+	BASE32_NOPAD.encode(&secret)
+}
+
+/// the uri which can be shown as a qr code to add the secret to an
+/// authenticator app
+pub fn provisioning_uri(secret: &str, username: &str) -> String {
+	let label = utf8_percent_encode(username, NON_ALPHANUMERIC);
+
+	format!(
+		"otpauth://totp/{ISSUER}:{label}?secret={secret}&issuer={ISSUER}\
+		&algorithm=SHA1&digits={DIGITS}&period={STEP}"
+	)
+}
+
+fn code_at(secret: &[u8], step: u64) -> u32 {
+	let mut mac = Hmac::<Sha1>::new_from_slice(secret)
+		.expect("hmac accepts any key length");
+	mac.update(&step.to_be_bytes());
+	let hash = mac.finalize().into_bytes();
+
+	// dynamic truncation
+	let offset = (hash[hash.len() - 1] & 0xf) as usize;
+	let bin = u32::from_be_bytes(hash[offset..offset + 4].try_into().unwrap())
+		& 0x7fff_ffff;
+
+	bin % 10u32.pow(DIGITS)
+}
+
+/// returns the step of the matching code
+fn verify_at(secret: &str, code: &str, time: u64) -> Option<u64> {
+	let secret = BASE32_NOPAD.decode(secret.as_bytes()).ok()?;
+	let code = normalize(code);
+	if code.len() != DIGITS as usize {
+		return None;
+	}
+	let code: u32 = code.parse().ok()?;
+
+	let current = time / STEP;
+	(current.saturating_sub(SKEW)..=current + SKEW)
+		.find(|step| code_at(&secret, *step) == code)
+}
+
+fn now_secs() -> u64 {
+	SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.unwrap_or_default()
+		.as_secs()
+}
+
+fn normalize(code: &str) -> String {
+	code.chars()
+		.filter(|c| !c.is_whitespace() && *c != '-')
+		.collect::<String>()
+		.to_uppercase()
+}
+
+/// returns the plain codes which should be shown to the user once and their
+/// hashes which should be stored
+pub fn new_recovery_codes() -> (Vec<String>, Vec<String>) {
+	(0..RECOVERY_CODES)
+		.map(|_| {
+			let mut bytes = [0u8; RECOVERY_CODE_LEN];
+			OsRng.fill_bytes(&mut bytes);
+			let code = BASE32_NOPAD.encode(&bytes);
+			let code = &code[..RECOVERY_CODE_LEN];
+			let (a, b) = code.split_at(RECOVERY_CODE_LEN / 2);
+			let code = format!("{a}-{b}").to_lowercase();
+
+			let hash = hash_recovery_code(&code);
+			(code, hash)
+		})
+		.unzip()
+}
+
+/// recovery codes are random so a fast hash is enough
+pub fn hash_recovery_code(code: &str) -> String {
+	let hash = Sha256::digest(normalize(code).as_bytes());
+
+	BASE32_NOPAD.encode(&hash)
+}
+
+struct PendingLogin {
+	user_id: UniqueId,
+	device: Option<String>,
+	remember: bool,
+	created: Instant,
+	attempts: u8,
+}
+
+#[derive(Default)]
+struct Inner {
+	/// logins where the password was correct but the code is still missing
+	pending: HashMap<Token, PendingLogin>,
+	/// the last step which was used by a user, so a code can only be used once
+	last_steps: HashMap<UniqueId, u64>,
+}
+
+#[derive(Clone, Default, Resource)]
+pub struct Totp {
+	inner: Arc<Mutex<Inner>>,
+}
+
+impl Totp {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// verifies the code and makes sure it can't be used a second time
+	pub fn verify(&self, user_id: &UniqueId, secret: &str, code: &str) -> bool {
+		let Some(step) = verify_at(secret, code, now_secs()) else {
+			return false;
+		};
+
+		let mut inner = self.inner.lock().unwrap();
+		let last = inner.last_steps.entry(*user_id).or_default();
+		if step <= *last {
+			return false;
+		}
+		*last = step;
+
+		true
+	}
+
+	/// stores a login which still requires a code and returns the token
+	/// to finish it
+	pub fn login_start(
+		&self,
+		user_id: UniqueId,
+		device: Option<String>,
+		remember: bool,
+	) -> Token {
+		let mut inner = self.inner.lock().unwrap();
+		inner
+			.pending
+			.retain(|_, p| p.created.elapsed() < LOGIN_TIMEOUT);
+
+		let token = Token::new();
+		inner.pending.insert(
+			token.clone(),
+			PendingLogin {
+				user_id,
+				device,
+				remember,
+				created: Instant::now(),
+				attempts: 0,
+			},
+		);
+
+		token
+	}
+
+	/// returns the user, the device label and if the user should be
+	/// remembered of a pending login
+	pub fn login_get(
+		&self,
+		token: &Token,
+	) -> Option<(UniqueId, Option<String>, bool)> {
+		let inner = self.inner.lock().unwrap();
+		inner
+			.pending
+			.get(token)
+			.filter(|p| p.created.elapsed() < LOGIN_TIMEOUT)
+			.map(|p| (p.user_id, p.device.clone(), p.remember))
+	}
+
+	/// after too many failed attempts the login needs to be started again
+	pub fn login_failed(&self, token: &Token) {
+		let mut inner = self.inner.lock().unwrap();
+		let Some(pending) = inner.pending.get_mut(token) else {
+			return;
+		};
+
+		pending.attempts += 1;
+		if pending.attempts >= LOGIN_MAX_ATTEMPTS {
+			inner.pending.remove(token);
+		}
+	}
+
+	pub fn login_done(&self, token: &Token) {
+		self.inner.lock().unwrap().pending.remove(token);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	// the sha1 test vectors from RFC 6238 truncated to 6 digits
+	#[test]
+	fn rfc_test_vectors() {
+		let secret = BASE32_NOPAD.encode(b"12345678901234567890");
+
+		for (time, code) in [
+			(59, "287082"),
+			(1111111109, "081804"),
+			(1111111111, "050471"),
+			(1234567890, "005924"),
+			(2000000000, "279037"),
+		] {
+			assert_eq!(verify_at(&secret, code, time), Some(time / STEP));
+		}
+
+		assert_eq!(verify_at(&secret, "287083", 59), None);
+		// the previous step is still accepted
+		assert!(verify_at(&secret, "287082", 59 + STEP).is_some());
+		assert!(verify_at(&secret, "287082", 59 + 3 * STEP).is_none());
+	}
+
+	#[test]
+	fn recovery_codes() {
+		let (codes, hashes) = new_recovery_codes();
+		assert_eq!(codes.len(), RECOVERY_CODES);
+		assert_eq!(hash_recovery_code(&codes[0]), hashes[0]);
+		assert_eq!(
+			hash_recovery_code(&codes[0].to_uppercase().replace('-', " ")),
+			hashes[0]
+		);
+	}
+}
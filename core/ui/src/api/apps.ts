@@ -6,6 +6,13 @@ export class App {
 	key: string;
 	jsEntry?: string;
 	cssEntry?: string;
+	version?: string;
+	displayName?: string;
+	// relative to the assets of the app
+	icon?: string;
+	description?: string;
+	// lower gets listed first
+	priority: number;
 
 	constructor(d: any) {
 		Object.assign(this, d);
@@ -20,7 +27,7 @@ export class AppsResp {
 	}
 }
 
-export async function apps() {
-	const d = await api.request('GET', 'list');
+export async function apps(token: string) {
+	const d = await api.request('GET', 'list', null, { 'auth-token': token });
 	return new AppsResp(d).apps;
 }
@@ -0,0 +1,35 @@
+import { Api } from 'chuchi/api';
+
+const api = new Api(import.meta.env.SERVER_ADDR + 'api/preferences/');
+
+/// the namespace of the core ui, apps use their key
+export const CORE_APP = 'core';
+
+/// returns all preferences of the user for the app
+export async function preferences(
+	app: string,
+	token: string,
+): Promise<Record<string, any>> {
+	const d = await api.request('GET', app, null, { 'auth-token': token });
+
+	return d.values;
+}
+
+/// the value needs to be serializable to json
+export async function setPreference(
+	app: string,
+	key: string,
+	value: any,
+	token: string,
+) {
+	await api.request('POST', `${app}/${key}`, { value }, { 'auth-token': token });
+}
+
+/// fails with NotFound if the preference was never set
+export async function deletePreference(
+	app: string,
+	key: string,
+	token: string,
+) {
+	await api.request('DELETE', `${app}/${key}`, null, { 'auth-token': token });
+}
@@ -14,15 +14,102 @@ export class Login {
 	}
 }
 
+/// if remember is true the session lasts longer and does not expire when it
+/// is not used
 export async function login(
 	username: string,
 	password: string,
+	remember: boolean = false,
 ): Promise<Login> {
 	username = username.toLowerCase();
 	const d = await api.request(
 		'POST',
 		'login',
-		{ username, password },
+		{ username, password, remember },
+		{ credentials: 'include' },
+	);
+
+	return new Login(d);
+}
+
+/// finishes a login which returned the error TotpRequired
+///
+/// the code can also be a recovery code
+export async function loginTotp(token: string, code: string): Promise<Login> {
+	const d = await api.request(
+		'POST',
+		'login/totp',
+		{ token, code },
+		{ credentials: 'include' },
+	);
+
+	return new Login(d);
+}
+
+/// returns the name of the single sign-on provider or null if it is not
+/// configured
+export async function oidcInfo(): Promise<string | null> {
+	const d = await api.request('GET', 'oidc', null);
+
+	return d.name ?? null;
+}
+
+/// returns the url of the provider where the browser needs to be sent to
+export async function oidcStart(remember: boolean = false): Promise<string> {
+	const d = await api.request('POST', 'oidc/start', { remember });
+
+	return d.url;
+}
+
+/// finishes a login after the provider redirected back with a code and a
+/// state
+export async function oidcLogin(code: string, state: string): Promise<Login> {
+	const d = await api.request(
+		'POST',
+		'oidc/login',
+		{ code, state },
+		{ credentials: 'include' },
+	);
+
+	return new Login(d);
+}
+
+/// logs in with the user set by an authenticating reverse proxy, fails if
+/// this is not configured
+export async function loginProxy(): Promise<Login> {
+	const d = await api.request(
+		'POST',
+		'login/proxy',
+		{},
+		{ credentials: 'include' },
+	);
+
+	return new Login(d);
+}
+
+/// returns who the invite is for, fails with NotFound if the invite does
+/// not exist or has expired
+export async function inviteInfo(
+	token: string,
+): Promise<{ name: string; expiresOn: Date }> {
+	const d = await api.request('GET', 'invite', { token });
+
+	return { name: d.name, expiresOn: new Date(d.expiresOn) };
+}
+
+/// creates the user, the invite can't be used again
+export async function acceptInvite(
+	token: string,
+	username: string,
+	name: string,
+	password: string,
+	remember: boolean = false,
+): Promise<Login> {
+	username = username.toLowerCase();
+	const d = await api.request(
+		'POST',
+		'invite/accept',
+		{ token, username, name, password, remember },
 		{ credentials: 'include' },
 	);
 
@@ -53,6 +140,18 @@ export async function renew(token: string) {
 	return new Login(d);
 }
 
+/// enter the password again to use endpoints which require a fresh session
+export async function reauth(password: string, token: string) {
+	const d = await api.request(
+		'POST',
+		'reauth',
+		{ password },
+		{ 'auth-token': token },
+	);
+
+	return new Session(d);
+}
+
 export async function logout(token: string) {
 	await api.request(
 		'POST',
@@ -63,18 +162,52 @@ export async function logout(token: string) {
 	);
 }
 
-export async function save(
-	name: string,
-	password: string | null,
-	token: string,
-) {
-	if (!password) password = null;
+export async function save(name: string, token: string) {
 	const d = await api.request(
 		'POST',
 		'save',
-		{ name, password },
+		{ name },
 		{ 'auth-token': token },
 	);
 
 	return new User(d);
 }
+
+/// all other sessions of the user get revoked
+///
+/// fails with LoginIncorrect, PasswordTooShort or PasswordNotAllowed
+export async function changePassword(
+	currentPassword: string,
+	newPassword: string,
+	token: string,
+) {
+	await api.request(
+		'POST',
+		'password',
+		{ currentPassword, newPassword },
+		{ 'auth-token': token },
+	);
+}
+
+/// only root can list all users
+export async function adminUsers(token: string) {
+	const d = await api.request('GET', 'admin/users', null, {
+		'auth-token': token,
+	});
+
+	return d.list.map((u: any) => new User(u));
+}
+
+/// returns a session of the user which only lasts a short time
+///
+/// fails with ReauthRequired if the password was not entered recently
+export async function impersonate(userId: string, token: string) {
+	const d = await api.request(
+		'POST',
+		`admin/users/${userId}/impersonate`,
+		null,
+		{ 'auth-token': token },
+	);
+
+	return new Login(d);
+}
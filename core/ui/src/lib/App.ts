@@ -4,9 +4,11 @@ const addr = import.meta.env.SERVER_ADDR;
 
 export default class App {
 	key: string;
+	description: string | null;
 
 	constructor(key: string) {
 		this.key = key;
+		this.description = null;
 	}
 
 	uri(): string {
@@ -10,6 +10,8 @@ const addr = import.meta.env.SERVER_ADDR;
 export class DynamicApp extends App {
 	jsEntry: string;
 	cssEntry: string;
+	displayName: string | null;
+	iconPath: string | null;
 
 	mod: any;
 	info: { name: string } | null;
@@ -18,6 +20,9 @@ export class DynamicApp extends App {
 		super(a.key);
 		this.jsEntry = a.jsEntry;
 		this.cssEntry = a.cssEntry;
+		this.displayName = a.displayName ?? null;
+		this.iconPath = a.icon ?? null;
+		this.description = a.description ?? null;
 
 		this.mod = null;
 		this.info = null;
@@ -28,8 +33,14 @@ export class DynamicApp extends App {
 		return `${addr}assets/${this.key}/${name}`;
 	}
 
+	icon() {
+		if (!this.iconPath) return super.icon();
+		return `${addr}assets/${this.key}/${this.iconPath}`;
+	}
+
+	// apps built before the manifest existed only provide the name in js
 	name() {
-		return this.info?.name ?? '';
+		return this.displayName ?? this.info?.name ?? '';
 	}
 
 	async prepare(cl: Core) {
@@ -63,9 +74,9 @@ async function importAppDev(key: string) {
 	}
 }
 
-/// only needs to be called once
+/// only needs to be called once, after a session exists
 export async function loadApps(cl: Core) {
-	const list = await appsApi();
+	const list = await appsApi(cl.session.get().token);
 	apps = list.map(a => new DynamicApp(a));
 	apps.push(new Settings());
 	await Promise.all(apps.map(a => a.prepare(cl)));
@@ -0,0 +1,43 @@
+import { Core, user as userMod } from 'core-lib';
+import { impersonate, logout } from '../api/users';
+const { Session } = userMod;
+
+// the session of root while a user is impersonated
+const ROOT_SESSION = 'auth-session-root';
+
+/// switches to a session of the user, root's session is kept to switch back
+/// once the impersonation ends
+export async function startImpersonation(cl: Core, userId: string) {
+	const sess = cl.session.getValid();
+	const login = await impersonate(userId, sess.token);
+
+	localStorage.setItem(ROOT_SESSION, JSON.stringify(sess));
+	cl.session.set(login.session);
+	window.location.href = '/';
+}
+
+export async function stopImpersonation(cl: Core) {
+	try {
+		await logout(cl.session.getValid().token);
+	} catch (e) {
+		console.log('could not logout', e);
+	}
+
+	cl.session.set(takeRootSession());
+	window.location.href = '/';
+}
+
+/// returns the session root had before the impersonation if it is still
+/// valid
+export function takeRootSession(): userMod.Session | null {
+	const itm = localStorage.getItem(ROOT_SESSION);
+	localStorage.removeItem(ROOT_SESSION);
+	if (!itm) return null;
+
+	try {
+		const sess = new Session(JSON.parse(itm));
+		return sess.isValid() ? sess : null;
+	} catch (e) {
+		return null;
+	}
+}
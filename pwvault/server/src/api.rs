@@ -23,7 +23,6 @@ impl Request for AllReq {
 
 	const PATH: &'static str = "/api/pwvault/passwords";
 	const METHOD: Method = Method::GET;
-	const HEADERS: &'static [&'static str] = &["auth-token"];
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -42,7 +41,6 @@ impl Request for EditReq {
 
 	const PATH: &'static str = "/api/pwvault/edit";
 	const METHOD: Method = Method::POST;
-	const HEADERS: &'static [&'static str] = &["auth-token"];
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -55,5 +53,4 @@ impl Request for DeleteReq {
 
 	const PATH: &'static str = "/api/pwvault/{id}/delete";
 	const METHOD: Method = Method::DELETE;
-	const HEADERS: &'static [&'static str] = &["auth-token"];
 }
@@ -3,9 +3,10 @@ use crate::data::Password;
 use crate::error::Result;
 use crate::{db, Config, Passwords};
 
+use crate::CheckedUser;
 use chuchi::extractor::PathParam;
 use chuchi::routes::PathParams;
-use core_lib::users::{CheckedUser, Users};
+use core_lib::users::{Fresh, NoImpersonation, Users};
 
 use chuchi::header::RequestHeader;
 use chuchi::{api, Chuchi};
@@ -13,8 +14,12 @@ use chuchi::{api, Chuchi};
 use chuchi_postgres::time::DateTime;
 use chuchi_postgres::UniqueId;
 
+// api tokens can read the passwords, changing them requires a fresh session
 #[api(AllReq)]
-pub async fn all(sess: CheckedUser, passwords: &Passwords) -> Result<All> {
+pub async fn all(
+	sess: CheckedUser<NoImpersonation>,
+	passwords: &Passwords,
+) -> Result<All> {
 	let list = passwords.all_by_user(&sess.user.id).await?;
 
 	Ok(All { list })
@@ -23,7 +28,7 @@ pub async fn all(sess: CheckedUser, passwords: &Passwords) -> Result<All> {
 #[api(EditReq)]
 pub async fn edit(
 	req: EditReq,
-	sess: CheckedUser,
+	sess: CheckedUser<NoImpersonation<Fresh>>,
 	passwords: &Passwords,
 ) -> Result<Password> {
 	let create_new = req.id.is_none();
@@ -50,7 +55,7 @@ pub async fn edit(
 #[api(DeleteReq)]
 pub async fn delete(
 	id: PathParam<UniqueId>,
-	sess: CheckedUser,
+	sess: CheckedUser<NoImpersonation<Fresh>>,
 	passwords: &Passwords,
 ) -> Result<()> {
 	passwords
@@ -15,7 +15,13 @@ pub enum Error {
 	InvalidAuthToken,
 	MissingDataToken,
 	InvalidDataToken,
+	InvalidApiToken,
 	InvalidUser,
+	MissingRights,
+	/// the password needs to be entered again via the core
+	ReauthRequired,
+	/// the action is not allowed while root impersonates the user
+	Impersonated,
 	Internal(String),
 	Request(String),
 }
@@ -30,6 +36,9 @@ impl error::ApiError for Error {
 				// we should check if the type is Error
 				e.downcast()
 					.map(|e| *e)
+					.or_else(|e| {
+						e.downcast::<users::Error>().map(|e| (*e).into())
+					})
 					.unwrap_or_else(|e| Self::Internal(e.to_string()))
 			}
 			e => Self::Internal(e.to_string()),
@@ -43,7 +52,11 @@ impl error::ApiError for Error {
 			| Self::InvalidAuthToken
 			| Self::MissingDataToken
 			| Self::InvalidDataToken
-			| Self::InvalidUser => StatusCode::FORBIDDEN,
+			| Self::InvalidApiToken
+			| Self::InvalidUser
+			| Self::MissingRights
+			| Self::ReauthRequired
+			| Self::Impersonated => StatusCode::FORBIDDEN,
 			Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
 			Self::Request(_) => StatusCode::BAD_REQUEST,
 		}
@@ -73,7 +86,11 @@ impl From<users::Error> for Error {
 			MissingDataToken => Self::MissingDataToken,
 			InvalidAuthToken => Self::InvalidAuthToken,
 			InvalidDataToken => Self::InvalidDataToken,
+			InvalidApiToken => Self::InvalidApiToken,
 			InvalidUser => Self::InvalidUser,
+			MissingRights => Self::MissingRights,
+			ReauthRequired => Self::ReauthRequired,
+			Impersonated => Self::Impersonated,
 			Db(e) => Self::Internal(e.to_string()),
 		}
 	}
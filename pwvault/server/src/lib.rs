@@ -17,8 +17,8 @@ mod assets {
 use db::Passwords;
 
 use core_lib::config::DbConf;
-use core_lib::users::Users;
-use core_lib::{init_fn, Core};
+use core_lib::users::{self, AppKey, NormalToken, RightsApp, Users};
+use core_lib::{init_fn, Core, Manifest};
 
 use serde::{Deserialize, Serialize};
 
@@ -34,7 +34,29 @@ struct PwVaultConf {
 	favicons_dir: String,
 }
 
-init_fn!(init, "pwvault", assets::JS, assets::CSS);
+/// users need this app in their rights to access any route
+pub(crate) struct PwVault;
+
+impl AppKey for PwVault {
+	const KEY: &'static str = "pwvault";
+}
+
+pub(crate) type CheckedUser<AuthToken = NormalToken> =
+	users::CheckedUser<RightsApp<PwVault>, AuthToken>;
+
+init_fn!(
+	init,
+	PwVault::KEY,
+	assets::JS,
+	assets::CSS,
+	Manifest {
+		version: env!("CARGO_PKG_VERSION"),
+		display_name: "Passwörter",
+		icon: "icon.png",
+		description: "Passwörter sicher speichere",
+		priority: 20,
+	}
+);
 async fn init(core: Core) {
 	tracing_subscriber::fmt()
 		.with_env_filter("pwvault_server=info,chuchi=info,warn")
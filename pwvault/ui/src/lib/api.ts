@@ -1,10 +1,12 @@
 import DateTime from 'chuchi-legacy/time/DateTime';
 import { searchScore } from 'chuchi-utils/search';
 import { Api } from 'chuchi/api';
+import { user as userMod } from 'core-lib';
 
 // @ts-ignore
 const addr: any = window.API_SERVER_ADDR;
 const api = new Api(addr + 'api/pwvault/');
+const usersApi = new Api(addr + 'api/users/');
 
 export function assets(url: string) {
 	return `${addr}assets/pwvault/${url}`;
@@ -73,3 +75,15 @@ export async function edit(obj: EditPassword, token: string) {
 export async function delete_(id: string, token: string) {
 	await api.request('DELETE', id + '/delete', null, { 'auth-token': token });
 }
+
+/// enters the login password again, which is required if the last login was
+/// too long ago
+export async function reauth(password: string, token: string) {
+	const d = await usersApi.request(
+		'POST',
+		'reauth',
+		{ password },
+		{ 'auth-token': token },
+	);
+	return new userMod.Session(d);
+}